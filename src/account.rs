@@ -0,0 +1,1138 @@
+//! Hacker News account, logged in through the login form of the site. The
+//! site has no api for votes, favorites and hidden stories, their links with
+//! auth tokens are scraped from item pages.
+
+use std::{
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use eframe::{
+    egui,
+    epaint::ahash::{HashMap, HashSet},
+};
+use poll_promise::Promise;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    error::{self, Error},
+    scheduler::{Priority, Request, Response, Scheduler},
+    scrape, store,
+    submit::Submission,
+    HnItemId,
+};
+
+/// Logged in user, the site recognizes them by the cookie.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Session {
+    pub username: String,
+    cookie: String, // `user=name&hash`, it logs in as the user so it is never logged
+}
+
+/// What logged in user did with item.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ItemState {
+    pub upvoted: bool,
+    pub favorited: bool,
+    pub hidden: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ItemAction {
+    Upvote,
+    Unvote,
+    Favorite,
+    Unfavorite,
+    Hide,
+    Unhide,
+}
+
+impl ItemAction {
+    fn link(self) -> Link {
+        match self {
+            ItemAction::Upvote | ItemAction::Unvote => Link::Vote,
+            ItemAction::Favorite | ItemAction::Unfavorite => Link::Fave,
+            ItemAction::Hide | ItemAction::Unhide => Link::Hide,
+        }
+    }
+
+    fn url(self, base_url: &Url, id: HnItemId, auth: &str) -> String {
+        let mut url = Url::parse(&join(base_url, self.link().path())).unwrap();
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("id", &id.to_string());
+            match self {
+                ItemAction::Upvote => pairs.append_pair("how", "up"),
+                ItemAction::Unvote => pairs.append_pair("how", "un"),
+                ItemAction::Unfavorite | ItemAction::Unhide => pairs.append_pair("un", "t"),
+                ItemAction::Favorite | ItemAction::Hide => &mut pairs,
+            };
+            pairs.append_pair("auth", auth);
+            pairs.append_pair("goto", "news");
+        }
+        url.to_string()
+    }
+
+    fn apply(self, state: &mut ItemState) {
+        match self {
+            ItemAction::Upvote => state.upvoted = true,
+            ItemAction::Unvote => state.upvoted = false,
+            ItemAction::Favorite => state.favorited = true,
+            ItemAction::Unfavorite => state.favorited = false,
+            ItemAction::Hide => state.hidden = true,
+            ItemAction::Unhide => state.hidden = false,
+        }
+    }
+}
+
+impl Display for ItemAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ItemAction::Upvote => "upvote",
+            ItemAction::Unvote => "unvote",
+            ItemAction::Favorite => "favorite",
+            ItemAction::Unfavorite => "unfavorite",
+            ItemAction::Hide => "hide",
+            ItemAction::Unhide => "unhide",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Page of the site that performs item actions, the auth token of item is
+/// read from the link to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Link {
+    Vote,
+    Fave,
+    Hide,
+}
+
+impl Link {
+    fn path(self) -> &'static str {
+        match self {
+            Link::Vote => "vote",
+            Link::Fave => "fave",
+            Link::Hide => "hide",
+        }
+    }
+
+    fn from_path(path: &str) -> Option<Self> {
+        match path.rsplit('/').next()? {
+            "vote" => Some(Link::Vote),
+            "fave" => Some(Link::Fave),
+            "hide" => Some(Link::Hide),
+            _ => None,
+        }
+    }
+}
+
+/// Item states and auth tokens read from page of the site.
+#[derive(Default)]
+struct Scraped {
+    states: HashMap<HnItemId, ItemState>,
+    tokens: HashMap<(HnItemId, Link), String>,
+}
+
+/// Account on the site, the session is kept in a plain file so they stay
+/// logged in after restart. Only the user can read it on unix, elsewhere it
+/// has the permissions of the data directory.
+pub(crate) struct Account {
+    base_url: Url,
+    scheduler: Scheduler,
+    session_path: Option<PathBuf>,
+    session: Option<Session>,
+    login: Option<Promise<error::Result<Session>>>,
+    pending: Vec<Promise<error::Result<Scraped>>>, // scraped item pages and performed actions
+    scraped_pages: HashSet<HnItemId>,
+    states: HashMap<HnItemId, ItemState>,
+    tokens: HashMap<(HnItemId, Link), String>,
+    error: Option<Error>,                                   // of the last action
+    replies: HashMap<HnItemId, Promise<error::Result<()>>>, // by parent, failed ones keep their error
+    posted: Vec<HnItemId>, // parents of replies posted since the last `take_posted`
+    submission: Option<Promise<error::Result<Option<HnItemId>>>>,
+}
+
+impl Account {
+    pub(crate) fn new(base_url: Url, scheduler: Scheduler, session_path: Option<PathBuf>) -> Self {
+        let base_url = scrape::with_trailing_slash(base_url);
+        scheduler.privacy().add_first_party(base_url.as_str());
+
+        Self {
+            base_url,
+            scheduler,
+            session: session_path.as_deref().and_then(read_session),
+            session_path,
+            login: None,
+            pending: Vec::new(),
+            scraped_pages: Default::default(),
+            states: Default::default(),
+            tokens: Default::default(),
+            error: None,
+            replies: Default::default(),
+            posted: Vec::new(),
+            submission: None,
+        }
+    }
+
+    /// Uses site url from `HACKER_NEWSFEED_SITE_URL` environment variable if
+    /// set and valid, otherwise the official site. The session is kept in the
+    /// data directory.
+    pub(crate) fn from_env(scheduler: Scheduler) -> Self {
+        let session_path = store::data_dir().map(|dir| dir.join("session.json"));
+        Self::new(scrape::site_url_from_env(), scheduler, session_path)
+    }
+
+    pub(crate) fn username(&self) -> Option<&str> {
+        self.session
+            .as_ref()
+            .map(|session| session.username.as_str())
+    }
+
+    pub(crate) fn is_logging_in(&self) -> bool {
+        self.login
+            .as_ref()
+            .map_or(false, |login| login.ready().is_none())
+    }
+
+    pub(crate) fn login_error(&self) -> Option<&Error> {
+        self.login.as_ref()?.ready()?.as_ref().err()
+    }
+
+    /// Error of the last item action, `None` once an action succeeds.
+    pub(crate) fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    pub(crate) fn login(&mut self, ctx: &egui::Context, username: &str, password: &str) {
+        let ctx = ctx.clone();
+        let base_url = self.base_url.clone();
+        let scheduler = self.scheduler.clone();
+        let username = username.trim().to_string();
+        let password = password.to_string();
+
+        self.login = Some(Promise::spawn_thread("login", move || {
+            let result = log_in(&base_url, &scheduler, &username, &password);
+            ctx.request_repaint(); // wake up UI thread, we are logged in
+            result
+        }));
+    }
+
+    /// Forgets the session, the site keeps it valid until it expires.
+    pub(crate) fn logout(&mut self) {
+        self.session = None;
+        self.login = None;
+        self.pending.clear();
+        self.scraped_pages.clear();
+        self.states.clear();
+        self.tokens.clear();
+        self.replies.clear();
+        self.submission = None;
+
+        if let Some(path) = &self.session_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// What logged in user did with item, `None` when logged out or until
+    /// the state is scraped from page of the item or its story.
+    pub(crate) fn state(&self, id: HnItemId) -> Option<ItemState> {
+        self.session
+            .as_ref()
+            .and_then(|_| self.states.get(&id).copied())
+    }
+
+    /// Scrapes item page for states and auth tokens of the item and its
+    /// comments unless it was scraped already.
+    pub(crate) fn load_item_page(&mut self, ctx: &egui::Context, id: HnItemId) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        if !self.scraped_pages.insert(id) {
+            return;
+        }
+
+        let ctx = ctx.clone();
+        let base_url = self.base_url.clone();
+        let scheduler = self.scheduler.clone();
+        let cookie = session.cookie.clone();
+
+        self.pending
+            .push(Promise::spawn_thread("item page", move || {
+                let result = scrape_item_page(&base_url, &scheduler, &cookie, id);
+                ctx.request_repaint(); // wake up UI thread, we know what user can do
+                result
+            }));
+    }
+
+    /// Performs action as logged in user, the item page is scraped first when
+    /// its auth token is not known.
+    pub(crate) fn perform(&mut self, ctx: &egui::Context, id: HnItemId, action: ItemAction) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        let ctx = ctx.clone();
+        let base_url = self.base_url.clone();
+        let scheduler = self.scheduler.clone();
+        let cookie = session.cookie.clone();
+        let token = self.tokens.get(&(id, action.link())).cloned();
+        let state = self.states.get(&id).copied().unwrap_or_default();
+
+        self.pending
+            .push(Promise::spawn_thread("item action", move || {
+                let result = perform(&base_url, &scheduler, &cookie, token, id, action, state);
+                ctx.request_repaint(); // wake up UI thread, item state changed
+                result
+            }));
+    }
+
+    /// Posts reply to story or comment, the site formats the text the way
+    /// `comment_parser::format` previews it.
+    pub(crate) fn reply(&mut self, ctx: &egui::Context, parent: HnItemId, text: &str) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        if self.is_replying(parent) {
+            return;
+        }
+
+        let ctx = ctx.clone();
+        let base_url = self.base_url.clone();
+        let scheduler = self.scheduler.clone();
+        let cookie = session.cookie.clone();
+        let text = text.to_string();
+
+        self.replies.insert(
+            parent,
+            Promise::spawn_thread("reply", move || {
+                let result = post_reply(&base_url, &scheduler, &cookie, parent, &text);
+                ctx.request_repaint(); // wake up UI thread, the reply is posted
+                result
+            }),
+        );
+    }
+
+    pub(crate) fn is_replying(&self, parent: HnItemId) -> bool {
+        self.replies
+            .get(&parent)
+            .map_or(false, |reply| reply.ready().is_none())
+    }
+
+    /// Why the last reply to parent was not posted.
+    pub(crate) fn reply_error(&self, parent: HnItemId) -> Option<&Error> {
+        self.replies.get(&parent)?.ready()?.as_ref().err()
+    }
+
+    /// Parents of replies posted since the last call, their drafts are no
+    /// longer needed.
+    pub(crate) fn take_posted(&mut self) -> Vec<HnItemId> {
+        std::mem::take(&mut self.posted)
+    }
+
+    /// Posts story as logged in user, it should be free of `problems`.
+    pub(crate) fn submit(&mut self, ctx: &egui::Context, submission: &Submission) {
+        let Some(session) = &self.session else {
+            return;
+        };
+
+        if self.is_submitting() {
+            return;
+        }
+
+        let ctx = ctx.clone();
+        let base_url = self.base_url.clone();
+        let scheduler = self.scheduler.clone();
+        let cookie = session.cookie.clone();
+        let submission = submission.clone();
+
+        self.submission = Some(Promise::spawn_thread("submit", move || {
+            let result = post_submission(&base_url, &scheduler, &cookie, &submission);
+            ctx.request_repaint(); // wake up UI thread, the story is submitted
+            result
+        }));
+    }
+
+    pub(crate) fn is_submitting(&self) -> bool {
+        self.submission
+            .as_ref()
+            .map_or(false, |submission| submission.ready().is_none())
+    }
+
+    /// Why the last story was not submitted.
+    pub(crate) fn submit_error(&self) -> Option<&Error> {
+        self.submission.as_ref()?.ready()?.as_ref().err()
+    }
+
+    /// Result of story submitted since the last call, the story it was
+    /// counted as upvote for when its url was submitted before.
+    pub(crate) fn take_submitted(&mut self) -> Option<Option<HnItemId>> {
+        match self.submission.as_ref()?.ready()? {
+            Ok(story) => {
+                let story = *story;
+                self.submission = None;
+                Some(story)
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub(crate) fn poll(&mut self) {
+        if let Some(Ok(_)) = self.login.as_ref().and_then(|login| login.ready()) {
+            if let Some(Ok(session)) = self.login.take().map(|login| login.block_and_take()) {
+                if let Some(path) = &self.session_path {
+                    if let Err(error) = write_session(path, &session) {
+                        warn!("Cannot write {}: {}", path.display(), error);
+                    }
+                }
+                self.session = Some(session);
+            }
+        }
+
+        let (done, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|promise| promise.ready().is_some());
+        self.pending = pending;
+
+        for promise in done {
+            match promise.block_and_take() {
+                Ok(scraped) => {
+                    self.states.extend(scraped.states);
+                    self.tokens.extend(scraped.tokens);
+                    self.error = None;
+                }
+                Err(Error::LoggedOut) => {
+                    self.logout();
+                    self.error = Some(Error::LoggedOut);
+                }
+                Err(error) => {
+                    warn!("Item action failed: {}", error);
+                    self.error = Some(error);
+                }
+            }
+        }
+
+        let posted: Vec<_> = self
+            .replies
+            .iter()
+            .filter(|(_, reply)| matches!(reply.ready(), Some(Ok(()))))
+            .map(|(parent, _)| *parent)
+            .collect();
+        for parent in posted {
+            self.replies.remove(&parent);
+            self.posted.push(parent);
+        }
+
+        let logged_out = self
+            .replies
+            .values()
+            .any(|reply| matches!(reply.ready(), Some(Err(Error::LoggedOut))))
+            || matches!(self.submit_error(), Some(Error::LoggedOut));
+        if logged_out {
+            self.logout();
+            self.error = Some(Error::LoggedOut);
+        }
+    }
+}
+
+fn join(base_url: &Url, path: &str) -> String {
+    base_url
+        .join(path)
+        .map(String::from)
+        .unwrap_or_else(|_| format!("{}{}", base_url, path))
+}
+
+/// Sends request with session cookie and waits for its response, redirects
+/// are not followed, only for background threads.
+fn send(
+    scheduler: &Scheduler,
+    mut request: Request,
+    cookie: Option<&str>,
+) -> error::Result<Response> {
+    if let Some(cookie) = cookie {
+        request
+            .headers
+            .insert("Cookie".to_string(), cookie.to_string());
+    }
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    scheduler.fetch_without_redirects(request, Priority::Account, move |response| {
+        let _ = sender.send(response);
+    });
+
+    receiver.recv().unwrap_or(Err(Error::Cancelled))
+}
+
+/// Fills the login form of the site, the site responds with session cookie
+/// when the password is right.
+fn log_in(
+    base_url: &Url,
+    scheduler: &Scheduler,
+    username: &str,
+    password: &str,
+) -> error::Result<Session> {
+    let page = send(scheduler, Request::get(join(base_url, "login")), None)?;
+    // the page also has form for creating account which differs by hidden `creating` field
+    let form = parse_forms(page.text().unwrap_or_default())
+        .into_iter()
+        .find(|form| form.field("password").is_some() && form.hidden("creating").is_none())
+        .ok_or_else(|| Error::Decode("Login form not found".to_string()))?;
+
+    let body = form.fill(&[("text", username), ("password", password)]);
+    let response = submit(scheduler, &page.url, &form, body, None)?;
+    match response
+        .headers
+        .get("set-cookie")
+        .and_then(|header| session_cookie(header))
+    {
+        Some(cookie) => Ok(Session {
+            username: username.to_string(),
+            cookie,
+        }),
+        None => Err(failure(&response, "Login failed")),
+    }
+}
+
+/// Posts filled form to its action url, relative to the page it is on.
+fn submit(
+    scheduler: &Scheduler,
+    page_url: &str,
+    form: &Form,
+    body: String,
+    cookie: Option<&str>,
+) -> error::Result<Response> {
+    let action = Url::parse(page_url)
+        .and_then(|url| url.join(&form.action))
+        .map_err(|error| Error::Decode(error.to_string()))?;
+
+    let mut request = Request::post(action, body.into_bytes());
+    request.headers.insert(
+        "Content-Type".to_string(),
+        "application/x-www-form-urlencoded".to_string(),
+    );
+
+    send(scheduler, request, cookie)
+}
+
+/// The site shows what went wrong in plain text before any markup, e.g.
+/// "Bad login." or "You're posting too fast."
+fn failure(response: &Response, default: &str) -> Error {
+    let message = response
+        .text()
+        .and_then(|html| html.split('<').next())
+        .map(str::trim)
+        .filter(|message| !message.is_empty())
+        .unwrap_or(default);
+
+    Error::InvalidRequest(message.to_string())
+}
+
+/// Redirect to the login page, the site sends users it does not recognize
+/// there.
+fn is_logged_out(response: &Response) -> bool {
+    response
+        .headers
+        .get("location")
+        .map_or(false, |location| location.starts_with("login"))
+}
+
+fn scrape_item_page(
+    base_url: &Url,
+    scheduler: &Scheduler,
+    cookie: &str,
+    id: HnItemId,
+) -> error::Result<Scraped> {
+    let url = join(base_url, &format!("item?id={}", id));
+    let page = send(scheduler, Request::get(url), Some(cookie))?;
+    let html = page
+        .text()
+        .ok_or_else(|| Error::Decode("Item page is not text".to_string()))?;
+
+    parse_item_page(&page.url, html)
+}
+
+fn perform(
+    base_url: &Url,
+    scheduler: &Scheduler,
+    cookie: &str,
+    token: Option<String>,
+    id: HnItemId,
+    action: ItemAction,
+    state: ItemState,
+) -> error::Result<Scraped> {
+    let mut scraped = Scraped::default();
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            scraped = scrape_item_page(base_url, scheduler, cookie, id)?;
+            scraped
+                .tokens
+                .get(&(id, action.link()))
+                .cloned()
+                .ok_or_else(|| Error::InvalidRequest(format!("Cannot {} item {}", action, id)))?
+        }
+    };
+
+    let request = Request::get(action.url(base_url, id, &token));
+    let response = send(scheduler, request, Some(cookie))?;
+
+    if is_logged_out(&response) {
+        return Err(Error::LoggedOut);
+    }
+
+    let mut state = scraped.states.get(&id).copied().unwrap_or(state);
+    action.apply(&mut state);
+    scraped.states.insert(id, state);
+
+    Ok(scraped)
+}
+
+/// Fills the comment form on the page of parent item, the site formats the
+/// text itself.
+fn post_reply(
+    base_url: &Url,
+    scheduler: &Scheduler,
+    cookie: &str,
+    parent: HnItemId,
+    text: &str,
+) -> error::Result<()> {
+    let url = join(base_url, &format!("item?id={}", parent));
+    let page = send(scheduler, Request::get(url), Some(cookie))?;
+    if is_logged_out(&page) {
+        return Err(Error::LoggedOut);
+    }
+
+    // pages of comments have form for replying to them too
+    let parent_id = parent.to_string();
+    let form = parse_forms(page.text().unwrap_or_default())
+        .into_iter()
+        .find(|form| form.field("textarea").is_some() && form.hidden("parent") == Some(&parent_id))
+        .ok_or_else(|| Error::InvalidRequest(format!("Cannot reply to item {}", parent)))?;
+
+    let body = form.fill(&[("textarea", text)]);
+    let response = submit(scheduler, &page.url, &form, body, Some(cookie))?;
+
+    // posted comment redirects back to the thread
+    if is_logged_out(&response) {
+        Err(Error::LoggedOut)
+    } else if response.headers.contains_key("location") {
+        Ok(())
+    } else {
+        Err(failure(&response, "Comment was not posted"))
+    }
+}
+
+/// Fills the submit form of the site. The site redirects to the story when
+/// its url was submitted recently and counts the submission as upvote.
+fn post_submission(
+    base_url: &Url,
+    scheduler: &Scheduler,
+    cookie: &str,
+    submission: &Submission,
+) -> error::Result<Option<HnItemId>> {
+    let page = send(
+        scheduler,
+        Request::get(join(base_url, "submit")),
+        Some(cookie),
+    )?;
+    if is_logged_out(&page) {
+        return Err(Error::LoggedOut);
+    }
+
+    let form = parse_forms(page.text().unwrap_or_default())
+        .into_iter()
+        .find(|form| form.field("title").is_some() && form.field("url").is_some())
+        .ok_or_else(|| Error::Decode("Submit form not found".to_string()))?;
+
+    let title = submission.title();
+    let url = submission.url().map(String::from).unwrap_or_default();
+    let body = form.fill(&[
+        ("title", &title),
+        ("url", &url),
+        ("text", submission.text.trim()),
+    ]);
+    let response = submit(scheduler, &page.url, &form, body, Some(cookie))?;
+
+    if is_logged_out(&response) {
+        return Err(Error::LoggedOut);
+    }
+
+    match response.headers.get("location") {
+        Some(location) => Ok(Url::parse(&join(base_url, location))
+            .ok()
+            .filter(|url| url.path().ends_with("/item"))
+            .and_then(|url| {
+                url.query_pairs()
+                    .find(|(key, _)| key == "id")
+                    .and_then(|(_, id)| id.parse().ok())
+            })
+            .map(HnItemId)),
+        None => Err(failure(&response, "Story was not submitted")),
+    }
+}
+
+/// Form on page of the site, its hidden fields carry tokens the site checks
+/// when the form is submitted.
+struct Form {
+    action: String,
+    hidden: Vec<(String, String)>,
+    fields: Vec<(String, String)>, // (type, name) of text, password and textarea fields
+}
+
+impl Form {
+    fn hidden(&self, name: &str) -> Option<&str> {
+        self.hidden
+            .iter()
+            .find(|(hidden, _)| hidden == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Name of the field named `key`, or else of the first field of `key`
+    /// type.
+    fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(_, name)| name == key)
+            .or_else(|| self.fields.iter().find(|(r#type, _)| r#type == key))
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Url encoded body with hidden fields and the fields found by `field`
+    /// set to their values.
+    fn fill(&self, values: &[(&str, &str)]) -> String {
+        let mut body = url::form_urlencoded::Serializer::new(String::new());
+        body.extend_pairs(&self.hidden);
+
+        for (key, value) in values {
+            if let Some(name) = self.field(key) {
+                body.append_pair(name, value);
+            }
+        }
+
+        body.finish()
+    }
+}
+
+fn parse_forms(html: &str) -> Vec<Form> {
+    let html = Html::parse_document(html);
+    let forms = Selector::parse("form[action]").unwrap();
+    let inputs = Selector::parse("input[name], textarea[name]").unwrap();
+
+    html.select(&forms)
+        .map(|form| {
+            let mut hidden = Vec::new();
+            let mut fields = Vec::new();
+
+            for input in form.select(&inputs) {
+                let name = input.value().attr("name").unwrap_or_default().to_string();
+                let r#type = match input.value().name() {
+                    "textarea" => "textarea",
+                    _ => input.value().attr("type").unwrap_or("text"),
+                };
+                match r#type {
+                    "hidden" => {
+                        let value = input.value().attr("value").unwrap_or_default();
+                        hidden.push((name, value.to_string()));
+                    }
+                    "text" | "password" | "textarea" => fields.push((r#type.to_string(), name)),
+                    _ => {}
+                }
+            }
+
+            Form {
+                action: form.value().attr("action").unwrap_or_default().to_string(),
+                hidden,
+                fields,
+            }
+        })
+        .collect()
+}
+
+/// The `user` cookie from `Set-Cookie` header, without its attributes.
+fn session_cookie(header: &str) -> Option<String> {
+    let cookie = header.split(';').next()?.trim();
+    let (name, value) = cookie.split_once('=')?;
+    (name == "user" && !value.is_empty()).then(|| cookie.to_string())
+}
+
+/// Reads vote, favorite and hide links of items on the page, links to undo
+/// them mean user did them already.
+fn parse_item_page(page_url: &str, html: &str) -> error::Result<Scraped> {
+    let html = Html::parse_document(html);
+
+    // link to profile of logged in user is in the header of every page
+    let me = Selector::parse("a#me").unwrap();
+    if html.select(&me).next().is_none() {
+        return Err(Error::LoggedOut);
+    }
+
+    let base_url = Url::parse(page_url).map_err(|error| Error::Decode(error.to_string()))?;
+    let links = Selector::parse("a[href]").unwrap();
+    let mut scraped = Scraped::default();
+
+    for href in html.select(&links).filter_map(|a| a.value().attr("href")) {
+        let Ok(url) = base_url.join(href) else {
+            continue;
+        };
+        let Some(link) = Link::from_path(url.path()) else {
+            continue;
+        };
+
+        let query: HashMap<_, _> = url.query_pairs().collect();
+        let id = query.get("id").and_then(|id| id.parse().ok()).map(HnItemId);
+        let (Some(id), Some(auth)) = (id, query.get("auth")) else {
+            continue;
+        };
+
+        let undo = query.get("how").map_or(false, |how| how == "un")
+            || query.get("un").map_or(false, |un| un == "t");
+        let state = scraped.states.entry(id).or_default();
+        if undo {
+            match link {
+                Link::Vote => state.upvoted = true,
+                Link::Fave => state.favorited = true,
+                Link::Hide => state.hidden = true,
+            }
+        }
+
+        scraped.tokens.insert((id, link), auth.to_string());
+    }
+
+    Ok(scraped)
+}
+
+fn read_session(path: &Path) -> Option<Session> {
+    serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+}
+
+/// Writes session to file only the user can read on unix, the file is
+/// created anew so older file with other permissions is not reused. Other
+/// platforms have no such mode, the file inherits access of its directory.
+fn write_session(path: &Path, session: &Session) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _ = std::fs::remove_file(path);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(&serde_json::to_vec(session)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        submit::SubmissionKind,
+        test_server::{Response, TestServer},
+    };
+
+    const LOGIN_PAGE: &str = r#"<html><body>
+        <b>Login</b><br><br>
+        <form action="login" method="post"><input type="hidden" name="goto" value="news">
+        <table border="0"><tr><td>username:</td><td><input type="text" name="acct" size="20"></td></tr>
+        <tr><td>password:</td><td><input type="password" name="pw" size="20"></td></tr></table><br>
+        <input type="submit" value="login"></form>
+        <b>Create Account</b><br><br>
+        <form action="login" method="post"><input type="hidden" name="goto" value="news">
+        <input type="hidden" name="creating" value="t">
+        <table border="0"><tr><td>username:</td><td><input type="text" name="acct" size="20"></td></tr>
+        <tr><td>password:</td><td><input type="password" name="pw" size="20"></td></tr></table><br>
+        <input type="submit" value="create account"></form>
+        </body></html>"#;
+
+    const ITEM_PAGE: &str = r#"<html><body>
+        <span class="pagetop"><a id="me" href="user?id=pg">pg</a> (155111) | <a id="logout" href="logout?auth=x&amp;goto=item%3Fid%3D1">logout</a></span>
+        <tr class="athing" id="1"><td class="votelinks"><a id="up_1" href="vote?id=1&amp;how=up&amp;auth=a1&amp;goto=item%3Fid%3D1"><div class="votearrow"></div></a></td>
+        <td class="subtext"><a href="hide?id=1&amp;goto=item%3Fid%3D1&amp;auth=a1">hide</a> | <a href="fave?id=1&amp;auth=a1">favorite</a></td></tr>
+        <tr class="athing comtr" id="2"><td class="votelinks"><a id="up_2" class="nosee" href="vote?id=2&amp;how=up&amp;auth=a2&amp;goto=item%3Fid%3D1"><div class="votearrow"></div></a></td>
+        <td><a id="un_2" href="vote?id=2&amp;how=un&amp;auth=a2&amp;goto=item%3Fid%3D1">unvote</a></td></tr>
+        <form action="comment" method="post"><input type="hidden" name="parent" value="1"><input type="hidden" name="goto" value="item?id=1">
+        <input type="hidden" name="hmac" value="h1"><textarea name="text" rows="8" cols="80" wrap="virtual"></textarea><br><br>
+        <input type="submit" value="add comment"></form>
+        </body></html>"#;
+
+    const COMMENT_PAGE: &str = r#"<html><body>
+        <a id="me" href="user?id=pg">pg</a>
+        <a href="fave?id=3&amp;un=t&amp;auth=a3">un-favorite</a>
+        </body></html>"#;
+
+    const SUBMIT_PAGE: &str = r#"<html><body>
+        <a id="me" href="user?id=pg">pg</a>
+        <form method="post" action="/r"><input type="hidden" name="fnop" value="submit-page"><input type="hidden" name="fnid" value="f1">
+        <table><tr><td>title</td><td><input type="text" name="title" value="" size="50" maxlength="80"></td></tr>
+        <tr><td>url</td><td><input type="text" name="url" value="" size="50"></td></tr>
+        <tr><td>text</td><td><textarea name="text" rows="4" cols="49"></textarea></td></tr>
+        <tr><td></td><td><input type="submit" value="submit"></td></tr></table></form>
+        </body></html>"#;
+
+    fn wait(account: &mut Account) {
+        if let Some(login) = &account.login {
+            login.block_until_ready();
+        }
+        for promise in &account.pending {
+            promise.block_until_ready();
+        }
+        for reply in account.replies.values() {
+            reply.block_until_ready();
+        }
+        if let Some(submission) = &account.submission {
+            submission.block_until_ready();
+        }
+        account.poll();
+    }
+
+    #[test]
+    fn parses_item_page_links() {
+        let scraped = parse_item_page("https://news.ycombinator.com/item?id=1", ITEM_PAGE).unwrap();
+
+        assert_eq!(scraped.states[&HnItemId(1)], ItemState::default());
+        assert!(scraped.states[&HnItemId(2)].upvoted);
+        assert_eq!(scraped.tokens[&(HnItemId(1), Link::Vote)], "a1");
+        assert_eq!(scraped.tokens[&(HnItemId(1), Link::Hide)], "a1");
+        assert_eq!(scraped.tokens[&(HnItemId(2), Link::Vote)], "a2");
+        assert!(!scraped.tokens.contains_key(&(HnItemId(2), Link::Fave)));
+
+        let logged_out = parse_item_page("https://news.ycombinator.com/item?id=1", LOGIN_PAGE);
+        assert!(matches!(logged_out, Err(Error::LoggedOut)));
+    }
+
+    #[test]
+    fn logs_in_and_performs_actions() {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let actions = actions.clone();
+            TestServer::start(move |request| {
+                let cookie = request.header("cookie").unwrap_or_default();
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/login") => Response::html(LOGIN_PAGE),
+                    ("POST", "/login") => {
+                        if request.body == b"goto=news&acct=pg&pw=secret" {
+                            Response::new(302, "text/html", "")
+                                .with_header("Location", "news")
+                                .with_header("Set-Cookie", "user=pg&hash; Secure; HttpOnly")
+                        } else {
+                            Response::html(format!("Bad login.\n<br><br>{}", LOGIN_PAGE))
+                        }
+                    }
+                    (_, _) if cookie != "user=pg&hash" => Response::new(302, "text/html", "")
+                        .with_header("Location", "login?goto=news"),
+                    ("GET", "/item") if request.query == "id=1" => Response::html(ITEM_PAGE),
+                    ("GET", "/item") if request.query == "id=3" => Response::html(COMMENT_PAGE),
+                    ("GET", "/vote" | "/fave") => {
+                        actions
+                            .lock()
+                            .unwrap()
+                            .push(format!("{}?{}", request.path, request.query));
+                        Response::new(302, "text/html", "").with_header("Location", "news")
+                    }
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let path = store::temp_dir("account").join("session.json");
+        let base_url: Url = server.url("/").parse().unwrap();
+        let ctx = egui::Context::default();
+        let mut account = Account::new(base_url.clone(), Scheduler::new(1), Some(path.clone()));
+
+        account.login(&ctx, "pg", "wrong");
+        wait(&mut account);
+        assert_eq!(
+            account.login_error(),
+            Some(&Error::InvalidRequest("Bad login.".to_string()))
+        );
+        assert_eq!(account.state(HnItemId(1)), None);
+
+        account.login(&ctx, "pg", "secret");
+        wait(&mut account);
+        assert_eq!(account.username(), Some("pg"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // session survives restart
+        let mut account = Account::new(base_url.clone(), Scheduler::new(1), Some(path.clone()));
+        assert_eq!(account.username(), Some("pg"));
+        // links are not offered until the state is known
+        assert_eq!(account.state(HnItemId(1)), None);
+
+        account.load_item_page(&ctx, HnItemId(1));
+        wait(&mut account);
+        assert!(account.state(HnItemId(2)).unwrap().upvoted);
+
+        account.perform(&ctx, HnItemId(1), ItemAction::Upvote);
+        wait(&mut account);
+        assert!(account.state(HnItemId(1)).unwrap().upvoted);
+
+        // token of comment is scraped from its own page first
+        account.perform(&ctx, HnItemId(3), ItemAction::Unfavorite);
+        wait(&mut account);
+        assert!(!account.state(HnItemId(3)).unwrap().favorited);
+        assert_eq!(account.error(), None);
+
+        assert_eq!(
+            *actions.lock().unwrap(),
+            [
+                "/vote?id=1&how=up&auth=a1&goto=news",
+                "/fave?id=3&un=t&auth=a3&goto=news",
+            ]
+        );
+
+        // expired session logs user out
+        account.session.as_mut().unwrap().cookie = "user=pg&expired".to_string();
+        account.perform(&ctx, HnItemId(1), ItemAction::Unvote);
+        wait(&mut account);
+        assert_eq!(account.error(), Some(&Error::LoggedOut));
+        assert_eq!(account.username(), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn posts_replies() {
+        let comments = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let comments = comments.clone();
+            TestServer::start(move |request| {
+                if request.header("cookie") != Some("user=pg&hash") {
+                    return Response::new(302, "text/html", "")
+                        .with_header("Location", "login?goto=news");
+                }
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/item") if request.query == "id=1" => Response::html(ITEM_PAGE),
+                    ("GET", "/item") if request.query == "id=3" => Response::html(COMMENT_PAGE),
+                    ("POST", "/comment") => {
+                        let body = String::from_utf8_lossy(&request.body).to_string();
+                        if body.ends_with("slow") {
+                            return Response::html(
+                                "You're posting too fast. Please slow down.<br>",
+                            );
+                        }
+                        comments.lock().unwrap().push(body);
+                        Response::new(302, "text/html", "").with_header("Location", "item?id=1")
+                    }
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let ctx = egui::Context::default();
+        let mut account = Account::new(server.url("/").parse().unwrap(), Scheduler::new(1), None);
+        account.session = Some(Session {
+            username: "pg".to_string(),
+            cookie: "user=pg&hash".to_string(),
+        });
+
+        account.reply(&ctx, HnItemId(1), "Hello *world*");
+        assert!(account.is_replying(HnItemId(1)));
+        wait(&mut account);
+        assert_eq!(account.take_posted(), [HnItemId(1)]);
+        assert_eq!(
+            *comments.lock().unwrap(),
+            ["parent=1&goto=item%3Fid%3D1&hmac=h1&text=Hello+*world*"]
+        );
+
+        account.reply(&ctx, HnItemId(1), "slow");
+        wait(&mut account);
+        assert!(account.take_posted().is_empty());
+        assert_eq!(
+            account.reply_error(HnItemId(1)),
+            Some(&Error::InvalidRequest(
+                "You're posting too fast. Please slow down.".to_string()
+            ))
+        );
+
+        // page without comment form, e.g. of thread that is too old
+        account.reply(&ctx, HnItemId(3), "late");
+        wait(&mut account);
+        assert_eq!(
+            account.reply_error(HnItemId(3)),
+            Some(&Error::InvalidRequest("Cannot reply to item 3".to_string()))
+        );
+
+        account.session.as_mut().unwrap().cookie = "user=pg&expired".to_string();
+        account.reply(&ctx, HnItemId(1), "again");
+        wait(&mut account);
+        assert_eq!(account.error(), Some(&Error::LoggedOut));
+        assert_eq!(account.username(), None);
+    }
+
+    #[test]
+    fn submits_stories() {
+        let submitted = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let submitted = submitted.clone();
+            TestServer::start(move |request| {
+                match (request.method.as_str(), request.path.as_str()) {
+                    ("GET", "/submit") => Response::html(SUBMIT_PAGE),
+                    ("POST", "/r") => {
+                        let body = String::from_utf8_lossy(&request.body).to_string();
+                        submitted.lock().unwrap().push(body.clone());
+                        if body.contains("url=&") {
+                            Response::new(302, "text/html", "").with_header("Location", "newest")
+                        } else {
+                            // the url was submitted recently
+                            Response::new(302, "text/html", "")
+                                .with_header("Location", "item?id=8863")
+                        }
+                    }
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let ctx = egui::Context::default();
+        let mut account = Account::new(server.url("/").parse().unwrap(), Scheduler::new(1), None);
+        account.session = Some(Session {
+            username: "pg".to_string(),
+            cookie: "user=pg&hash".to_string(),
+        });
+
+        let mut submission = Submission {
+            kind: SubmissionKind::Ask,
+            title: "Why is the sky blue?".to_string(),
+            url: "https://ignored.example.com/".to_string(),
+            text: "Asking for a friend.".to_string(),
+        };
+        account.submit(&ctx, &submission);
+        assert!(account.is_submitting());
+        wait(&mut account);
+        assert_eq!(account.take_submitted(), Some(None));
+
+        submission.kind = SubmissionKind::Link;
+        submission.title = "My YC app: Dropbox".to_string();
+        submission.url = "http://www.getdropbox.com/u/2/screencast.html".to_string();
+        submission.text.clear();
+        account.submit(&ctx, &submission);
+        wait(&mut account);
+        assert_eq!(account.take_submitted(), Some(Some(HnItemId(8863))));
+        assert_eq!(account.take_submitted(), None);
+
+        assert_eq!(
+            *submitted.lock().unwrap(),
+            [
+                "fnop=submit-page&fnid=f1&title=Ask+HN%3A+Why+is+the+sky+blue%3F&url=&text=Asking+for+a+friend.",
+                "fnop=submit-page&fnid=f1&title=My+YC+app%3A+Dropbox&url=http%3A%2F%2Fwww.getdropbox.com%2Fu%2F2%2Fscreencast.html&text=",
+            ]
+        );
+    }
+}
@@ -0,0 +1,498 @@
+use eframe::egui;
+use poll_promise::Promise;
+use serde::Deserialize;
+use time::{Date, Month, OffsetDateTime};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    error::{self, Error},
+    fetch,
+    scheduler::{Priority, Scheduler},
+    HnItem, HnItemId,
+};
+
+/// Full-text search over stories and comments https://hn.algolia.com/api
+pub(crate) struct Algolia {
+    base_url: Url,
+    scheduler: Scheduler,
+}
+
+impl Algolia {
+    pub(crate) const DEFAULT_BASE_URL: &'static str = "https://hn.algolia.com/api/v1/";
+
+    pub(crate) fn new(mut base_url: Url, scheduler: Scheduler) -> Self {
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        scheduler.privacy().add_first_party(base_url.as_str());
+
+        Self {
+            base_url,
+            scheduler,
+        }
+    }
+
+    /// Uses base url from `HACKER_NEWSFEED_SEARCH_URL` environment variable if
+    /// set and valid, otherwise the official api.
+    pub(crate) fn from_env(scheduler: Scheduler) -> Self {
+        if let Ok(base_url) = std::env::var("HACKER_NEWSFEED_SEARCH_URL") {
+            match Url::parse(&base_url) {
+                Ok(base_url) => return Self::new(base_url, scheduler),
+                Err(error) => warn!("Invalid search url {}: {}", base_url, error),
+            }
+        }
+
+        Self::new(Url::parse(Self::DEFAULT_BASE_URL).unwrap(), scheduler)
+    }
+
+    pub(crate) fn search(
+        &self,
+        ctx: egui::Context,
+        query: &SearchQuery,
+        page: usize,
+    ) -> Promise<error::Result<SearchResults>> {
+        match query.url(&self.base_url, page) {
+            Ok(url) => {
+                fetch::url_and_deserialize(ctx, &self.scheduler, Priority::Visible, url.as_str())
+            }
+            Err(error) => Promise::from_ready(Err(Error::InvalidRequest(error))),
+        }
+    }
+
+    /// Stories submitted with the url before, the newest first. The search
+    /// also matches similar urls, hits are filtered by the caller.
+    pub(crate) fn submissions(
+        &self,
+        ctx: egui::Context,
+        url: &Url,
+    ) -> Promise<error::Result<SearchResults>> {
+        match submissions_url(&self.base_url, url) {
+            Ok(url) => {
+                fetch::url_and_deserialize(ctx, &self.scheduler, Priority::Visible, url.as_str())
+            }
+            Err(error) => Promise::from_ready(Err(Error::InvalidRequest(error))),
+        }
+    }
+
+    /// Item with all its descendants in one request, see `ThreadItem::items`.
+    pub(crate) fn thread(
+        &self,
+        ctx: egui::Context,
+        id: HnItemId,
+    ) -> Promise<error::Result<ThreadItem>> {
+        match self.base_url.join(&format!("items/{}", id)) {
+            Ok(url) => {
+                fetch::url_and_deserialize(ctx, &self.scheduler, Priority::Thread, url.as_str())
+            }
+            Err(error) => Promise::from_ready(Err(Error::InvalidRequest(error.to_string()))),
+        }
+    }
+}
+
+fn submissions_url(base_url: &Url, url: &Url) -> Result<Url, String> {
+    let mut search_url = base_url
+        .join("search_by_date")
+        .map_err(|err| err.to_string())?;
+
+    // the scheme is left out so stories submitted with the other one match too
+    let query = format!(
+        "{}{}",
+        url.host_str().unwrap_or_default(),
+        url.path().trim_end_matches('/')
+    );
+
+    search_url
+        .query_pairs_mut()
+        .append_pair("query", &query)
+        .append_pair("restrictSearchableAttributes", "url")
+        .append_pair("tags", "story")
+        .append_pair("hitsPerPage", "50");
+
+    Ok(search_url)
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SearchSort {
+    #[default]
+    Relevance,
+    Date,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SearchTags {
+    #[default]
+    All,
+    Stories,
+    Comments,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub(crate) struct SearchQuery {
+    pub text: String,
+    pub sort: SearchSort,
+    pub tags: SearchTags,
+    pub author: String,
+    pub min_points: usize,
+    pub from: String, // inclusive date in YYYY-MM-DD format
+    pub to: String,   // inclusive date in YYYY-MM-DD format
+}
+
+impl SearchQuery {
+    fn url(&self, base_url: &Url, page: usize) -> Result<Url, String> {
+        let endpoint = match self.sort {
+            SearchSort::Relevance => "search",
+            SearchSort::Date => "search_by_date",
+        };
+
+        let mut url = base_url.join(endpoint).map_err(|err| err.to_string())?;
+
+        let mut tags = Vec::new();
+        match self.tags {
+            SearchTags::All => tags.push("(story,comment,poll,pollopt)".to_string()),
+            SearchTags::Stories => tags.push("story".to_string()),
+            SearchTags::Comments => tags.push("comment".to_string()),
+        }
+
+        let author = self.author.trim();
+        if !author.is_empty() {
+            tags.push(format!("author_{}", author));
+        }
+
+        let mut numeric_filters = Vec::new();
+        if self.min_points > 0 {
+            numeric_filters.push(format!("points>={}", self.min_points));
+        }
+
+        if let Some(from) = parse_date_filter(&self.from, "from")? {
+            numeric_filters.push(format!("created_at_i>={}", from.unix_timestamp()));
+        }
+
+        if let Some(to) = parse_date_filter(&self.to, "to")? {
+            // include the whole day
+            let to = to + time::Duration::DAY;
+            numeric_filters.push(format!("created_at_i<{}", to.unix_timestamp()));
+        }
+
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.append_pair("query", self.text.trim());
+            pairs.append_pair("tags", &tags.join(","));
+            if !numeric_filters.is_empty() {
+                pairs.append_pair("numericFilters", &numeric_filters.join(","));
+            }
+            pairs.append_pair("page", &page.to_string());
+        }
+
+        Ok(url)
+    }
+}
+
+fn parse_date_filter(text: &str, name: &str) -> Result<Option<OffsetDateTime>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    parse_date(text)
+        .map(|date| Some(date.midnight().assume_utc()))
+        .ok_or_else(|| format!("Invalid {} date '{}', expected YYYY-MM-DD", name, text))
+}
+
+/// Parses date in `YYYY-MM-DD` format.
+pub(crate) fn parse_date(text: &str) -> Option<Date> {
+    let mut parts = text.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+
+    Date::from_calendar_date(year, Month::try_from(month).ok()?, day).ok()
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub(crate) struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    pub nb_hits: usize,
+    pub page: usize,
+    pub nb_pages: usize,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub(crate) struct SearchHit {
+    #[serde(rename = "objectID")]
+    pub object_id: String,
+    pub created_at_i: i64,
+    pub author: String,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub points: Option<usize>,
+    pub num_comments: Option<usize>,
+    pub story_text: Option<String>,
+    pub comment_text: Option<String>,
+    pub story_id: Option<usize>,
+    pub story_title: Option<String>,
+    pub parent_id: Option<usize>,
+    #[serde(rename = "_tags")]
+    pub tags: Vec<String>,
+}
+
+impl SearchHit {
+    pub(crate) fn id(&self) -> HnItemId {
+        HnItemId(self.object_id.parse().unwrap_or_default())
+    }
+
+    pub(crate) fn is_comment(&self) -> bool {
+        self.tags.iter().any(|tag| tag == "comment")
+    }
+
+    /// The story to open when the hit is clicked, for comments this is
+    /// the story the comment belongs to.
+    pub(crate) fn story_id(&self) -> HnItemId {
+        match (self.is_comment(), self.story_id) {
+            (true, Some(story_id)) => HnItemId(story_id),
+            _ => self.id(),
+        }
+    }
+
+    /// Converts hit to item so it can be displayed with regular story widget,
+    /// comments get the title of the story they were posted in.
+    pub(crate) fn to_item(&self) -> HnItem {
+        let is_comment = self.is_comment();
+
+        HnItem {
+            id: self.id(),
+            r#type: if is_comment { "comment" } else { "story" }.to_string(),
+            by: self.author.clone(),
+            time: OffsetDateTime::from_unix_timestamp(self.created_at_i)
+                .unwrap_or(OffsetDateTime::UNIX_EPOCH),
+            text: if is_comment {
+                self.comment_text.clone()
+            } else {
+                self.story_text.clone()
+            }
+            .unwrap_or_default(),
+            parent: HnItemId(self.parent_id.unwrap_or_default()),
+            url: self.url.as_deref().and_then(|url| Url::parse(url).ok()),
+            score: self.points.unwrap_or_default(),
+            title: if is_comment {
+                self.story_title.clone()
+            } else {
+                self.title.clone()
+            }
+            .unwrap_or_default(),
+            descendants: self.num_comments.unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Item of the nested tree returned by the items endpoint.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub(crate) struct ThreadItem {
+    pub id: usize,
+    pub created_at_i: i64,
+    pub r#type: String,
+    pub author: Option<String>, // missing for deleted comments
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub text: Option<String>,
+    pub points: Option<usize>,
+    pub parent_id: Option<usize>,
+    pub children: Vec<ThreadItem>,
+    pub options: Vec<ThreadItem>, // of polls
+}
+
+impl ThreadItem {
+    /// Flattens the tree to items like the api returns them. Kids are in the
+    /// order of the tree which is not the ranking of the site, items already
+    /// loaded from the api keep theirs.
+    pub(crate) fn items(self) -> Vec<HnItem> {
+        let mut items = Vec::new();
+        self.flatten(&mut items);
+        items
+    }
+
+    /// Pushes the item and its descendants, returns how many comments are
+    /// below it.
+    fn flatten(self, items: &mut Vec<HnItem>) -> usize {
+        let deleted = self.author.is_none() && self.text.is_none();
+        let mut item = HnItem {
+            id: HnItemId(self.id),
+            deleted,
+            r#type: self.r#type,
+            by: self.author.unwrap_or_default(),
+            time: OffsetDateTime::from_unix_timestamp(self.created_at_i)
+                .unwrap_or(OffsetDateTime::UNIX_EPOCH),
+            text: self.text.unwrap_or_default(),
+            parent: HnItemId(self.parent_id.unwrap_or_default()),
+            kids: self.children.iter().map(|kid| HnItemId(kid.id)).collect(),
+            url: self.url.as_deref().and_then(|url| Url::parse(url).ok()),
+            score: self.points.unwrap_or_default(),
+            title: self.title.unwrap_or_default(),
+            parts: self.options.iter().map(|part| HnItemId(part.id)).collect(),
+            ..Default::default()
+        };
+
+        let position = items.len();
+        items.push(HnItem::default());
+
+        for part in self.options {
+            part.flatten(items);
+        }
+
+        let mut descendants = 0;
+        for kid in self.children {
+            descendants += 1 + kid.flatten(items);
+        }
+
+        if item.r#type == "pollopt" {
+            item.poll = item.parent;
+        } else if item.r#type != "comment" {
+            item.descendants = descendants;
+        }
+        items[position] = item;
+
+        descendants
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    fn query_pairs(url: &Url) -> Vec<(String, String)> {
+        url.query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn builds_search_url_with_filters() {
+        let query = SearchQuery {
+            text: " rust egui ".to_string(),
+            sort: SearchSort::Date,
+            tags: SearchTags::Stories,
+            author: "pg".to_string(),
+            min_points: 100,
+            from: "2023-02-01".to_string(),
+            to: "2023-02-01".to_string(),
+        };
+
+        let url = query
+            .url(&Url::parse(Algolia::DEFAULT_BASE_URL).unwrap(), 2)
+            .unwrap();
+
+        assert_eq!(url.path(), "/api/v1/search_by_date");
+        assert_eq!(
+            query_pairs(&url),
+            [
+                ("query", "rust egui"),
+                ("tags", "story,author_pg"),
+                (
+                    "numericFilters",
+                    "points>=100,created_at_i>=1675209600,created_at_i<1675296000"
+                ),
+                ("page", "2"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn builds_submissions_url() {
+        let url = submissions_url(
+            &Url::parse(Algolia::DEFAULT_BASE_URL).unwrap(),
+            &Url::parse("https://www.example.com/blog/post/?ref=hn").unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(url.path(), "/api/v1/search_by_date");
+        assert_eq!(
+            query_pairs(&url),
+            [
+                ("query", "www.example.com/blog/post"),
+                ("restrictSearchableAttributes", "url"),
+                ("tags", "story"),
+                ("hitsPerPage", "50"),
+            ]
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        let query = SearchQuery {
+            from: "2023-13-01".to_string(),
+            ..Default::default()
+        };
+
+        let base_url = Url::parse(Algolia::DEFAULT_BASE_URL).unwrap();
+        assert!(query.url(&base_url, 0).is_err());
+    }
+
+    #[test]
+    fn converts_comment_hit_to_item() {
+        let hit: SearchHit = serde_json::from_str(
+            r#"{"created_at_i":1676400000,"author":"dang","comment_text":"See <a href=\"https:&#x2F;&#x2F;news.ycombinator.com\">here</a>","story_id":34791256,"story_title":"Ask HN: Something","parent_id":34791300,"objectID":"34791400","points":null,"num_comments":null,"_tags":["comment","author_dang","story_34791256"]}"#,
+        )
+        .unwrap();
+
+        assert!(hit.is_comment());
+        assert_eq!(hit.story_id(), HnItemId(34791256));
+
+        let item = hit.to_item();
+        assert_eq!(item.id, HnItemId(34791400));
+        assert_eq!(item.r#type, "comment");
+        assert_eq!(item.title, "Ask HN: Something");
+        assert_eq!(item.parent, HnItemId(34791300));
+        assert!(item.text.starts_with("See "));
+    }
+
+    #[test]
+    fn loads_whole_thread() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/v1/items/100" => Response::json(
+                r#"{"id":100,"created_at":"2023-02-14T10:00:00.000Z","created_at_i":1676368800,"type":"story","author":"pg","title":"Example story","url":"https://example.com/","text":null,"points":42,"parent_id":null,"story_id":100,"children":[
+                    {"id":101,"created_at_i":1676368900,"type":"comment","author":"dang","text":"<p>First</p>","points":null,"parent_id":100,"story_id":100,"children":[
+                        {"id":103,"created_at_i":1676369000,"type":"comment","author":null,"text":null,"points":null,"parent_id":101,"story_id":100,"children":[],"options":[]}
+                    ],"options":[]},
+                    {"id":102,"created_at_i":1676368950,"type":"comment","author":"tptacek","text":"Second","points":null,"parent_id":100,"story_id":100,"children":[],"options":[]}
+                ],"options":[]}"#,
+            ),
+            _ => Response::not_found(),
+        });
+
+        let algolia = Algolia::new(server.url("/api/v1/").parse().unwrap(), Scheduler::new(1));
+        let thread = algolia
+            .thread(egui::Context::default(), HnItemId(100))
+            .block_and_take()
+            .unwrap();
+        let items = thread.items();
+
+        let ids: Vec<_> = items.iter().map(|item| item.id).collect();
+        assert_eq!(
+            ids,
+            [HnItemId(100), HnItemId(101), HnItemId(103), HnItemId(102)]
+        );
+
+        let story = &items[0];
+        assert_eq!(story.kids, [HnItemId(101), HnItemId(102)]);
+        assert_eq!(story.descendants, 3);
+        assert_eq!(story.score, 42);
+        assert_eq!(
+            story.url.as_ref().map(Url::as_str),
+            Some("https://example.com/")
+        );
+
+        assert_eq!(items[1].by, "dang");
+        assert_eq!(items[1].parent, HnItemId(100));
+        assert_eq!(items[1].kids, [HnItemId(103)]);
+        assert!(items[2].deleted);
+        assert!(!items[3].deleted);
+    }
+}
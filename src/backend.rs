@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use eframe::egui;
+use poll_promise::Promise;
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    backoff::Backoff,
+    error::{self, Error},
+    feeds::FeedSource,
+    fetch,
+    scheduler::{Priority, Scheduler},
+    sse::Subscription,
+    HnItem, HnItemId, HnUpdates, HnUser,
+};
+
+/// Source of Hacker News data, the app talks to the api only through this
+/// trait so it can be pointed at a local mirror or a test server.
+pub(crate) trait Backend {
+    fn stories(
+        &self,
+        ctx: egui::Context,
+        source: &FeedSource,
+        priority: Priority,
+    ) -> Promise<error::Result<Vec<HnItemId>>>;
+
+    fn item(
+        &self,
+        ctx: egui::Context,
+        item_id: HnItemId,
+        priority: Priority,
+    ) -> Promise<error::Result<HnItem>>;
+
+    fn user(
+        &self,
+        ctx: egui::Context,
+        user_id: &str,
+        priority: Priority,
+    ) -> Promise<error::Result<HnUser>>;
+
+    /// Items and profiles that changed recently, polled in the background.
+    fn updates(&self, ctx: egui::Context) -> Promise<error::Result<HnUpdates>>;
+
+    /// The largest item id, new items have larger ids, polled in the background.
+    fn max_item(&self, ctx: egui::Context) -> Promise<error::Result<HnItemId>>;
+
+    /// Streams changes of story list, `None` if backend does not support streaming.
+    fn watch_stories(&self, _ctx: egui::Context, _source: &FeedSource) -> Option<Subscription> {
+        None
+    }
+
+    /// Streams changes of item, `None` if backend does not support streaming.
+    fn watch_item(&self, _ctx: egui::Context, _item_id: HnItemId) -> Option<Subscription> {
+        None
+    }
+}
+
+/// The official api at https://github.com/HackerNews/API
+pub(crate) struct Firebase {
+    base_url: Url,
+    scheduler: Scheduler,
+}
+
+impl Firebase {
+    pub(crate) const DEFAULT_BASE_URL: &'static str = "https://hacker-news.firebaseio.com/v0/";
+
+    pub(crate) fn new(mut base_url: Url, scheduler: Scheduler) -> Self {
+        // without trailing slash joining would replace the last path segment
+        if !base_url.path().ends_with('/') {
+            base_url.set_path(&format!("{}/", base_url.path()));
+        }
+
+        // mirrors of the api are as private as the api itself
+        scheduler.privacy().add_first_party(base_url.as_str());
+
+        Self {
+            base_url,
+            scheduler,
+        }
+    }
+
+    /// Uses base url from `HACKER_NEWSFEED_API_URL` environment variable if
+    /// set and valid, otherwise the official api.
+    pub(crate) fn from_env(scheduler: Scheduler) -> Self {
+        if let Ok(base_url) = std::env::var("HACKER_NEWSFEED_API_URL") {
+            match Url::parse(&base_url) {
+                Ok(base_url) => return Self::new(base_url, scheduler),
+                Err(error) => warn!("Invalid api url {}: {}", base_url, error),
+            }
+        }
+
+        Self::new(Url::parse(Self::DEFAULT_BASE_URL).unwrap(), scheduler)
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.base_url
+            .join(path)
+            .map(String::from)
+            .unwrap_or_else(|_| format!("{}{}", self.base_url, path))
+    }
+
+    /// Url of story list, `None` for pages of the site that are scraped
+    /// instead.
+    fn stories_url(&self, source: &FeedSource) -> Option<String> {
+        match source {
+            FeedSource::Api(name) => Some(self.url(&format!("{name}.json"))),
+            FeedSource::Url(url) => Some(url.to_string()),
+            FeedSource::Site(_) => None,
+        }
+    }
+
+    fn stream_backoff() -> Backoff {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(60)).with_jitter(0.5)
+    }
+}
+
+impl Backend for Firebase {
+    fn stories(
+        &self,
+        ctx: egui::Context,
+        source: &FeedSource,
+        priority: Priority,
+    ) -> Promise<error::Result<Vec<HnItemId>>> {
+        match self.stories_url(source) {
+            Some(url) => fetch::url_and_deserialize(ctx, &self.scheduler, priority, &url),
+            None => Promise::from_ready(Err(Error::InvalidRequest(format!(
+                "{:?} is not in the api",
+                source
+            )))),
+        }
+    }
+
+    fn item(
+        &self,
+        ctx: egui::Context,
+        item_id: HnItemId,
+        priority: Priority,
+    ) -> Promise<error::Result<HnItem>> {
+        // https://hacker-news.firebaseio.com/v0/item/8863.json
+        let url = self.url(&format!("item/{item_id}.json"));
+        fetch::url_and_deserialize(ctx, &self.scheduler, priority, &url)
+    }
+
+    fn user(
+        &self,
+        ctx: egui::Context,
+        user_id: &str,
+        priority: Priority,
+    ) -> Promise<error::Result<HnUser>> {
+        // https://hacker-news.firebaseio.com/v0/user/jl.json
+        let url = self.url(&format!("user/{user_id}.json"));
+        fetch::url_and_deserialize(ctx, &self.scheduler, priority, &url)
+    }
+
+    fn updates(&self, ctx: egui::Context) -> Promise<error::Result<HnUpdates>> {
+        let url = self.url("updates.json");
+        fetch::url_and_deserialize(ctx, &self.scheduler, Priority::Prefetch, &url)
+    }
+
+    fn max_item(&self, ctx: egui::Context) -> Promise<error::Result<HnItemId>> {
+        let url = self.url("maxitem.json");
+        fetch::url_and_deserialize(ctx, &self.scheduler, Priority::Prefetch, &url)
+    }
+
+    fn watch_stories(&self, ctx: egui::Context, source: &FeedSource) -> Option<Subscription> {
+        // other servers are not expected to speak the firebase streaming protocol
+        match source {
+            FeedSource::Api(_) => {
+                let url = self.stories_url(source)?;
+                Some(Subscription::new(ctx, url, Self::stream_backoff()))
+            }
+            FeedSource::Url(_) | FeedSource::Site(_) => None,
+        }
+    }
+
+    fn watch_item(&self, ctx: egui::Context, item_id: HnItemId) -> Option<Subscription> {
+        let url = self.url(&format!("item/{item_id}.json"));
+        Some(Subscription::new(ctx, url, Self::stream_backoff()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    fn backend(server: &TestServer) -> Firebase {
+        Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(4))
+    }
+
+    #[test]
+    fn fetches_from_local_server() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/v0/topstories.json" => Response::json("[3, 2, 1]"),
+            "/custom/ids.json" => Response::json("[4]"),
+            "/v0/item/8863.json" => Response::json(
+                r#"{"by":"dhouston","descendants":71,"id":8863,"kids":[9224,8917],"score":104,"time":1175714200,"title":"My YC app: Dropbox - Throw away your USB drive","type":"story","url":"http://www.getdropbox.com/u/2/screencast.html"}"#,
+            ),
+            "/v0/user/jl.json" => Response::json(
+                r#"{"about":"This is a test","created":1173923446,"id":"jl","karma":2937,"submitted":[8265435,8168423]}"#,
+            ),
+            "/v0/updates.json" => {
+                Response::json(r#"{"items":[8423305,8420805],"profiles":["thefox","mdda"]}"#)
+            }
+            "/v0/maxitem.json" => Response::json("9130260"),
+            _ => Response::not_found(),
+        });
+        let backend = backend(&server);
+        let ctx = egui::Context::default();
+
+        let top = FeedSource::Api("topstories".to_string());
+        let stories = backend
+            .stories(ctx.clone(), &top, Priority::Visible)
+            .block_and_take();
+        assert_eq!(
+            stories.unwrap(),
+            vec![HnItemId(3), HnItemId(2), HnItemId(1)]
+        );
+
+        let custom = FeedSource::Url(server.url("/custom/ids.json").parse().unwrap());
+        let stories = backend
+            .stories(ctx.clone(), &custom, Priority::Visible)
+            .block_and_take();
+        assert_eq!(stories.unwrap(), vec![HnItemId(4)]);
+
+        let item = backend
+            .item(ctx.clone(), HnItemId(8863), Priority::Visible)
+            .block_and_take();
+        let item = item.unwrap();
+        assert_eq!(item.by, "dhouston");
+        assert_eq!(item.kids, vec![HnItemId(9224), HnItemId(8917)]);
+        assert_eq!(item.time.unix_timestamp(), 1175714200);
+
+        let user = backend
+            .user(ctx.clone(), "jl", Priority::Visible)
+            .block_and_take();
+        let user = user.unwrap();
+        assert_eq!(user.karma, 2937);
+        assert_eq!(user.submitted, vec![HnItemId(8265435), HnItemId(8168423)]);
+
+        let updates = backend.updates(ctx.clone()).block_and_take().unwrap();
+        assert_eq!(updates.items, vec![HnItemId(8423305), HnItemId(8420805)]);
+        assert_eq!(updates.profiles, vec!["thefox", "mdda"]);
+
+        let max_item = backend.max_item(ctx).block_and_take();
+        assert_eq!(max_item.unwrap(), HnItemId(9130260));
+    }
+
+    #[test]
+    fn reports_null_items_and_status_errors() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/v0/item/1.json" => Response::json("null"),
+            "/v0/item/2.json" => Response::json(r#"{"id": "not a number"}"#),
+            _ => Response::not_found(),
+        });
+        let backend = backend(&server);
+        let ctx = egui::Context::default();
+
+        let item = |id| {
+            backend
+                .item(ctx.clone(), HnItemId(id), Priority::Visible)
+                .block_and_take()
+                .map(|item| item.id)
+        };
+
+        assert_eq!(item(1), Err(Error::NullItem));
+        assert!(matches!(item(2), Err(Error::Decode(_))));
+        assert!(matches!(
+            item(3),
+            Err(Error::HttpStatus { status: 404, .. })
+        ));
+    }
+
+    #[test]
+    fn base_url_without_trailing_slash() {
+        let backend = Firebase::new(
+            "http://localhost:8080/mirror/v0".parse().unwrap(),
+            Scheduler::new(1),
+        );
+        assert_eq!(
+            backend.url("item/1.json"),
+            "http://localhost:8080/mirror/v0/item/1.json"
+        );
+    }
+}
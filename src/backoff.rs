@@ -0,0 +1,74 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// Exponential backoff between attempts, doubles the delay after every
+/// failure up to `max` and starts over from `min` after `reset`.
+#[derive(Clone, Debug)]
+pub(crate) struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    jitter: f64,
+}
+
+impl Backoff {
+    pub(crate) fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+            jitter: 0.0,
+        }
+    }
+
+    /// Shortens every delay by random fraction up to `jitter` so requests
+    /// that failed at the same time are not retried at the same time.
+    pub(crate) fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Delay to wait before the next attempt.
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay.mul_f64(1.0 - self.jitter * random_fraction())
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// Random number in `0.0..=1.0`, every `RandomState` is seeded with new random keys.
+fn random_fraction() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jittered_delays_stay_within_bounds() {
+        let mut backoff =
+            Backoff::new(Duration::from_secs(1), Duration::from_secs(4)).with_jitter(0.5);
+
+        for max in [1, 2, 4, 4] {
+            let delay = backoff.next_delay();
+            let max = Duration::from_secs(max);
+            assert!(
+                delay <= max && delay >= max / 2,
+                "{:?} for {:?}",
+                delay,
+                max
+            );
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
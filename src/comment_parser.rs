@@ -28,31 +28,32 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn to_string(&mut self) -> String {
-        let mut string = String::new();
-        for (item, _) in self {
-            match item {
-                Item::Escape(ch) => string.push(ch),
-                Item::Text(text) => string.push_str(text),
-                Item::NewLine => string.push('\n'),
-                Item::Link(_, mut text) => string.push_str(&text.to_string()),
-            }
-        }
-
-        string
-    }
-
     fn return_item_or_next(&mut self, item: Item<'a>) -> Option<(Item<'a>, TextStyle)> {
         // Do not allow sending multiple new lines
         // and decorate item with current style.
 
         match (self.last_was_newline, item) {
-            (true, Item::NewLine) => return self.next(),
+            (true, Item::NewLine) => self.next(),
             (_, item) => {
                 self.last_was_newline = matches!(item, Item::NewLine);
-                return Some((item, self.style));
+                Some((item, self.style))
+            }
+        }
+    }
+}
+
+impl<'a> std::fmt::Display for Parser<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (item, _) in *self {
+            match item {
+                Item::Escape(ch) => write!(f, "{}", ch)?,
+                Item::Text(text) => write!(f, "{}", text)?,
+                Item::NewLine => writeln!(f)?,
+                Item::Link(_, text) => write!(f, "{}", text)?,
             }
         }
+
+        Ok(())
     }
 }
 
@@ -200,7 +201,7 @@ impl<'a> Iterator for Parser<'a> {
                 ],
             )
             .unwrap_or(self.s.len())];
-            if remainder.len() > 0 {
+            if !remainder.is_empty() {
                 self.s = &self.s[remainder.len()..];
                 return self.return_item_or_next(Item::Text(remainder));
             }
@@ -210,6 +211,85 @@ impl<'a> Iterator for Parser<'a> {
     }
 }
 
+/// Formats plain text the way the site formats comments when they are
+/// posted: blank lines separate paragraphs, text surrounded by asterisks is
+/// italic, paragraphs indented by two spaces are code and urls become links.
+pub fn format(text: &str) -> String {
+    let mut html = String::new();
+    let lines: Vec<_> = text.lines().map(|line| line.trim_end()).collect();
+
+    for (index, paragraph) in lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .enumerate()
+    {
+        if index > 0 {
+            html.push_str("<p>");
+        }
+
+        if paragraph[0].starts_with("  ") {
+            html.push_str("<pre><code>");
+            html.push_str(&escape(&paragraph.join("\n")));
+            html.push_str("</code></pre>");
+        } else {
+            let words: Vec<_> = paragraph.iter().map(|line| line.trim()).collect();
+            format_inline(&words.join(" "), &mut html);
+        }
+    }
+
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&#x26;"),
+            '<' => escaped.push_str("&#x3C;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_inline(mut text: &str, html: &mut String) {
+    let mut italic = false;
+    let mut after_word = false; // urls only start at word boundary
+
+    while let Some(c) = text.chars().next() {
+        if text.starts_with("\\*") || text.starts_with("**") {
+            html.push('*');
+            text = &text[2..];
+        } else if c == '*' && (italic || text[1..].contains('*')) {
+            // asterisk without pair is literal
+            html.push_str(if italic { "</i>" } else { "<i>" });
+            italic = !italic;
+            text = &text[1..];
+        } else if !after_word && (text.starts_with("http://") || text.starts_with("https://")) {
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            let url = text[..end].trim_end_matches(|c| ".,;:!?)'\"*".contains(c));
+            html.push_str(&format!(
+                r#"<a href="{}" rel="nofollow">{}</a>"#,
+                escape(url),
+                escape(url)
+            ));
+            text = &text[url.len()..];
+        } else {
+            html.push_str(&escape(&c.to_string()));
+            text = &text[c.len_utf8()..];
+        }
+
+        after_word = c.is_alphanumeric();
+    }
+
+    if italic {
+        html.push_str("</i>");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -313,4 +393,22 @@ mod tests {
 
         assert_eq!(parser.next(), None);
     }
+
+    #[test]
+    fn formats_draft_like_the_site() {
+        let draft = "Use *Rust*, see https://www.rust-lang.org/learn.\nIt's 2 * 3 & <b>\n\n  fn main() {\n      println!(\"hi\");\n  }\n\n\n\\*not italic\\* and ** too\n";
+
+        assert_eq!(
+            format(draft),
+            "Use <i>Rust</i>, see <a href=\"https://www.rust-lang.org/learn\" rel=\"nofollow\">https://www.rust-lang.org/learn</a>. It&#x27;s 2 * 3 &#x26; &#x3C;b&gt;\
+            <p><pre><code>  fn main() {\n      println!(&quot;hi&quot;);\n  }</code></pre>\
+            <p>*not italic* and * too"
+        );
+
+        let text = Parser::new(&format(draft)).to_string();
+        assert!(
+            text.starts_with("Use Rust, see https://www.rust-lang.org/learn. It's 2 * 3 & <b>\n")
+        );
+        assert!(text.contains("println!(\"hi\");"));
+    }
 }
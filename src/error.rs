@@ -0,0 +1,73 @@
+use std::{fmt::Display, io};
+
+/// Why a request did not produce a value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Error {
+    Network(String), // cannot connect or the connection broke
+    Timeout,
+    HttpStatus { status: u16, status_text: String },
+    Decode(String),         // response body is not what we expected
+    NullItem,               // the api returns `null` for items that do not exist
+    InvalidRequest(String), // request was not sent, e.g. invalid search filters
+    Cancelled,              // request was cancelled before it was sent
+    Blocked,                // request to third party was not sent because of privacy settings
+    LoggedOut,              // the site does not recognize the session of logged in user
+}
+
+pub(crate) type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Whether the same request might succeed when tried again later.
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            Error::Network(_) | Error::Timeout => true,
+            Error::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn from_transport(error: ureq::Transport) -> Self {
+        let timed_out = std::error::Error::source(&error)
+            .and_then(|source| source.downcast_ref::<io::Error>())
+            .map_or(false, |error| {
+                matches!(
+                    error.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                )
+            });
+
+        if timed_out {
+            Error::Timeout
+        } else {
+            Error::Network(error.to_string())
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout,
+            _ => Error::Network(error.to_string()),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Network(error) => write!(f, "Cannot connect: {}", error),
+            Error::Timeout => write!(f, "Request timed out"),
+            Error::HttpStatus {
+                status,
+                status_text,
+            } => write!(f, "Server responded with {} {}", status, status_text),
+            Error::Decode(error) => write!(f, "Invalid response: {}", error),
+            Error::NullItem => write!(f, "Item does not exist"),
+            Error::InvalidRequest(error) => write!(f, "{}", error),
+            Error::Cancelled => write!(f, "Request cancelled"),
+            Error::Blocked => write!(f, "Blocked by privacy settings"),
+            Error::LoggedOut => write!(f, "Logged out, log in again"),
+        }
+    }
+}
@@ -0,0 +1,541 @@
+//! Favicons of story sites, one per origin, cached on disk and revalidated
+//! with `ETag` and `Last-Modified` headers once they get old. Link previews
+//! of story pages are read from the same download of the page.
+
+use std::cell::OnceCell;
+
+use eframe::{egui, epaint::ahash::HashMap};
+use egui_extras::RetainedImage;
+use poll_promise::{Promise, Sender};
+use serde::{Deserialize, Serialize};
+use time::Duration;
+use url::Url;
+
+use crate::{
+    error::{self, Error},
+    fetch::{self, FetchedFavicon, LinkPreview},
+    scheduler::{Priority, Request, Response, Scheduler},
+    store::{Store, Stored},
+    widgets, HnItemId,
+};
+
+/// How long stored favicon is used without asking its site.
+const FAVICON_TTL: Duration = Duration::weeks(1);
+
+/// How long site without favicon is not asked again.
+const MISSING_FAVICON_TTL: Duration = Duration::days(1);
+
+/// How long stored link preview is used without downloading its page.
+const PREVIEW_TTL: Duration = Duration::weeks(1);
+
+/// Page of story downloaded once it is needed, by preview or favicon.
+type Page<'a> = dyn Fn() -> &'a error::Result<Response> + 'a;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FaviconEntry {
+    url: Option<String>, // None when site has no favicon
+    content_type: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl FaviconEntry {
+    fn missing() -> Self {
+        Self {
+            url: None,
+            content_type: String::new(),
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    fn from_response(response: &Response) -> Self {
+        let header = |name: &str| response.headers.get(name).cloned();
+
+        Self {
+            url: Some(response.url.clone()),
+            content_type: response.content_type().unwrap_or_default().to_string(),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+        }
+    }
+
+    /// Response the stored image came from.
+    fn response(&self, url: &str, bytes: Vec<u8>) -> Response {
+        Response {
+            url: url.to_string(),
+            status: 200,
+            status_text: "OK".to_string(),
+            bytes,
+            headers: [("content-type".to_string(), self.content_type.clone())].into(),
+        }
+    }
+}
+
+/// Favicons by origin of the site and link previews by story, loaded from
+/// the store or fetched on background threads.
+pub(crate) struct Favicons {
+    scheduler: Scheduler,
+    store: Store,
+    icons: HashMap<String, Promise<error::Result<RetainedImage>>>,
+    previews: HashMap<HnItemId, Promise<error::Result<LinkPreview>>>,
+    thumbnails: HashMap<Url, Promise<error::Result<RetainedImage>>>, // images of previews
+}
+
+impl Favicons {
+    pub(crate) fn new(scheduler: Scheduler, store: Store) -> Self {
+        Self {
+            scheduler,
+            store,
+            icons: Default::default(),
+            previews: Default::default(),
+            thumbnails: Default::default(),
+        }
+    }
+
+    /// Loads favicon of site of `url` and preview of story with the `url` if
+    /// `with_preview` unless they are loaded already. Favicon of new site is
+    /// loaded on its own thread which also reads the preview from the page.
+    pub(crate) fn load(
+        &mut self,
+        ctx: &egui::Context,
+        story_id: HnItemId,
+        url: &Url,
+        with_preview: bool,
+    ) {
+        let Some(origin) = origin(url) else {
+            return;
+        };
+
+        if let Some(Ok(preview)) = self.previews.get(&story_id).and_then(|p| p.ready()) {
+            if let Some(image) = &preview.image {
+                self.thumbnails.entry(image.clone()).or_insert_with(|| {
+                    fetch::image(
+                        ctx.clone(),
+                        &self.scheduler,
+                        image.as_str(),
+                        Priority::Favicon,
+                    )
+                });
+            }
+        }
+
+        let icon = (!self.icons.contains_key(&origin)).then(|| {
+            let (sender, promise) = Promise::new();
+            self.icons.insert(origin.clone(), promise);
+            sender
+        });
+        let preview = (with_preview && !self.previews.contains_key(&story_id)).then(|| {
+            let (sender, promise) = Promise::new();
+            self.previews.insert(story_id, promise);
+            sender
+        });
+
+        let Some(icon) = icon else {
+            if let Some(sender) = preview {
+                self.fetch_preview(ctx, story_id, url, sender);
+            }
+            return;
+        };
+
+        let size = widgets::favicon_pixels(ctx);
+        let ctx = ctx.clone();
+        let scheduler = self.scheduler.clone();
+        let store = self.store.clone();
+        let url = url.to_string();
+        std::thread::Builder::new()
+            .name("favicon".to_string())
+            .spawn(move || {
+                let page = OnceCell::new();
+                let page = || page.get_or_init(|| fetch::get_blocking(&scheduler, &url));
+
+                if let Some(sender) = preview {
+                    sender.send(load_preview(&store, story_id, &page));
+                }
+
+                icon.send(load(&ctx, &scheduler, &store, &origin, &url, &page, size));
+                ctx.request_repaint(); // wake up UI thread, we have icon to re-render
+            })
+            .expect("cannot spawn favicon thread");
+    }
+
+    /// Preview of story of site whose favicon is loaded already, the stored
+    /// one is read off the ui thread.
+    fn fetch_preview(
+        &self,
+        ctx: &egui::Context,
+        story_id: HnItemId,
+        url: &Url,
+        sender: Sender<error::Result<LinkPreview>>,
+    ) {
+        let ctx = ctx.clone();
+        let scheduler = self.scheduler.clone();
+        let store = self.store.clone();
+        let url = url.to_string();
+        std::thread::Builder::new()
+            .name("preview".to_string())
+            .spawn(move || {
+                let stored = store.preview(story_id);
+                if let Some(preview) = fresh_preview(&stored) {
+                    sender.send(Ok(preview));
+                    ctx.request_repaint();
+                    return;
+                }
+
+                scheduler.fetch(Request::get(url), Priority::Favicon, move |page| {
+                    sender.send(preview_from_page(&store, story_id, stored, &page));
+                    ctx.request_repaint(); // wake up UI thread, we have preview to render
+                });
+            })
+            .expect("cannot spawn preview thread");
+    }
+
+    /// Forgets favicons and previews that were blocked so they are loaded
+    /// again, e.g. when privacy settings change.
+    pub(crate) fn remove_blocked(&mut self) {
+        fn is_blocked<T: Send>(promise: &Promise<error::Result<T>>) -> bool {
+            matches!(promise.ready(), Some(Err(Error::Blocked)))
+        }
+
+        self.icons.retain(|_, promise| !is_blocked(promise));
+        self.previews.retain(|_, promise| !is_blocked(promise));
+        self.thumbnails.retain(|_, promise| !is_blocked(promise));
+    }
+
+    pub(crate) fn get(&self, url: &Url) -> Option<&RetainedImage> {
+        self.icons
+            .get(&origin(url)?)
+            .and_then(|promise| promise.ready())
+            .and_then(|result| result.as_ref().ok())
+    }
+
+    pub(crate) fn preview(&self, story_id: HnItemId) -> Option<&LinkPreview> {
+        self.previews
+            .get(&story_id)
+            .and_then(|promise| promise.ready())
+            .and_then(|result| result.as_ref().ok())
+    }
+
+    pub(crate) fn thumbnail(&self, url: &Url) -> Option<&RetainedImage> {
+        self.thumbnails
+            .get(url)
+            .and_then(|promise| promise.ready())
+            .and_then(|result| result.as_ref().ok())
+    }
+}
+
+fn origin(url: &Url) -> Option<String> {
+    match url.scheme() {
+        "http" | "https" => Some(url.origin().ascii_serialization()),
+        _ => None,
+    }
+}
+
+/// Stored preview unless it is old, pages that cannot be read get empty
+/// preview so they are not downloaded again and again.
+fn load_preview(store: &Store, story_id: HnItemId, page: &Page) -> error::Result<LinkPreview> {
+    let stored = store.preview(story_id);
+    match fresh_preview(&stored) {
+        Some(preview) => Ok(preview),
+        None => preview_from_page(store, story_id, stored, page()),
+    }
+}
+
+fn fresh_preview(stored: &Option<Stored<LinkPreview>>) -> Option<LinkPreview> {
+    stored
+        .as_ref()
+        .filter(|stored| stored.age() < PREVIEW_TTL)
+        .map(|stored| stored.value.clone())
+}
+
+fn preview_from_page(
+    store: &Store,
+    story_id: HnItemId,
+    stored: Option<Stored<LinkPreview>>,
+    page: &error::Result<Response>,
+) -> error::Result<LinkPreview> {
+    match page {
+        Ok(page) => {
+            let preview = page
+                .text()
+                .map(|html| fetch::parse_link_preview(&page.url, html))
+                .unwrap_or_default();
+            store.save_preview(story_id, &preview);
+            Ok(preview)
+        }
+        // keep using the old one until the site is reachable or allowed
+        Err(error) if error.is_transient() || *error == Error::Blocked => match stored {
+            Some(stored) => Ok(stored.value),
+            None => Err(error.clone()),
+        },
+        Err(error) => {
+            store.save_preview(story_id, &LinkPreview::default());
+            Err(error.clone())
+        }
+    }
+}
+
+fn load(
+    ctx: &egui::Context,
+    scheduler: &Scheduler,
+    store: &Store,
+    origin: &str,
+    url: &str,
+    page: &Page,
+    size: u32,
+) -> error::Result<RetainedImage> {
+    let Some(stored) = store.favicon(origin) else {
+        return discover(scheduler, store, origin, url, page, size);
+    };
+
+    let Some(icon_url) = &stored.value.url else {
+        if stored.age() < MISSING_FAVICON_TTL {
+            return Err(Error::Decode("Site has no favicon".to_string()));
+        }
+        return discover(scheduler, store, origin, url, page, size);
+    };
+
+    let Some(bytes) = store.favicon_image(origin) else {
+        return discover(scheduler, store, origin, url, page, size);
+    };
+    let cached = stored.value.response(icon_url, bytes);
+
+    if stored.age() < FAVICON_TTL {
+        return fetch::decode_image(&cached);
+    }
+
+    let revalidated = fetch::revalidate_favicon(
+        ctx.clone(),
+        scheduler,
+        icon_url,
+        stored.value.etag.as_deref(),
+        stored.value.last_modified.as_deref(),
+    )
+    .block_and_take();
+
+    match revalidated {
+        Ok(None) => {
+            store.save_favicon(origin, &stored.value, None);
+            fetch::decode_image(&cached)
+        }
+        Ok(Some(fetched)) => Ok(save(store, origin, fetched)),
+        // keep using the old one until the site is reachable or allowed
+        Err(error) if error.is_transient() || error == Error::Blocked => {
+            fetch::decode_image(&cached)
+        }
+        Err(_) => discover(scheduler, store, origin, url, page, size),
+    }
+}
+
+/// Looks for favicon of the site, sites without one are stored as well.
+fn discover(
+    scheduler: &Scheduler,
+    store: &Store,
+    origin: &str,
+    url: &str,
+    page: &Page,
+    size: u32,
+) -> error::Result<RetainedImage> {
+    match fetch::discover_favicon(scheduler, page(), url, size) {
+        Ok(fetched) => Ok(save(store, origin, fetched)),
+        Err(error) => {
+            if !error.is_transient() && error != Error::Blocked {
+                store.save_favicon(origin, &FaviconEntry::missing(), None);
+            }
+            Err(error)
+        }
+    }
+}
+
+fn save(store: &Store, origin: &str, fetched: FetchedFavicon) -> RetainedImage {
+    store.save_favicon(
+        origin,
+        &FaviconEntry::from_response(&fetched.response),
+        Some(&fetched.response.bytes),
+    );
+    fetched.image
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{
+        store::{file_name, temp_dir, Stored},
+        test_server::{Response, TestServer},
+    };
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn load_icon(favicons: &mut Favicons, story_id: usize, url: &str, previews: bool) -> bool {
+        let ctx = egui::Context::default();
+        let url = Url::parse(url).unwrap();
+        favicons.load(&ctx, HnItemId(story_id), &url, previews);
+        if let Some(preview) = favicons.previews.get(&HnItemId(story_id)) {
+            preview.block_until_ready();
+        }
+        favicons.icons[&origin(&url).unwrap()].block_until_ready();
+        favicons.get(&url).is_some()
+    }
+
+    #[test]
+    fn caches_favicons_per_origin_and_revalidates_them() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let requests = requests.clone();
+            TestServer::start(move |request| {
+                let not_modified = request.header("If-None-Match") == Some("\"v1\"");
+                requests
+                    .lock()
+                    .unwrap()
+                    .push((request.path.clone(), not_modified));
+
+                match request.path.as_str() {
+                    "/favicon.ico" if not_modified => Response::new(304, "image/png", ""),
+                    "/favicon.ico" => {
+                        Response::new(200, "image/png", png()).with_header("ETag", "\"v1\"")
+                    }
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let dir = temp_dir("favicons");
+        let store = Store::new(Some(dir.clone()));
+        let scheduler = Scheduler::new(1);
+        let mut favicons = Favicons::new(scheduler.clone(), store.clone());
+        let favicon_requests = || {
+            let requests = requests.lock().unwrap();
+            requests
+                .iter()
+                .filter(|(path, _)| path == "/favicon.ico")
+                .count()
+        };
+
+        // stories from the same site share one favicon
+        assert!(load_icon(&mut favicons, 1, &server.url("/a.html"), true));
+        assert!(load_icon(&mut favicons, 2, &server.url("/b.html"), true));
+        store.flush();
+        assert_eq!(favicon_requests(), 1);
+
+        // fresh favicon is loaded from disk after restart
+        let mut favicons = Favicons::new(scheduler.clone(), store.clone());
+        assert!(load_icon(&mut favicons, 3, &server.url("/c.html"), true));
+        store.flush();
+        assert_eq!(favicon_requests(), 1);
+
+        // old favicon is revalidated and kept when it did not change
+        let origin = origin(&Url::parse(&server.url("/")).unwrap()).unwrap();
+        let entry = store.favicon(&origin).unwrap().value;
+        let stored = Stored {
+            fetched: time::OffsetDateTime::now_utc() - Duration::weeks(2),
+            value: &entry,
+        };
+        let path = dir.join(format!("favicons/{}.json", file_name(&origin)));
+        std::fs::write(path, serde_json::to_vec(&stored).unwrap()).unwrap();
+
+        let mut favicons = Favicons::new(scheduler, store.clone());
+        assert!(load_icon(&mut favicons, 3, &server.url("/c.html"), true));
+        assert_eq!(
+            requests.lock().unwrap().last(),
+            Some(&("/favicon.ico".to_string(), true))
+        );
+        store.flush();
+        assert!(store.favicon(&origin).unwrap().age() < Duration::minutes(1));
+    }
+
+    #[test]
+    fn remembers_sites_without_favicon() {
+        let requests = Arc::new(Mutex::new(0));
+        let server = {
+            let requests = requests.clone();
+            TestServer::start(move |request| {
+                *requests.lock().unwrap() += 1;
+                match request.path.as_str() {
+                    "/story.html" => Response::html(
+                        r#"<html><head><meta property="og:description" content="Story"></head></html>"#,
+                    ),
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let store = Store::new(Some(temp_dir("favicons-missing")));
+        let scheduler = Scheduler::new(1);
+
+        let mut favicons = Favicons::new(scheduler.clone(), store.clone());
+        assert!(!load_icon(
+            &mut favicons,
+            1,
+            &server.url("/story.html"),
+            true
+        ));
+        assert_eq!(*requests.lock().unwrap(), 2); // favicon.ico and the page
+        store.flush();
+
+        // the preview is read from the same page and stored with it
+        let mut favicons = Favicons::new(scheduler, store);
+        assert!(!load_icon(
+            &mut favicons,
+            1,
+            &server.url("/story.html"),
+            true
+        ));
+        assert_eq!(*requests.lock().unwrap(), 2);
+        assert_eq!(
+            favicons
+                .preview(HnItemId(1))
+                .unwrap()
+                .description
+                .as_deref(),
+            Some("Story")
+        );
+    }
+
+    #[test]
+    fn loads_previews_only_when_shown() {
+        let pages = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let pages = pages.clone();
+            TestServer::start(move |request| match request.path.as_str() {
+                "/favicon.ico" => Response::new(200, "image/png", png()),
+                path => {
+                    pages.lock().unwrap().push(path.to_string());
+                    Response::html(
+                        r#"<html><head><meta property="og:description" content="Story"></head></html>"#,
+                    )
+                }
+            })
+        };
+
+        let store = Store::new(Some(temp_dir("favicons-previews")));
+        let mut favicons = Favicons::new(Scheduler::new(1), store);
+
+        // favicon of new site is looked for in the page
+        assert!(load_icon(&mut favicons, 1, &server.url("/a.html"), false));
+        assert!(load_icon(&mut favicons, 2, &server.url("/b.html"), false));
+        assert_eq!(*pages.lock().unwrap(), ["/a.html"]);
+        assert!(favicons.preview(HnItemId(2)).is_none());
+
+        assert!(load_icon(&mut favicons, 2, &server.url("/b.html"), true));
+        assert_eq!(*pages.lock().unwrap(), ["/a.html", "/b.html"]);
+        assert_eq!(
+            favicons
+                .preview(HnItemId(2))
+                .unwrap()
+                .description
+                .as_deref(),
+            Some("Story")
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use eframe::egui::{Key, KeyboardShortcut, Modifiers};
+use serde::Deserialize;
+use tracing::warn;
+use url::Url;
+
+/// Where the item ids of a feed come from.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FeedSource {
+    Api(String),  // story list of the backend, e.g. `topstories` for `/v0/topstories.json`
+    Url(Url),     // any url returning json array of item ids
+    Site(String), // page of the site scraped for its items, e.g. `from?site=github.com`
+}
+
+/// Story list displayed as a tab in the header.
+#[derive(Clone, Debug)]
+pub(crate) struct Feed {
+    pub name: String,
+    pub source: FeedSource,
+    pub shortcut: Option<KeyboardShortcut>,
+}
+
+/// Feed as written in the user feeds file.
+#[derive(Deserialize)]
+struct FeedConfig {
+    name: String,
+    #[serde(flatten)]
+    source: FeedSource,
+}
+
+/// Shortcuts are assigned to feeds by their position in the header.
+const SHORTCUT_KEYS: [Key; 9] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+
+/// Feeds in the order they are displayed in the header.
+pub(crate) struct Feeds {
+    feeds: Vec<Feed>,
+}
+
+impl Feeds {
+    pub(crate) fn builtin() -> Self {
+        let mut feeds = Self { feeds: Vec::new() };
+        feeds.add("Top", FeedSource::Api("topstories".to_string()));
+        feeds.add("New", FeedSource::Api("newstories".to_string()));
+        feeds.add("Show", FeedSource::Api("showstories".to_string()));
+        feeds.add("Ask", FeedSource::Api("askstories".to_string()));
+        feeds.add("Jobs", FeedSource::Api("jobstories".to_string()));
+        feeds.add("Best", FeedSource::Api("beststories".to_string()));
+        feeds
+    }
+
+    /// Built-in feeds followed by feeds from the json file at path in
+    /// `HACKER_NEWSFEED_FEEDS` environment variable if set.
+    pub(crate) fn from_env() -> Self {
+        let mut feeds = Self::builtin();
+
+        if let Ok(path) = std::env::var("HACKER_NEWSFEED_FEEDS") {
+            match std::fs::read_to_string(&path) {
+                Ok(json) => {
+                    if let Err(error) = feeds.add_from_json(&json) {
+                        warn!("Invalid feeds file {}: {}", path, error);
+                    }
+                }
+                Err(error) => warn!("Cannot read feeds file {}: {}", path, error),
+            }
+        }
+
+        feeds
+    }
+
+    /// Appends feed to the end of the header.
+    pub(crate) fn add(&mut self, name: impl Into<String>, source: FeedSource) {
+        let shortcut = SHORTCUT_KEYS
+            .get(self.feeds.len())
+            .map(|&key| KeyboardShortcut::new(Modifiers::ALT, key));
+
+        self.feeds.push(Feed {
+            name: name.into(),
+            source,
+            shortcut,
+        });
+    }
+
+    /// Adds feeds from json array like `[{"name": "Ids", "url": "https://example.com/ids.json"}]`.
+    fn add_from_json(&mut self, json: &str) -> serde_json::Result<()> {
+        let configs: Vec<FeedConfig> = serde_json::from_str(json)?;
+        for config in configs {
+            self.add(config.name, config.source);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&Feed> {
+        self.feeds.get(index)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Feed> {
+        self.feeds.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_user_feeds_after_builtin() {
+        let mut feeds = Feeds::builtin();
+        feeds
+            .add_from_json(
+                r#"[
+                    {"name": "Polls", "url": "http://localhost:8080/polls.json"},
+                    {"name": "Launches", "api": "launchstories"},
+                    {"name": "GitHub", "site": "from?site=github.com"},
+                    {"name": "Many", "api": "manystories"},
+                    {"name": "Too Many", "api": "toomanystories"}
+                ]"#,
+            )
+            .unwrap();
+
+        let names: Vec<_> = feeds.iter().map(|feed| feed.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Top", "New", "Show", "Ask", "Jobs", "Best", "Polls", "Launches", "GitHub", "Many",
+                "Too Many"
+            ]
+        );
+
+        let polls = feeds.get(6).unwrap();
+        assert_eq!(
+            polls.source,
+            FeedSource::Url("http://localhost:8080/polls.json".parse().unwrap())
+        );
+        assert_eq!(
+            polls.shortcut,
+            Some(KeyboardShortcut::new(Modifiers::ALT, Key::Num7))
+        );
+
+        assert_eq!(
+            feeds.get(8).unwrap().source,
+            FeedSource::Site("from?site=github.com".to_string())
+        );
+
+        // there are only nine number keys
+        assert_eq!(feeds.get(9).unwrap().shortcut, None);
+    }
+
+    #[test]
+    fn rejects_feed_without_source() {
+        let mut feeds = Feeds::builtin();
+        assert!(feeds.add_from_json(r#"[{"name": "Nothing"}]"#).is_err());
+    }
+}
@@ -1,146 +1,500 @@
+use std::{collections::HashSet, time::Duration};
+
 use eframe::egui;
 use egui_extras::RetainedImage;
 use poll_promise::{Promise, Sender};
 use scraper::{Html, Selector};
-use tracing::warn;
+use serde::{Deserialize, Serialize};
+use time::Date;
+use tracing::debug;
 use url::Url;
 
-use crate::{HnItem, HnItemId, Page};
+use crate::{
+    algolia,
+    backoff::Backoff,
+    error::{self, Error},
+    reader::{self, Article},
+    scheduler::{Priority, Request, Response, Scheduler},
+};
+
+/// How many times request is sent before its transient error is reported.
+const ATTEMPTS: usize = 4;
+
+fn retry_backoff() -> Backoff {
+    Backoff::new(Duration::from_millis(500), Duration::from_secs(4)).with_jitter(0.5)
+}
 
-fn url_and_deserialize<T>(ctx: egui::Context, url: &str) -> Promise<ehttp::Result<T>>
+pub(crate) fn url_and_deserialize<T>(
+    ctx: egui::Context,
+    scheduler: &Scheduler,
+    priority: Priority,
+    url: &str,
+) -> Promise<error::Result<T>>
 where
-    T: serde::de::DeserializeOwned + Send,
+    T: serde::de::DeserializeOwned + Send + 'static,
 {
     let (sender, promise) = Promise::new();
-    let request = ehttp::Request::get(url);
-    ehttp::fetch(request, move |response| {
-        ctx.request_repaint(); // wake up UI thread
+    let request = Retry {
+        ctx,
+        scheduler: scheduler.clone(),
+        priority,
+        url: url.to_string(),
+        backoff: retry_backoff(),
+        attempts: ATTEMPTS,
+    };
+    request.send(Duration::ZERO, sender);
 
-        if let Err(err) = response {
-            sender.send(Err(err));
-            return;
-        }
+    promise
+}
 
-        let response = response.unwrap();
+/// Request that is sent again after a delay when it fails with transient error.
+struct Retry {
+    ctx: egui::Context,
+    scheduler: Scheduler,
+    priority: Priority,
+    url: String,
+    backoff: Backoff,
+    attempts: usize,
+}
 
-        match serde_json::from_slice::<T>(&response.bytes) {
-            Ok(value) => sender.send(Ok(value)),
-            Err(err) => sender.send(Err(format!("Could not deserialize response: {}", err))),
-        }
-    });
+impl Retry {
+    fn send<T>(mut self, delay: Duration, sender: Sender<error::Result<T>>)
+    where
+        T: serde::de::DeserializeOwned + Send + 'static,
+    {
+        let request = Request::get(&self.url);
+        let scheduler = self.scheduler.clone();
+        scheduler.fetch_after(delay, request, self.priority, move |response| {
+            match response.and_then(|response| deserialize(&response.bytes)) {
+                Err(error) if error.is_transient() && self.attempts > 1 => {
+                    let delay = self.backoff.next_delay();
+                    debug!("Retrying {} in {:?}: {}", self.url, delay, error);
+                    self.attempts -= 1;
 
-    promise
+                    // waits in the scheduler so it is cancelled with its priority
+                    self.send(delay, sender);
+                }
+                result => {
+                    self.ctx.request_repaint(); // wake up UI thread
+                    sender.send(result);
+                }
+            }
+        });
+    }
 }
 
-#[rustfmt::skip]
-pub(crate) fn page_stories(page: Page, ctx: egui::Context) -> Promise<ehttp::Result<Vec<HnItemId>>> {
-    match page {
-        Page::Top => url_and_deserialize(ctx, "https://hacker-news.firebaseio.com/v0/topstories.json"),
-        Page::New => url_and_deserialize(ctx, "https://hacker-news.firebaseio.com/v0/newstories.json"),
-        Page::Show => url_and_deserialize(ctx, "https://hacker-news.firebaseio.com/v0/showstories.json"),
-        Page::Ask => url_and_deserialize(ctx, "https://hacker-news.firebaseio.com/v0/askstories.json"),
-        Page::Jobs => url_and_deserialize(ctx, "https://hacker-news.firebaseio.com/v0/jobstories.json"),
+/// Deserializes json body, the api responds with `null` to items that do not exist.
+fn deserialize<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> error::Result<T> {
+    if std::str::from_utf8(bytes).map(str::trim) == Ok("null") {
+        return Err(Error::NullItem);
     }
+
+    serde_json::from_slice(bytes).map_err(|error| Error::Decode(error.to_string()))
 }
 
-pub(crate) fn hn_item(ctx: egui::Context, item_id: HnItemId) -> Promise<ehttp::Result<HnItem>> {
-    // https://hacker-news.firebaseio.com/v0/item/8863.json
-    url_and_deserialize(
-        ctx,
-        &format!("https://hacker-news.firebaseio.com/v0/item/{item_id}.json"),
-    )
+/// Favicon image with the response it was decoded from.
+pub(crate) struct FetchedFavicon {
+    pub image: RetainedImage,
+    pub response: Response,
 }
 
-pub(crate) fn favicon(ctx: egui::Context, url: &str) -> Promise<ehttp::Result<RetainedImage>> {
-    // 1. try to fetch base url + /favicon.ico
-    // 2. if that fails download the web page and check head for
-    //   1. link rel shortcut icon href
-    //   2. link rel icon href
+/// Finds favicon of `page` at `url` that fits `size` pixels the best.
+pub(crate) fn discover_favicon(
+    scheduler: &Scheduler,
+    page: &error::Result<Response>,
+    url: &str,
+    size: u32,
+) -> error::Result<FetchedFavicon> {
+    // 1. collect icons from head of the page
+    //   1. link rel icon, shortcut icon and apple-touch-icon with their sizes
+    //   2. icons listed in web app manifest linked from the page
+    // 2. try them from the best fitting one
+    // 3. if none of them works fall back to base url + /favicon.ico
     //
-    // href can also be relative or absolute
+    // hrefs can also be relative or absolute
 
-    let (sender, promise) = Promise::new();
+    let mut candidates = Vec::new();
+    let mut last_error = Error::Decode("Site has no favicon".to_string());
 
-    if let Some(favicon_url) = get_root_favicon_url(&url) {
-        favicon_or_else(ctx, &favicon_url, sender, favicon_from_html);
-    } else {
-        favicon_from_html(ctx, url, sender);
+    match page {
+        Ok(page) => {
+            if let Some(html) = page.text() {
+                let (icons, manifest_url) = parse_icon_links(&page.url, html);
+                candidates = icons;
+
+                if let Some(manifest_url) = manifest_url {
+                    match get_blocking(scheduler, manifest_url.as_str()) {
+                        Ok(manifest) => {
+                            candidates.extend(parse_manifest_icons(&manifest.url, &manifest.bytes))
+                        }
+                        Err(error) => debug!("Cannot fetch manifest {}: {}", manifest_url, error),
+                    }
+                }
+            }
+        }
+        Err(error) => last_error = error.clone(),
     }
 
-    promise
+    sort_icon_candidates(&mut candidates, size);
+
+    let mut urls: Vec<String> = candidates.iter().map(|icon| icon.url.to_string()).collect();
+    urls.extend(get_root_favicon_url(url));
+
+    // pages often link the root favicon too, it is tried once
+    let mut seen = HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+
+    for icon_url in urls {
+        let result = get_blocking(scheduler, &icon_url).and_then(|response| {
+            let image = decode_image(&response)?;
+            Ok(FetchedFavicon { image, response })
+        });
+
+        match result {
+            Ok(favicon) => return Ok(favicon),
+            Err(error) => {
+                debug!("Cannot fetch favicon {}: {}", icon_url, error);
+                last_error = error;
+            }
+        }
+    }
+
+    Err(last_error)
 }
 
-fn favicon_from_html(ctx: egui::Context, url: &str, sender: Sender<Result<RetainedImage, String>>) {
-    let request = ehttp::Request::get(url);
-    ehttp::fetch(request, move |response| match response {
-        Ok(response) => {
-            if let Some(text) = response.text() {
-                let html = Html::parse_document(text);
-                let selector = Selector::parse("link[rel~='icon']").unwrap();
-
-                if let Some(element) = html.select(&selector).next() {
-                    if let Some(href) = element.value().attr("href") {
-                        if let Some(url) = parse_favicon_url_from_base(&response.url, href) {
-                            favicon_or_else(ctx, url.as_str(), sender, |_, _, sender| {
-                                sender.send(Err("Cannot fetch favicon".to_string()));
-                            });
-                            return;
-                        };
-                        sender.send(Err(format!(
-                            "cannot resolve favicon href {} from {}",
-                            href, response.url
-                        )));
-                        return;
-                    }
+/// Sends request and waits for its response, only for background threads.
+pub(crate) fn get_blocking(scheduler: &Scheduler, url: &str) -> error::Result<Response> {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    scheduler.fetch(Request::get(url), Priority::Favicon, move |response| {
+        let _ = sender.send(response);
+    });
+
+    receiver.recv().unwrap_or(Err(Error::Cancelled))
+}
+
+/// Icon referenced by page or its manifest.
+#[derive(Debug, PartialEq)]
+struct IconCandidate {
+    url: Url,
+    sizes: Vec<u32>, // widths in pixels, declared or guessed from the kind of icon
+    scalable: bool,
+}
+
+impl IconCandidate {
+    /// How bad the icon looks at `size` pixels, upscaled icons are blurry so
+    /// they are worse than bigger ones.
+    fn penalty(&self, size: u32) -> u32 {
+        if self.scalable {
+            return 0;
+        }
+
+        self.sizes
+            .iter()
+            .map(|&width| {
+                if width >= size {
+                    width - size
+                } else {
+                    (size - width) * 4
                 }
+            })
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+/// Parses `sizes` attribute like `16x16 32x32` or `any`.
+fn parse_icon_sizes(sizes: &str) -> (Vec<u32>, bool) {
+    let mut widths = Vec::new();
+    let mut scalable = false;
+
+    for size in sizes.split_ascii_whitespace() {
+        if size.eq_ignore_ascii_case("any") {
+            scalable = true;
+        } else if let Some((width, height)) = size.to_ascii_lowercase().split_once('x') {
+            if let (Ok(width), Ok(height)) = (width.parse::<u32>(), height.parse::<u32>()) {
+                widths.push(width.max(height));
             }
+        }
+    }
+
+    (widths, scalable)
+}
+
+fn icon_candidate(
+    base_url: &str,
+    href: &str,
+    sizes: Option<&str>,
+    mime_type: Option<&str>,
+    default_size: u32,
+) -> Option<IconCandidate> {
+    let url = parse_favicon_url_from_base(base_url, href)?;
+    let (mut sizes, any_size) = parse_icon_sizes(sizes.unwrap_or_default());
+    if sizes.is_empty() {
+        sizes.push(default_size);
+    }
+
+    let scalable = any_size
+        || mime_type.map_or(false, |mime_type| mime_type.starts_with("image/svg"))
+        || url.path().ends_with(".svg");
+
+    Some(IconCandidate {
+        url,
+        sizes,
+        scalable,
+    })
+}
+
+/// Icons linked from head of the page and url of its web app manifest.
+fn parse_icon_links(page_url: &str, html: &str) -> (Vec<IconCandidate>, Option<Url>) {
+    let html = Html::parse_document(html);
+    let selector = Selector::parse(
+        "link[rel~='icon'], link[rel~='apple-touch-icon'], link[rel~='apple-touch-icon-precomposed'], link[rel~='manifest']",
+    )
+    .unwrap();
 
-            sender.send(Err("Cannot fetch favicon".to_string()));
+    let mut icons = Vec::new();
+    let mut manifest_url = None;
+
+    for element in html.select(&selector) {
+        let element = element.value();
+        let Some(href) = element.attr("href") else {
+            continue;
+        };
+        let rel = element.attr("rel").unwrap_or_default().to_ascii_lowercase();
+        let rel: Vec<_> = rel.split_ascii_whitespace().collect();
+
+        if rel.contains(&"manifest") {
+            if manifest_url.is_none() {
+                manifest_url = parse_favicon_url_from_base(page_url, href);
+            }
+            continue;
         }
+
+        // apple touch icons are 180x180 unless they say otherwise, icons
+        // without sizes are usually the classic 16x16 favicons
+        let default_size = if rel.contains(&"icon") { 16 } else { 180 };
+
+        icons.extend(icon_candidate(
+            page_url,
+            href,
+            element.attr("sizes"),
+            element.attr("type"),
+            default_size,
+        ));
+    }
+
+    (icons, manifest_url)
+}
+
+/// Icons listed in web app manifest, their urls are relative to the manifest.
+fn parse_manifest_icons(manifest_url: &str, bytes: &[u8]) -> Vec<IconCandidate> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        #[serde(default)]
+        icons: Vec<ManifestIcon>,
+    }
+
+    #[derive(Deserialize)]
+    struct ManifestIcon {
+        src: String,
+        sizes: Option<String>,
+        r#type: Option<String>,
+        purpose: Option<String>,
+    }
+
+    let manifest: Manifest = match serde_json::from_slice(bytes) {
+        Ok(manifest) => manifest,
         Err(error) => {
-            sender.send(Err(error));
+            debug!("Invalid manifest {}: {}", manifest_url, error);
+            return Vec::new();
         }
-    });
+    };
+
+    manifest
+        .icons
+        .iter()
+        // monochrome icons are masks for the system to fill with color
+        .filter(|icon| icon.purpose.as_deref() != Some("monochrome"))
+        .filter_map(|icon| {
+            icon_candidate(
+                manifest_url,
+                &icon.src,
+                icon.sizes.as_deref(),
+                icon.r#type.as_deref(),
+                192,
+            )
+        })
+        .collect()
+}
+
+/// What page says about itself for link previews, fields it does not have
+/// are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct LinkPreview {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<Url>,
+    pub published: Option<Date>,
 }
 
-fn favicon_or_else<T>(
+/// Reads OpenGraph and other meta tags from head of the page.
+pub(crate) fn parse_link_preview(page_url: &str, html: &str) -> LinkPreview {
+    let html = Html::parse_document(html);
+
+    // the first of the selectors that matches element with non-empty value wins
+    let find = |selectors: &[&str], attr: &str| {
+        selectors.iter().find_map(|selector| {
+            let selector = Selector::parse(selector).unwrap();
+            html.select(&selector)
+                .filter_map(|element| element.value().attr(attr))
+                .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+                .find(|value| !value.is_empty())
+        })
+    };
+
+    let published = find(
+        &[
+            "meta[property='article:published_time']",
+            "meta[property='og:published_time']",
+            "meta[itemprop='datePublished']",
+            "meta[name='date']",
+        ],
+        "content",
+    )
+    .or_else(|| find(&["time[datetime]"], "datetime"));
+
+    LinkPreview {
+        title: find(
+            &["meta[property='og:title']", "meta[name='twitter:title']"],
+            "content",
+        ),
+        description: find(
+            &[
+                "meta[property='og:description']",
+                "meta[name='twitter:description']",
+                "meta[name='description']",
+            ],
+            "content",
+        ),
+        image: find(
+            &[
+                "meta[property='og:image']",
+                "meta[property='og:image:url']",
+                "meta[name='twitter:image']",
+            ],
+            "content",
+        )
+        .and_then(|href| parse_favicon_url_from_base(page_url, &href)),
+        // only the date of timestamps like 2023-02-14T09:30:00+00:00
+        published: published.and_then(|published| algolia::parse_date(published.get(..10)?)),
+    }
+}
+
+/// Orders candidates from the one that fits `size` pixels the best, equally
+/// good ones keep the order of the page.
+fn sort_icon_candidates(candidates: &mut [IconCandidate], size: u32) {
+    candidates.sort_by_key(|candidate| candidate.penalty(size));
+}
+
+/// Fetches favicon again unless it did not change since it was fetched with
+/// given `ETag` and `Last-Modified` headers, then it is `None`.
+pub(crate) fn revalidate_favicon(
     ctx: egui::Context,
+    scheduler: &Scheduler,
     url: &str,
-    sender: Sender<Result<RetainedImage, String>>,
-    or_else: T,
-) where
-    T: FnOnce(egui::Context, &str, Sender<Result<RetainedImage, String>>) + Send + 'static,
-{
-    let original_url = url.to_string();
-    let request = ehttp::Request::get(url);
-    ehttp::fetch(request, move |response| {
-        if let Ok(response) = response {
-            let content_type = response.content_type().unwrap_or_default();
-            let image_result = if content_type.starts_with("image/svg") {
-                RetainedImage::from_svg_bytes(&response.url, &response.bytes)
-            } else if content_type.starts_with("image/") {
-                RetainedImage::from_image_bytes(&response.url, &response.bytes)
-            } else {
-                Err("Invalid content type".to_string())
-            };
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Promise<error::Result<Option<FetchedFavicon>>> {
+    let (sender, promise) = Promise::new();
 
-            match image_result {
-                Ok(image) => {
-                    ctx.request_repaint(); // wake up UI thread, we have icon to re-render
-                    sender.send(Ok(image));
-                    return;
-                }
-                Err(error) => {
-                    warn!(
-                        "Could not read image: {} (content-type {}) from url {}",
-                        error, content_type, response.url
-                    );
-                }
+    let mut request = Request::get(url);
+    if let Some(etag) = etag {
+        request
+            .headers
+            .insert("If-None-Match".to_string(), etag.to_string());
+    }
+    if let Some(last_modified) = last_modified {
+        request
+            .headers
+            .insert("If-Modified-Since".to_string(), last_modified.to_string());
+    }
+
+    scheduler.fetch(request, Priority::Favicon, move |response| {
+        let result = response.and_then(|response| {
+            if response.status == 304 {
+                return Ok(None);
             }
-        }
 
-        or_else(ctx, &original_url, sender);
+            let image = decode_image(&response)?;
+            Ok(Some(FetchedFavicon { image, response }))
+        });
+
+        ctx.request_repaint(); // wake up UI thread, we have icon to re-render
+        sender.send(result);
     });
+
+    promise
+}
+
+/// Article of story read in app.
+pub(crate) fn article(
+    ctx: egui::Context,
+    scheduler: &Scheduler,
+    url: &str,
+) -> Promise<error::Result<Article>> {
+    let (sender, promise) = Promise::new();
+
+    scheduler.fetch(Request::get(url), Priority::Visible, move |response| {
+        let result = response.and_then(|response| {
+            let html = response
+                .text()
+                .ok_or_else(|| Error::Decode("Page is not text".to_string()))?;
+            reader::extract(html, &response.url)
+                .ok_or_else(|| Error::Decode("Page has no article".to_string()))
+        });
+
+        ctx.request_repaint(); // wake up UI thread
+        sender.send(result);
+    });
+
+    promise
+}
+
+/// Image of article or thumbnail of story.
+pub(crate) fn image(
+    ctx: egui::Context,
+    scheduler: &Scheduler,
+    url: &str,
+    priority: Priority,
+) -> Promise<error::Result<RetainedImage>> {
+    let (sender, promise) = Promise::new();
+
+    scheduler.fetch(Request::get(url), priority, move |response| {
+        let result = response.and_then(|response| decode_image(&response));
+        ctx.request_repaint(); // wake up UI thread, we have image to render
+        sender.send(result);
+    });
+
+    promise
+}
+
+/// Decodes image based on its content type.
+pub(crate) fn decode_image(response: &Response) -> error::Result<RetainedImage> {
+    let content_type = response.content_type().unwrap_or_default();
+    let image_result = if content_type.starts_with("image/svg") {
+        RetainedImage::from_svg_bytes(&response.url, &response.bytes)
+    } else if content_type.starts_with("image/") {
+        RetainedImage::from_image_bytes(&response.url, &response.bytes)
+    } else {
+        Err("Invalid content type".to_string())
+    };
+
+    image_result.map_err(|error| {
+        Error::Decode(format!(
+            "Could not read image: {} (content-type {}) from url {}",
+            error, content_type, response.url
+        ))
+    })
 }
 
 fn get_root_favicon_url(url: &str) -> Option<String> {
@@ -166,12 +520,12 @@ fn parse_favicon_url_from_base(base_url: &str, href: &str) -> Option<Url> {
     }
 
     let mut base_url = base_url.to_string();
-    if base_url.ends_with("/") {
+    if base_url.ends_with('/') {
         base_url.push_str("index.html");
     }
 
     if let Ok(base_url) = Url::parse(&base_url) {
-        return Url::options().base_url(Some(&base_url)).parse(&href).ok();
+        return Url::options().base_url(Some(&base_url)).parse(href).ok();
     }
 
     None
@@ -179,7 +533,248 @@ fn parse_favicon_url_from_base(base_url: &str, href: &str) -> Option<Url> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
     use super::*;
+    use crate::{
+        test_server::{Response, TestServer},
+        HnItemId,
+    };
+
+    #[test]
+    fn retries_transient_errors() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let requests = requests.clone();
+            TestServer::start(move |request| {
+                let attempt = requests.fetch_add(1, Ordering::SeqCst);
+                match request.path.as_str() {
+                    "/flaky" if attempt < 2 => Response::new(503, "text/plain", "busy"),
+                    "/flaky" => Response::json("[1]"),
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let send = |path: &str| {
+            let (sender, promise) = Promise::new();
+            let request = Retry {
+                ctx: egui::Context::default(),
+                scheduler: Scheduler::new(1),
+                priority: Priority::Visible,
+                url: server.url(path),
+                backoff: Backoff::new(Duration::from_millis(1), Duration::from_millis(1)),
+                attempts: ATTEMPTS,
+            };
+            request.send(Duration::ZERO, sender);
+            promise.block_and_take()
+        };
+
+        assert_eq!(send("/flaky"), Ok(vec![HnItemId(1)]));
+        assert_eq!(requests.swap(0, Ordering::SeqCst), 3);
+
+        // missing resource does not appear by asking again
+        assert!(matches!(
+            send("/missing"),
+            Err(Error::HttpStatus { status: 404, .. })
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn parses_icon_sizes() {
+        assert_eq!(parse_icon_sizes("16x16"), (vec![16], false));
+        assert_eq!(
+            parse_icon_sizes("16x16 32X32  48x24"),
+            (vec![16, 32, 48], false)
+        );
+        assert_eq!(parse_icon_sizes("any"), (vec![], true));
+        assert_eq!(parse_icon_sizes("32 large"), (vec![], false));
+    }
+
+    #[test]
+    fn picks_icons_closest_to_displayed_size() {
+        let sized = r#"
+            <html><head>
+            <link rel="icon" type="image/png" sizes="16x16" href="/favicon-16x16.png">
+            <link rel="icon" type="image/png" sizes="32x32" href="/favicon-32x32.png">
+            <link rel="apple-touch-icon" sizes="180x180" href="/apple-touch-icon.png">
+            <link rel="manifest" href="/site.webmanifest">
+            </head></html>"#;
+        let svg = r#"
+            <html><head>
+            <link rel="alternate icon" class="js-site-favicon" type="image/png" href="https://github.githubassets.com/favicons/favicon.png">
+            <link rel="icon" class="js-site-favicon" type="image/svg+xml" href="https://github.githubassets.com/favicons/favicon.svg">
+            </head></html>"#;
+        let apple_only = r#"
+            <html><head>
+            <link rel="shortcut icon" href="https://cdn.sstatic.net/Sites/stackoverflow/Img/favicon.ico?v=ec617d715196">
+            <link rel="apple-touch-icon" href="https://cdn.sstatic.net/Sites/stackoverflow/Img/apple-touch-icon.png?v=c78bd457575a">
+            </head></html>"#;
+
+        let items: &[(&str, &str, u32, &str)] = &[
+            ("https://www.bbc.com/future/article/", sized, 18, "https://www.bbc.com/favicon-16x16.png"),
+            // the same page on HiDPI screen
+            ("https://www.bbc.com/future/article/", sized, 36, "https://www.bbc.com/favicon-32x32.png"),
+            ("https://www.bbc.com/future/article/", sized, 120, "https://www.bbc.com/apple-touch-icon.png"),
+            ("https://github.com/dfloer/SC2k-docs", svg, 18, "https://github.githubassets.com/favicons/favicon.svg"),
+            ("https://stackoverflow.com/questions/1", apple_only, 18, "https://cdn.sstatic.net/Sites/stackoverflow/Img/favicon.ico?v=ec617d715196"),
+            ("https://stackoverflow.com/questions/1", apple_only, 72, "https://cdn.sstatic.net/Sites/stackoverflow/Img/apple-touch-icon.png?v=c78bd457575a"),
+        ];
+
+        for (page_url, html, size, icon_url) in items {
+            let (mut icons, _) = parse_icon_links(page_url, html);
+            sort_icon_candidates(&mut icons, *size);
+            assert_eq!(
+                icons[0].url.as_str(),
+                *icon_url,
+                "{} at {}px",
+                page_url,
+                size
+            );
+        }
+
+        let (_, manifest_url) = parse_icon_links("https://www.bbc.com/future/article/", sized);
+        assert_eq!(
+            manifest_url.map(String::from).as_deref(),
+            Some("https://www.bbc.com/site.webmanifest")
+        );
+    }
+
+    #[test]
+    fn parses_manifest_icons() {
+        let manifest = br#"{
+            "name": "Example",
+            "icons": [
+                { "src": "icons/icon-192.png", "sizes": "192x192", "type": "image/png" },
+                { "src": "icons/mask.svg", "type": "image/svg+xml", "purpose": "monochrome" },
+                { "src": "/icons/icon-48.png", "sizes": "48x48" }
+            ]
+        }"#;
+
+        let icons = parse_manifest_icons("https://example.com/app/manifest.json", manifest);
+        assert_eq!(
+            icons,
+            [
+                IconCandidate {
+                    url: "https://example.com/app/icons/icon-192.png"
+                        .parse()
+                        .unwrap(),
+                    sizes: vec![192],
+                    scalable: false,
+                },
+                IconCandidate {
+                    url: "https://example.com/icons/icon-48.png".parse().unwrap(),
+                    sizes: vec![48],
+                    scalable: false,
+                },
+            ]
+        );
+
+        assert!(parse_manifest_icons("https://example.com/manifest.json", b"<html>").is_empty());
+    }
+
+    #[test]
+    fn follows_manifest_to_icon() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/story.html" => Response::html(r#"<link rel="manifest" href="/manifest.json">"#),
+            "/manifest.json" => {
+                Response::json(r#"{"icons":[{"src":"/icon.svg","type":"image/svg+xml"}]}"#)
+            }
+            "/icon.svg" => Response::new(
+                200,
+                "image/svg+xml",
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="8" height="8"/>"#,
+            ),
+            _ => Response::not_found(),
+        });
+
+        let scheduler = Scheduler::new(1);
+        let discover = |path| {
+            let url = server.url(path);
+            discover_favicon(&scheduler, &get_blocking(&scheduler, &url), &url, 18)
+        };
+
+        let favicon = discover("/story.html");
+        assert_eq!(favicon.unwrap().response.url, server.url("/icon.svg"));
+
+        let missing = discover("/missing.html");
+        assert!(matches!(
+            missing,
+            Err(Error::HttpStatus { status: 404, .. })
+        ));
+    }
+
+    #[test]
+    fn tries_each_icon_once() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let server = {
+            let requests = requests.clone();
+            TestServer::start(move |request| {
+                requests.lock().unwrap().push(request.path.clone());
+                match request.path.as_str() {
+                    "/story.html" => Response::html(
+                        r#"<link rel="icon" href="/favicon.ico"><link rel="apple-touch-icon" href="/touch.png">"#,
+                    ),
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let scheduler = Scheduler::new(1);
+        let url = server.url("/story.html");
+        let page = get_blocking(&scheduler, &url);
+        assert!(discover_favicon(&scheduler, &page, &url, 18).is_err());
+
+        let favicon_requests = requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|path| *path == "/favicon.ico")
+            .count();
+        assert_eq!(favicon_requests, 1);
+    }
+
+    #[test]
+    fn parses_link_previews() {
+        let html = r#"
+            <html><head>
+            <title>Shrinkflation | ounapuu.ee</title>
+            <meta property="og:title" content="Shrinkflation">
+            <meta name="description" content="Generic description of the site">
+            <meta property="og:description" content="  Products get smaller
+                while prices stay the same. ">
+            <meta property="og:image" content="/media/shrinkflation.jpg">
+            <meta property="article:published_time" content="2023-02-15T08:00:00+02:00">
+            </head></html>"#;
+
+        assert_eq!(
+            parse_link_preview("https://ounapuu.ee/posts/2023/02/15/shrinkflation/", html),
+            LinkPreview {
+                title: Some("Shrinkflation".to_string()),
+                description: Some("Products get smaller while prices stay the same.".to_string()),
+                image: Some(
+                    "https://ounapuu.ee/media/shrinkflation.jpg"
+                        .parse()
+                        .unwrap()
+                ),
+                published: Date::from_calendar_date(2023, time::Month::February, 15).ok(),
+            }
+        );
+
+        let html = r#"<meta name="description" content="Plain page"><time datetime="2021-06-01">"#;
+        assert_eq!(
+            parse_link_preview("https://example.com/", html),
+            LinkPreview {
+                description: Some("Plain page".to_string()),
+                published: Date::from_calendar_date(2021, time::Month::June, 1).ok(),
+                ..Default::default()
+            }
+        );
+    }
 
     #[test]
     fn parses_favicon_hrefs() {
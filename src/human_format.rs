@@ -1,8 +1,8 @@
-use time::OffsetDateTime;
+use time::{Date, OffsetDateTime};
 use url::Url;
 
 pub(crate) fn date_time(date_time: &OffsetDateTime) -> String {
-    let duration = OffsetDateTime::now_utc() - date_time.clone();
+    let duration = OffsetDateTime::now_utc() - *date_time;
 
     if duration.whole_minutes() < 60 {
         if duration.whole_minutes() == 1 {
@@ -16,15 +16,36 @@ pub(crate) fn date_time(date_time: &OffsetDateTime) -> String {
         } else {
             format!("{} hours ago", duration.whole_hours())
         }
-    } else {
+    } else if duration.whole_days() < 30 {
         if duration.whole_days() == 1 {
             "1 day ago".to_string()
         } else {
             format!("{} days ago", duration.whole_days())
         }
+    } else if duration.whole_days() < 365 {
+        if duration.whole_days() / 30 == 1 {
+            "1 month ago".to_string()
+        } else {
+            format!("{} months ago", duration.whole_days() / 30)
+        }
+    } else if duration.whole_days() / 365 == 1 {
+        "1 year ago".to_string()
+    } else {
+        format!("{} years ago", duration.whole_days() / 365)
     }
 }
 
+/// Day like `Tuesday, February 14, 2023`.
+pub(crate) fn date(date: Date) -> String {
+    format!(
+        "{}, {} {}, {}",
+        date.weekday(),
+        date.month(),
+        date.day(),
+        date.year()
+    )
+}
+
 pub(crate) fn points(points: usize) -> Option<String> {
     match points {
         0 => None,
@@ -46,3 +67,21 @@ pub(crate) fn comment_count(count: usize) -> String {
         n => format!("{} comments", n),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn formats_old_dates_in_months_and_years() {
+        let ago = |days| date_time(&(OffsetDateTime::now_utc() - Duration::days(days)));
+
+        assert_eq!(ago(29), "29 days ago");
+        assert_eq!(ago(30), "1 month ago");
+        assert_eq!(ago(95), "3 months ago");
+        assert_eq!(ago(365), "1 year ago");
+        assert_eq!(ago(800), "2 years ago");
+    }
+}
@@ -0,0 +1,554 @@
+use std::collections::{hash_map::Entry, HashSet};
+
+use eframe::{egui, epaint::ahash::HashMap};
+use poll_promise::{Promise, Sender};
+use time::OffsetDateTime;
+use tracing::warn;
+
+use crate::{
+    algolia::{Algolia, ThreadItem},
+    backend::Backend,
+    error::Result,
+    scheduler::{self, Priority},
+    store::{self, Store, Stored},
+    HnItem, HnItemId,
+};
+
+/// Items that are loaded or being loaded, backed by the on-disk store.
+/// Stored copy is used right away and when it is older than its ttl it is
+/// refreshed in the background (stale-while-revalidate).
+pub(crate) struct ItemCache {
+    store: Store,
+    items: HashMap<HnItemId, Promise<Result<HnItem>>>,
+    // refetched items replace the cached ones when they arrive so the
+    // displayed data updates in place
+    refreshes: HashMap<HnItemId, Promise<Result<HnItem>>>,
+    unsaved: HashSet<HnItemId>, // requested from the api, saved once loaded
+    fetched: HashMap<HnItemId, OffsetDateTime>, // when loaded items came from the api
+    reads: HashMap<HnItemId, Read>, // stored items read in the background
+}
+
+/// Stored item being read off the ui thread, its promise is fulfilled once
+/// the read finishes.
+struct Read {
+    stored: Promise<Option<Stored<HnItem>>>,
+    sender: Sender<Result<HnItem>>,
+    priority: Priority, // of the api request when the item cannot be read
+}
+
+impl ItemCache {
+    pub(crate) fn new(store: Store) -> Self {
+        Self {
+            store,
+            items: Default::default(),
+            refreshes: Default::default(),
+            unsaved: Default::default(),
+            fetched: Default::default(),
+            reads: Default::default(),
+        }
+    }
+
+    /// Promise of item from the store or the api, the caller is expected to
+    /// `insert` it.
+    pub(crate) fn fetch(
+        &mut self,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+        id: HnItemId,
+        priority: Priority,
+    ) -> Promise<Result<HnItem>> {
+        if self.store.has_item(id) {
+            let (sender, promise) = Promise::new();
+            let store = self.store.clone();
+            let ctx = ctx.clone();
+            let stored = Promise::spawn_thread("read item", move || {
+                let stored = store.item(id);
+                ctx.request_repaint();
+                stored
+            });
+            self.reads.insert(
+                id,
+                Read {
+                    stored,
+                    sender,
+                    priority,
+                },
+            );
+            return promise;
+        }
+
+        self.unsaved.insert(id);
+        backend.item(ctx.clone(), id, priority)
+    }
+
+    /// Loads item unless it is already loaded or being loaded.
+    pub(crate) fn request(
+        &mut self,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+        id: HnItemId,
+        priority: Priority,
+    ) -> &Promise<Result<HnItem>> {
+        let promise = match self.items.remove(&id) {
+            Some(promise) => promise,
+            None => self.fetch(ctx, backend, id, priority),
+        };
+
+        self.items.entry(id).or_insert(promise)
+    }
+
+    /// Refetches item from the api in the background.
+    pub(crate) fn refresh(
+        &mut self,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+        id: HnItemId,
+        priority: Priority,
+    ) {
+        self.refreshes
+            .entry(id)
+            .or_insert_with(|| backend.item(ctx.clone(), id, priority));
+    }
+
+    /// Replaces item with newer copy that did not come through `fetch`.
+    pub(crate) fn update(&mut self, item: HnItem) {
+        self.store.save_item(&item);
+        self.fetched.insert(item.id, OffsetDateTime::now_utc());
+        self.items.insert(item.id, Promise::from_ready(Ok(item)));
+    }
+
+    /// Adds item that was loaded along with others in its thread, items that
+    /// are loaded, being loaded or stored already are kept. Filled items did
+    /// not come from the api, they are saved as stale so they are available
+    /// offline and refetched when read again. Returns whether it was added.
+    pub(crate) fn fill(&mut self, item: HnItem) -> bool {
+        if self.is_known(&item.id) {
+            return false;
+        }
+
+        self.store.save_unvalidated_item(&item);
+        self.items.insert(item.id, Promise::from_ready(Ok(item)));
+        true
+    }
+
+    /// Whether item is loaded, being loaded or stored.
+    pub(crate) fn is_known(&self, id: &HnItemId) -> bool {
+        self.items.contains_key(id) || self.store.has_item(*id)
+    }
+
+    pub(crate) fn insert(&mut self, id: HnItemId, promise: Promise<Result<HnItem>>) {
+        self.items.insert(id, promise);
+    }
+
+    pub(crate) fn remove(&mut self, id: &HnItemId) -> Option<Promise<Result<HnItem>>> {
+        self.items.remove(id)
+    }
+
+    pub(crate) fn get(&self, id: &HnItemId) -> Option<&Promise<Result<HnItem>>> {
+        self.items.get(id)
+    }
+
+    pub(crate) fn item(&self, id: &HnItemId) -> Option<&HnItem> {
+        self.items
+            .get(id)
+            .and_then(|promise| promise.ready())
+            .and_then(|result| result.as_ref().ok())
+    }
+
+    /// Loaded items.
+    pub(crate) fn items(&self) -> impl Iterator<Item = &HnItem> {
+        self.items
+            .values()
+            .filter_map(|promise| promise.ready())
+            .filter_map(|result| result.as_ref().ok())
+    }
+
+    /// When loaded item was fetched from the api, it is older than now when it
+    /// comes from the store.
+    pub(crate) fn fetched(&self, id: &HnItemId) -> Option<OffsetDateTime> {
+        self.fetched.get(id).copied()
+    }
+
+    pub(crate) fn is_loading(&self, id: &HnItemId) -> bool {
+        self.items
+            .get(id)
+            .map_or(false, |promise| promise.ready().is_none())
+    }
+
+    pub(crate) fn any_loading(&self) -> bool {
+        self.items.values().any(|promise| promise.ready().is_none())
+    }
+
+    /// Forgets items whose request was cancelled so they are requested again.
+    pub(crate) fn remove_cancelled(&mut self) {
+        self.items
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+        self.refreshes
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+    }
+
+    /// Forgets items that failed to load because of network errors so they
+    /// are requested again.
+    pub(crate) fn remove_failed(&mut self) {
+        self.items.retain(
+            |_, promise| !matches!(promise.ready(), Some(Err(error)) if error.is_transient()),
+        );
+    }
+
+    /// Hands out items read from the store, saves items that finished
+    /// loading and swaps in finished refreshes.
+    pub(crate) fn poll(&mut self, ctx: &egui::Context, backend: &dyn Backend) {
+        let read: Vec<_> = self
+            .reads
+            .iter()
+            .filter(|(_, read)| read.stored.ready().is_some())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in read {
+            let Some(read) = self.reads.remove(&id) else {
+                continue;
+            };
+            match read.stored.block_and_take() {
+                Some(stored) => {
+                    if stored.age() > store::item_ttl(&stored.value) {
+                        self.refresh(ctx, backend, id, Priority::Prefetch);
+                    }
+                    self.fetched.insert(id, stored.fetched);
+                    read.sender.send(Ok(stored.value));
+                }
+                None => {
+                    // the file could not be read, the api has the item too
+                    self.unsaved.insert(id);
+                    let promise = backend.item(ctx.clone(), id, read.priority);
+                    self.items.insert(id, promise);
+                }
+            }
+        }
+
+        let refreshed: Vec<_> = self
+            .refreshes
+            .iter()
+            .filter(|(_, promise)| promise.ready().is_some())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in refreshed {
+            if let Some(promise) = self.refreshes.remove(&id) {
+                match promise.block_and_take() {
+                    Ok(item) => self.update(item),
+                    Err(error) => warn!("cannot refresh item {}: {}", id, error),
+                }
+            }
+        }
+
+        let items = &self.items;
+        let store = &self.store;
+        let fetched = &mut self.fetched;
+        self.unsaved
+            .retain(|id| match items.get(id).map(|p| p.ready()) {
+                Some(Some(Ok(item))) => {
+                    store.save_item(item);
+                    fetched.insert(*id, OffsetDateTime::now_utc());
+                    false
+                }
+                Some(None) => true, // still loading
+                _ => false,
+            });
+    }
+}
+
+/// Comments of stories loaded from Algolia in one request, threads that fail
+/// to load are walked in the api.
+#[derive(Default)]
+pub(crate) struct WholeThreads {
+    threads: HashMap<HnItemId, Option<Promise<Result<ThreadItem>>>>, // taken once filled
+}
+
+impl WholeThreads {
+    /// Loads comments of story not seen before into `cache`, returns whether
+    /// they can be walked in the api.
+    pub(crate) fn load(
+        &mut self,
+        ctx: &egui::Context,
+        search: &Algolia,
+        backend: &dyn Backend,
+        cache: &mut ItemCache,
+        story: &HnItem,
+    ) -> bool {
+        let entry = match self.threads.entry(story.id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let missing = story.kids.iter().any(|kid| !cache.is_known(kid));
+                if !missing {
+                    return true;
+                }
+
+                entry.insert(Some(search.thread(ctx.clone(), story.id)))
+            }
+        };
+
+        match entry.take() {
+            Some(promise) => match promise.try_take() {
+                Ok(Ok(thread)) => {
+                    for item in thread.items() {
+                        // the story keeps the ranking of its comments from the api
+                        if item.id == story.id {
+                            continue;
+                        }
+
+                        // replies are in the order of the tree, the api ranks them
+                        let (id, ranked) = (item.id, item.kids.len() < 2);
+                        if cache.fill(item) && !ranked {
+                            cache.refresh(ctx, backend, id, Priority::Thread);
+                        }
+                    }
+                    true
+                }
+                Ok(Err(error)) => {
+                    warn!("cannot load whole thread {}: {}", story.id, error);
+                    true
+                }
+                Err(promise) => {
+                    *entry = Some(promise);
+                    false
+                }
+            },
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+    use crate::{
+        backend::Firebase,
+        scheduler::Scheduler,
+        store::temp_dir,
+        test_server::{Response, TestServer},
+    };
+
+    #[test]
+    fn serves_stored_items_and_revalidates_stale_ones() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let requests = requests.clone();
+            TestServer::start(move |request| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                match request.path.as_str() {
+                    "/v0/item/1.json" => Response::json(r#"{"id":1,"title":"Fresh","time":0}"#),
+                    _ => Response::not_found(),
+                }
+            })
+        };
+
+        let ctx = egui::Context::default();
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(1));
+        let dir = temp_dir("items");
+        let store = Store::new(Some(dir.clone()));
+        let mut cache = ItemCache::new(store.clone());
+
+        // first request goes to the api and the result is saved
+        cache
+            .request(&ctx, &backend, HnItemId(1), Priority::Visible)
+            .block_until_ready();
+        cache.poll(&ctx, &backend);
+        store.flush();
+        assert_eq!(store.item(HnItemId(1)).unwrap().value.title, "Fresh");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // stored item from long time ago is shown immediately and refreshed
+        let old = HnItem {
+            id: HnItemId(1),
+            title: "Stale".to_string(),
+            time: OffsetDateTime::now_utc() - Duration::days(1000),
+            ..Default::default()
+        };
+        let stored = store::Stored {
+            fetched: OffsetDateTime::now_utc() - Duration::weeks(2),
+            value: &old,
+        };
+        std::fs::write(
+            dir.join("items/1.json"),
+            serde_json::to_vec(&stored).unwrap(),
+        )
+        .unwrap();
+
+        let mut cache = ItemCache::new(store);
+        cache.request(&ctx, &backend, HnItemId(1), Priority::Visible);
+        assert!(cache.is_loading(&HnItemId(1)));
+        for _ in 0..500 {
+            cache.poll(&ctx, &backend);
+            if !cache.is_loading(&HnItemId(1)) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(cache.item(&HnItemId(1)).unwrap().title, "Stale");
+
+        for _ in 0..500 {
+            cache.poll(&ctx, &backend);
+            if cache.item(&HnItemId(1)).unwrap().title == "Fresh" {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(cache.item(&HnItemId(1)).unwrap().title, "Fresh");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn fills_only_unknown_items() {
+        let store = Store::new(Some(temp_dir("items-fill")));
+        let stored = HnItem {
+            id: HnItemId(1),
+            text: "From api".to_string(),
+            ..Default::default()
+        };
+        store.save_item(&stored);
+        store.flush();
+
+        let mut cache = ItemCache::new(store.clone());
+        assert!(!cache.fill(HnItem {
+            text: "From thread".to_string(),
+            ..stored
+        }));
+        assert!(cache.fill(HnItem {
+            id: HnItemId(2),
+            text: "From thread".to_string(),
+            ..Default::default()
+        }));
+        store.flush();
+
+        // the stored copy is kept and revalidated as usual
+        assert!(cache.item(&HnItemId(1)).is_none());
+        assert_eq!(store.item(HnItemId(1)).unwrap().value.text, "From api");
+
+        assert_eq!(cache.item(&HnItemId(2)).unwrap().text, "From thread");
+        assert_eq!(cache.fetched(&HnItemId(2)), None);
+
+        // filled item is kept for offline reading but refetched when read
+        let filled = store.item(HnItemId(2)).unwrap();
+        assert_eq!(filled.value.text, "From thread");
+        assert_eq!(filled.fetched, OffsetDateTime::UNIX_EPOCH);
+    }
+
+    const THREAD: &str = r#"{"id":1,"created_at_i":1676368800,"type":"story","author":"pg","title":"Story","children":[
+        {"id":2,"created_at_i":1676368900,"type":"comment","author":"dang","text":"Comment","parent_id":1,"children":[
+            {"id":3,"created_at_i":1676369000,"type":"comment","author":"pg","text":"Older reply","parent_id":2},
+            {"id":4,"created_at_i":1676369100,"type":"comment","author":"pg","text":"Better reply","parent_id":2}
+        ]}
+    ]}"#;
+
+    fn load_thread(
+        threads: &mut WholeThreads,
+        search: &Algolia,
+        backend: &Firebase,
+        cache: &mut ItemCache,
+        story: &HnItem,
+    ) {
+        let ctx = egui::Context::default();
+        for _ in 0..500 {
+            if threads.load(&ctx, search, backend, cache, story) {
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("thread {} did not load", story.id);
+    }
+
+    #[test]
+    fn ranks_replies_of_whole_thread_in_api() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/v1/items/1" => Response::json(THREAD),
+            "/v0/item/2.json" => Response::json(
+                r#"{"id":2,"by":"dang","text":"Comment","parent":1,"kids":[4,3],"time":1676368900,"type":"comment"}"#,
+            ),
+            _ => Response::not_found(),
+        });
+
+        let ctx = egui::Context::default();
+        let scheduler = Scheduler::new(2);
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), scheduler.clone());
+        let search = Algolia::new(server.url("/api/v1/").parse().unwrap(), scheduler);
+        let store = Store::new(Some(temp_dir("items-thread")));
+        let mut cache = ItemCache::new(store.clone());
+        let story = HnItem {
+            id: HnItemId(1),
+            kids: vec![HnItemId(2)],
+            ..Default::default()
+        };
+
+        let mut threads = WholeThreads::default();
+        load_thread(&mut threads, &search, &backend, &mut cache, &story);
+        assert_eq!(
+            cache.item(&HnItemId(2)).unwrap().kids,
+            [HnItemId(3), HnItemId(4)]
+        );
+        assert_eq!(cache.item(&HnItemId(3)).unwrap().text, "Older reply");
+        assert!(cache.item(&HnItemId(1)).is_none());
+
+        // replies take the ranking of the api once it answers
+        for _ in 0..500 {
+            cache.poll(&ctx, &backend);
+            if cache.fetched(&HnItemId(2)).is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(
+            cache.item(&HnItemId(2)).unwrap().kids,
+            [HnItemId(4), HnItemId(3)]
+        );
+        store.flush();
+        assert_ne!(
+            store.item(HnItemId(2)).unwrap().fetched,
+            OffsetDateTime::UNIX_EPOCH
+        );
+        assert_eq!(
+            store.item(HnItemId(4)).unwrap().fetched,
+            OffsetDateTime::UNIX_EPOCH
+        );
+    }
+
+    #[test]
+    fn walks_api_when_whole_thread_fails() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/api/v1/items/1" => Response::new(500, "text/html", "<h1>Oops</h1>"),
+            "/v0/item/2.json" => Response::json(
+                r#"{"id":2,"by":"dang","text":"From api","parent":1,"time":1676368900,"type":"comment"}"#,
+            ),
+            _ => Response::not_found(),
+        });
+
+        let ctx = egui::Context::default();
+        let scheduler = Scheduler::new(2);
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), scheduler.clone());
+        let search = Algolia::new(server.url("/api/v1/").parse().unwrap(), scheduler);
+        let mut cache = ItemCache::new(Store::new(Some(temp_dir("items-walk"))));
+        let story = HnItem {
+            id: HnItemId(1),
+            kids: vec![HnItemId(2)],
+            ..Default::default()
+        };
+
+        let mut threads = WholeThreads::default();
+        load_thread(&mut threads, &search, &backend, &mut cache, &story);
+        assert!(!cache.is_known(&HnItemId(2)));
+
+        // the failed thread is not requested again, comments come from the api
+        assert!(threads.load(&ctx, &search, &backend, &mut cache, &story));
+        cache
+            .request(&ctx, &backend, HnItemId(2), Priority::Thread)
+            .block_until_ready();
+        assert_eq!(cache.item(&HnItemId(2)).unwrap().text, "From api");
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(dead_code)]
 
-use std::fmt::Display;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use eframe::{
     egui::{self, Color32, FontId, Key, KeyboardShortcut, Modifiers, RichText, TextStyle},
@@ -9,14 +14,52 @@ use eframe::{
 };
 use egui_extras::RetainedImage;
 use poll_promise::Promise;
-use serde::Deserialize;
-use time::OffsetDateTime;
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
 use tracing::warn;
 use url::Url;
 
+use crate::{
+    account::Account,
+    algolia::{Algolia, SearchQuery, SearchResults, SearchSort, SearchTags},
+    backend::{Backend, Firebase},
+    error::Error,
+    favicons::Favicons,
+    feeds::{FeedSource, Feeds},
+    items::{ItemCache, WholeThreads},
+    privacy::{Privacy, PrivacySettings},
+    reader::{Article, Block},
+    scheduler::{Priority, Scheduler},
+    scrape::Site,
+    sse::Watch,
+    store::{Store, FEED_TTL},
+    submit::{Submission, SubmissionKind},
+    sync::{SyncConfig, ThreadSync},
+    updates::LiveUpdates,
+};
+
+mod account;
+mod algolia;
+mod backend;
+mod backoff;
 mod comment_parser;
+mod error;
+mod favicons;
+mod feeds;
 mod fetch;
 mod human_format;
+mod items;
+mod privacy;
+mod reader;
+mod scheduler;
+mod scrape;
+mod sse;
+mod store;
+mod submit;
+mod sync;
+#[cfg(test)]
+mod test_server;
+mod updates;
 mod widgets;
 
 pub const DEBUG_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::F12);
@@ -25,13 +68,21 @@ pub const GO_BACK_FROM_COMMENTS: KeyboardShortcut =
     KeyboardShortcut::new(Modifiers::NONE, Key::Backspace);
 pub const GO_BACK: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowLeft);
 pub const GO_NEXT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowRight);
-pub const TAB_TOP: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Num1);
-pub const TAB_NEW: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Num2);
-pub const TAB_SHOW: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Num3);
-pub const TAB_ASK: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Num4);
-pub const TAB_JOBS: KeyboardShortcut = KeyboardShortcut::new(Modifiers::ALT, Key::Num5);
+pub const SEARCH_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::F);
+
+const SUBMIT_URL_ID: &str = "submit_url"; // earlier submissions are looked up once it loses focus
+
+// levels of comments loaded when story is opened, deeper ones are loaded
+// when user asks for them
+const THREAD_DEPTH: usize = 3;
+
+// replies of comment loaded at first, the top-level comments are all loaded
+const REPLIES_SHOWN: usize = 10;
 
-#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+// how often to check whether the api is reachable again while offline
+const OFFLINE_PROBE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
 struct HnItemId(usize);
 
 impl Display for HnItemId {
@@ -40,7 +91,7 @@ impl Display for HnItemId {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(default)]
 struct HnItem {
     id: HnItemId,
@@ -83,14 +134,58 @@ impl Default for HnItem {
     }
 }
 
-#[derive(Default, Clone, Copy, Debug, PartialEq)]
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+struct HnUser {
+    id: String,
+    #[serde(with = "time::serde::timestamp")]
+    created: OffsetDateTime,
+    karma: i64,
+    about: String,
+    submitted: Vec<HnItemId>,
+}
+
+impl Default for HnUser {
+    fn default() -> Self {
+        Self {
+            id: Default::default(),
+            created: OffsetDateTime::now_utc(),
+            karma: Default::default(),
+            about: Default::default(),
+            submitted: Default::default(),
+        }
+    }
+}
+
+/// Views opened on top of the page, only the last one is displayed and
+/// going back returns to the previous one.
+#[derive(Clone, Debug, PartialEq)]
+enum View {
+    Comments(HnItemId),
+    Article(HnItemId), // story read in app, shares the view with its comments
+    User { id: String, page_number: usize },
+    Submit,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HnUpdates {
+    items: Vec<HnItemId>,
+    profiles: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Page {
-    #[default]
-    Top,
-    New,
-    Show,
-    Ask,
-    Jobs,
+    Feed(usize), // index into feeds
+    Front(Date), // front page as it was on the day
+    Bookmarks,
+    Search,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page::Feed(0)
+    }
 }
 
 fn configure_styles(ctx: &egui::Context) {
@@ -135,22 +230,84 @@ fn configure_visuals(ctx: &egui::Context) {
 }
 
 struct Application {
-    display_comments_for_story: Option<HnItemId>,
-
-    // items that are loaded or being loaded from api
-    item_cache: HashMap<HnItemId, Promise<ehttp::Result<HnItem>>>,
+    backend: Box<dyn Backend>,
+    site: Site, // lists that are only on the site
+    scheduler: Scheduler,
+
+    views: Vec<View>,
+    locate_thread_for_comment: Option<(HnItemId, HnItemId)>, // (comment, ancestor being resolved)
+    scroll_to_comment: Cell<Option<HnItemId>>, // comment to scroll to once the thread is loaded
+    thread_loaded: bool,
+    expanded: HashSet<HnItemId>, // comments whose replies are loaded past the limits
+    collapsed: HashSet<HnItemId>, // comments closed by user, their replies wait
+    // threads loaded from algolia in one request, `None` once they finished,
+    // comments are walked in the api after that to fill in what is missing
+    whole_threads: WholeThreads,
+    whole_threads_enabled: bool,
+
+    // items that are loaded or being loaded from disk or api
+    store: Store,
+    item_cache: ItemCache,
+    user_cache: HashMap<String, Promise<error::Result<HnUser>>>,
+    bookmarks: Vec<HnItemId>, // the newest first
+    articles: HashMap<HnItemId, Promise<error::Result<Article>>>,
+    images: HashMap<Url, Promise<error::Result<RetainedImage>>>, // images of articles
+    sync: ThreadSync,
+
+    // live updates, changed items are refetched in the background and replace
+    // the cached ones when they arrive so the displayed data updates in place
+    live_updates: LiveUpdates,
+    live_updates_enabled: bool,
+    user_refreshes: HashMap<String, Promise<error::Result<HnUser>>>,
+    page_refresh: Option<Promise<error::Result<Vec<HnItemId>>>>,
+    live_refresh: Option<Promise<error::Result<Vec<HnItemId>>>>, // story list after new items appeared
+    new_stories: Option<Vec<HnItemId>>, // changed story list, shown once user asks for it
+
+    // real-time streaming of displayed story list and opened story
+    streaming_enabled: bool,
+    stories_watch: Option<(Page, Watch)>,
+    thread_watch: Option<(HnItemId, Watch)>,
 
     // page state
+    feeds: Feeds,
     page_name: Page,    // what type of page/tab to display
     page_number: usize, // the story/article offset of given page to display
     page_size: usize,   // how many stories to display at once in page from page number offset
     page_status: RequestStatus,
+    page_fetched: Option<OffsetDateTime>, // when displayed stories came from the api
+
+    // offline mode, stored data is displayed until requests succeed again
+    offline: bool,
+    connection_probe: Option<Promise<error::Result<HnItemId>>>,
+    last_probe: Option<Instant>,
+
+    // logged in user, the login window asks for their password
+    account: Account,
+    show_login_window: bool,
+    login_username: String,
+    login_password: String,
+    drafts: RefCell<HashMap<HnItemId, String>>, // replies being written by their parent
+    drafts_changed: Cell<bool>,                 // drafts are saved after the frame
+    submission: Submission,
+    duplicates: Option<(Url, Promise<error::Result<SearchResults>>)>, // earlier submissions of url
+
+    // search page state
+    search: Algolia,
+    search_query: SearchQuery,
+    search_pages: Vec<Promise<error::Result<SearchResults>>>, // result pages loaded so far
+    focus_search: bool,
 
     // icons
-    favicons: HashMap<Url, Promise<ehttp::Result<RetainedImage>>>,
+    favicons: Favicons,
     default_icon: RetainedImage,
     y_icon: RetainedImage,
 
+    // privacy window
+    show_privacy_window: bool,
+    allow_input: String, // domain being added to allowed ones
+    deny_input: String,
+    show_previews: bool, // copy of the privacy setting
+
     // debug
     render_html: bool,
     show_debug_window: bool,
@@ -158,10 +315,33 @@ struct Application {
 }
 
 impl Application {
-    fn new(cc: &CreationContext) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        cc: &CreationContext,
+        scheduler: Scheduler,
+        backend: Box<dyn Backend>,
+        site: Site,
+        search: Algolia,
+        account: Account,
+        feeds: Feeds,
+        store: Store,
+        sync_config: SyncConfig,
+    ) -> Self {
         configure_visuals(&cc.egui_ctx);
         configure_styles(&cc.egui_ctx);
 
+        if let Some(settings) = store.privacy_settings() {
+            scheduler.privacy().set_settings(settings);
+        }
+        let show_previews = scheduler.privacy().settings().previews;
+
+        // feeds added by user are trusted like the api
+        for feed in feeds.iter() {
+            if let FeedSource::Url(url) = &feed.source {
+                scheduler.privacy().add_first_party(url.as_str());
+            }
+        }
+
         let default_icon = RetainedImage::from_image_bytes(
             "default_icon",
             include_bytes!(concat!(
@@ -177,22 +357,212 @@ impl Application {
         )
         .unwrap();
 
-        Self {
-            display_comments_for_story: None,
-            item_cache: Default::default(),
+        let mut app = Self {
+            favicons: Favicons::new(scheduler.clone(), store.clone()),
+            backend,
+            site,
+            scheduler,
+            views: Vec::new(),
+            locate_thread_for_comment: None,
+            scroll_to_comment: Default::default(),
+            thread_loaded: false,
+            expanded: Default::default(),
+            collapsed: Default::default(),
+            whole_threads: Default::default(),
+            whole_threads_enabled: true,
+            item_cache: ItemCache::new(store.clone()),
+            bookmarks: store.bookmarks(),
+            drafts: RefCell::new(store.drafts()),
+            drafts_changed: Cell::new(false),
+            submission: Default::default(),
+            duplicates: None,
+            sync: ThreadSync::new(store.clone(), sync_config),
+            store,
+            articles: Default::default(),
+            images: Default::default(),
+            user_cache: Default::default(),
+            live_updates: LiveUpdates::new(Duration::from_secs(30)),
+            live_updates_enabled: true,
+            user_refreshes: Default::default(),
+            page_refresh: None,
+            live_refresh: None,
+            new_stories: None,
+            streaming_enabled: true,
+            stories_watch: None,
+            thread_watch: None,
+            feeds,
             page_name: Default::default(),
             page_number: 0,
             page_size: 15,
-            page_status: RequestStatus::Loading(fetch::page_stories(
-                Default::default(),
-                cc.egui_ctx.clone(),
-            )),
-            default_icon: default_icon,
-            y_icon: y_icon,
+            page_status: Default::default(),
+            page_fetched: None,
+            offline: false,
+            connection_probe: None,
+            last_probe: None,
+            account,
+            show_login_window: false,
+            login_username: String::new(),
+            login_password: String::new(),
+            search,
+            search_query: Default::default(),
+            search_pages: Vec::new(),
+            focus_search: false,
+            default_icon,
+            y_icon,
+            show_privacy_window: false,
+            allow_input: String::new(),
+            deny_input: String::new(),
             render_html: true,
-            favicons: Default::default(),
+            show_previews,
             show_debug_window: false,
             text_input: String::new(),
+        };
+
+        app.load_page(&cc.egui_ctx);
+        app
+    }
+
+    fn feed_source(&self) -> Option<FeedSource> {
+        match self.page_name {
+            Page::Feed(index) => self.feeds.get(index).map(|feed| feed.source.clone()),
+            Page::Front(day) => Some(scrape::front_page(day)),
+            Page::Bookmarks => None, // bookmarks are kept locally
+            Page::Search => None,    // search results come from algolia
+        }
+    }
+
+    fn load_page_stories(
+        &self,
+        ctx: &egui::Context,
+        priority: Priority,
+    ) -> Option<Promise<error::Result<Vec<HnItemId>>>> {
+        self.feed_source().map(|source| match &source {
+            FeedSource::Site(path) => {
+                let (sender, promise) = Promise::new();
+                self.site.items(ctx.clone(), path, priority, move |items| {
+                    // feeds keep ids in the order of their rank
+                    sender.send(items.map(|items| items.into_iter().map(|item| item.id).collect()));
+                });
+                promise
+            }
+            _ => self.backend.stories(ctx.clone(), &source, priority),
+        })
+    }
+
+    /// Whether stories of the page fetched at `fetched` might have changed
+    /// since, front page of a day is final once the day is over on the site.
+    fn page_can_change(&self, fetched: OffsetDateTime) -> bool {
+        match self.page_name {
+            Page::Front(day) => scrape::site_date(fetched) <= day,
+            _ => true,
+        }
+    }
+
+    /// Displays stored stories of the page right away and refreshes them when
+    /// they are older than `FEED_TTL`, loads them from api when there are none.
+    fn load_page(&mut self, ctx: &egui::Context) {
+        if self.page_name == Page::Bookmarks {
+            self.page_fetched = None;
+            self.page_status = RequestStatus::Done(self.bookmarks.clone());
+            return;
+        }
+
+        let stored = self
+            .feed_source()
+            .and_then(|source| self.store.feed(&source));
+
+        match stored {
+            Some(stored) => {
+                if stored.age() > FEED_TTL && self.page_can_change(stored.fetched) {
+                    self.page_refresh = self.load_page_stories(ctx, Priority::Visible);
+                }
+                self.page_fetched = Some(stored.fetched);
+                self.page_status = RequestStatus::Done(stored.value);
+            }
+            None => {
+                self.page_fetched = None;
+                self.page_status = match self.load_page_stories(ctx, Priority::Visible) {
+                    Some(promise) => RequestStatus::Loading(promise),
+                    None => RequestStatus::Done(Vec::new()),
+                };
+            }
+        }
+    }
+
+    /// Stories of the page that failed to load, stored ones when the api is
+    /// unreachable.
+    fn failed_page(&mut self, error: &Error) -> RequestStatus {
+        let stored = self
+            .feed_source()
+            .filter(|_| error.is_transient())
+            .and_then(|source| self.store.feed(&source));
+
+        match stored {
+            Some(stored) => {
+                self.page_fetched = Some(stored.fetched);
+                RequestStatus::Done(stored.value)
+            }
+            None => RequestStatus::Error(error.clone()),
+        }
+    }
+
+    fn save_page(&mut self, story_ids: &[HnItemId]) {
+        if let Some(source) = self.feed_source() {
+            self.store.save_feed(&source, story_ids);
+        }
+        self.page_fetched = Some(OffsetDateTime::now_utc());
+    }
+
+    /// Probes the api while offline and once it responds again loads what
+    /// failed in the meantime.
+    fn check_connection(&mut self, ctx: &egui::Context) {
+        let offline = self.scheduler.is_offline();
+
+        if offline {
+            let probing = self
+                .connection_probe
+                .as_ref()
+                .map_or(false, |probe| probe.ready().is_none());
+            let due = self.last_probe.map_or(true, |last_probe| {
+                last_probe.elapsed() >= OFFLINE_PROBE_INTERVAL
+            });
+
+            if !probing && due {
+                self.last_probe = Some(Instant::now());
+                self.connection_probe = Some(self.backend.max_item(ctx.clone()));
+            }
+
+            // wake up for the next probe even if user does not interact with the app
+            ctx.request_repaint_after(OFFLINE_PROBE_INTERVAL);
+        } else if self.offline {
+            self.connection_probe = None;
+            self.item_cache.remove_failed();
+            self.user_cache.retain(
+                |_, promise| !matches!(promise.ready(), Some(Err(error)) if error.is_transient()),
+            );
+
+            match self.page_status {
+                RequestStatus::Error(_) => self.load_page(ctx),
+                RequestStatus::Done(_) if self.page_refresh.is_none() => {
+                    let can_change = self
+                        .page_fetched
+                        .map_or(true, |fetched| self.page_can_change(fetched));
+                    if can_change {
+                        self.page_refresh = self.load_page_stories(ctx, Priority::Prefetch);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.offline = offline;
+    }
+
+    /// When the displayed data was fetched from the api.
+    fn displayed_data_fetched(&self) -> Option<OffsetDateTime> {
+        match self.displayed_story() {
+            Some(story_id) => self.item_cache.fetched(&story_id),
+            None => self.page_fetched,
         }
     }
 
@@ -202,146 +572,1167 @@ impl Application {
         } else {
             ui.label(text);
         }
-    }
+    }
+
+    fn load_missing_icons(&mut self, ctx: &egui::Context) {
+        let item_urls = self
+            .item_cache
+            .items()
+            .filter_map(|item| Some((item.id, item.url.clone()?)));
+
+        let search_urls = self
+            .search_pages
+            .iter()
+            .filter_map(|promise| promise.ready())
+            .filter_map(|result| result.as_ref().ok())
+            .flat_map(|results| &results.hits)
+            .filter_map(|hit| Some((hit.id(), Url::parse(hit.url.as_deref()?).ok()?)));
+
+        for (id, url) in item_urls.chain(search_urls) {
+            self.favicons.load(ctx, id, &url, self.show_previews);
+        }
+    }
+
+    fn get_favicon_or_default(&self, url: &Url) -> &RetainedImage {
+        self.favicons.get(url).unwrap_or(&self.default_icon)
+    }
+
+    fn render_story(
+        &self,
+        story: &HnItem,
+        ui: &mut egui::Ui,
+        show_text: bool,
+        can_open_comments: bool,
+    ) -> Option<widgets::Action> {
+        widgets::story(
+            story,
+            ui,
+            show_text,
+            can_open_comments,
+            self.render_html,
+            self.bookmarks.contains(&story.id),
+            story.url.as_ref().map(|url| {
+                let preview = self
+                    .show_previews
+                    .then(|| self.favicons.preview(story.id))
+                    .flatten();
+
+                widgets::Site {
+                    favicon: self.get_favicon_or_default(url),
+                    preview,
+                    thumbnail: preview
+                        .and_then(|preview| preview.image.as_ref())
+                        .and_then(|image| self.favicons.thumbnail(image)),
+                }
+            }),
+            self.account.state(story.id),
+        )
+    }
+
+    fn render_comment(&self, comment_id: HnItemId, ui: &mut egui::Ui) -> Option<widgets::Action> {
+        let promise = self.item_cache.get(&comment_id)?;
+
+        match promise.ready()? {
+            Ok(comment) => {
+                let more_replies = comment
+                    .kids
+                    .iter()
+                    .filter(|kid| self.item_cache.get(kid).is_none())
+                    .count();
+
+                let response = widgets::comment(
+                    comment,
+                    ui,
+                    self.render_html,
+                    self.account.state(comment.id),
+                    more_replies,
+                    |ui| self.render_reply_box(comment.id, ui),
+                    |child_id, ui| self.render_comment(child_id, ui),
+                );
+
+                // scroll only when all comments are loaded so the position does not shift
+                if self.thread_loaded && self.scroll_to_comment.get() == Some(comment.id) {
+                    response.response.scroll_to_me(Some(egui::Align::TOP));
+                    self.scroll_to_comment.set(None);
+                }
+
+                response.inner
+            }
+            // comments that no longer exist are left out like deleted ones
+            Err(Error::NullItem) => None,
+            Err(error) => widgets::item_error(comment_id, error, ui),
+        }
+    }
+
+    /// Reply box under story or comment when user is logged in and there is
+    /// draft of the reply.
+    fn render_reply_box(&self, parent: HnItemId, ui: &mut egui::Ui) -> Option<widgets::Action> {
+        self.account.username()?;
+
+        let mut drafts = self.drafts.borrow_mut();
+        let draft = drafts.get_mut(&parent)?;
+
+        let response = widgets::reply_box(
+            parent,
+            draft,
+            self.account.is_replying(parent),
+            self.account.reply_error(parent),
+            ui,
+        );
+        if response.response.changed() {
+            self.drafts_changed.set(true);
+        }
+
+        response.inner
+    }
+
+    fn load_missing_article(&mut self, ctx: &egui::Context) {
+        let Some(View::Article(story_id)) = self.views.last() else {
+            return;
+        };
+        let Some(url) = self.get_item(story_id).and_then(|story| story.url.clone()) else {
+            return;
+        };
+
+        let promise = self
+            .articles
+            .entry(*story_id)
+            .or_insert_with(|| fetch::article(ctx.clone(), &self.scheduler, url.as_str()));
+
+        if let Some(Ok(article)) = promise.ready() {
+            for block in &article.blocks {
+                if let Block::Image { url, .. } = block {
+                    self.images.entry(url.clone()).or_insert_with(|| {
+                        fetch::image(ctx.clone(), &self.scheduler, url.as_str(), Priority::Image)
+                    });
+                }
+            }
+        }
+    }
+
+    fn render_article(&self, story: &HnItem, ui: &mut egui::Ui) -> Option<widgets::Action> {
+        match self.articles.get(&story.id).and_then(|p| p.ready()) {
+            Some(Ok(article)) => {
+                widgets::article(article, ui, |url| {
+                    self.images
+                        .get(url)
+                        .and_then(|p| p.ready())
+                        .and_then(|result| result.as_ref().ok())
+                });
+                None
+            }
+            Some(Err(error)) => {
+                ui.vertical_centered(|ui| {
+                    ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                    if let Some(url) = &story.url {
+                        ui.hyperlink_to("Open in browser", url);
+                    }
+                    ui.button("Retry")
+                        .clicked()
+                        .then_some(widgets::Action::Retry(story.id))
+                })
+                .inner
+            }
+            None => {
+                ui.vertical_centered(|ui| ui.spinner());
+                None
+            }
+        }
+    }
+
+    fn displayed_story(&self) -> Option<HnItemId> {
+        match self.views.last() {
+            Some(View::Comments(story_id) | View::Article(story_id)) => Some(*story_id),
+            _ => None,
+        }
+    }
+
+    fn open_view(&mut self, view: View) {
+        if self.views.last() != Some(&view) {
+            self.views.push(view);
+        }
+    }
+
+    /// Opens comments or article of story, switches between them when the
+    /// story is already displayed.
+    fn open_story_view(&mut self, view: View) {
+        let displayed = self.displayed_story();
+        match (self.views.last_mut(), &view) {
+            (Some(last), View::Comments(id) | View::Article(id)) if displayed == Some(*id) => {
+                *last = view;
+            }
+            _ => self.open_view(view),
+        }
+    }
+
+    fn handle_action(&mut self, ctx: &egui::Context, action: widgets::Action) {
+        match action {
+            widgets::Action::OpenComments(item_id) => {
+                // comments are displayed in the thread of their story
+                match self.get_item(&item_id) {
+                    Some(item) if item.r#type == "comment" => {
+                        self.locate_thread_for_comment = Some((item_id, item.parent));
+                    }
+                    _ => self.open_story_view(View::Comments(item_id)),
+                }
+            }
+            widgets::Action::OpenArticle(item_id) => {
+                self.open_story_view(View::Article(item_id));
+            }
+            widgets::Action::ToggleBookmark(item_id) => {
+                match self.bookmarks.iter().position(|id| *id == item_id) {
+                    Some(index) => {
+                        self.bookmarks.remove(index);
+                    }
+                    None => self.bookmarks.insert(0, item_id),
+                }
+                self.store.save_bookmarks(&self.bookmarks);
+
+                if self.page_name == Page::Bookmarks {
+                    self.page_status = RequestStatus::Done(self.bookmarks.clone());
+                }
+            }
+            widgets::Action::Retry(item_id) => {
+                // missing items are requested again by the load_missing_* functions
+                self.item_cache.remove(&item_id);
+                self.articles.remove(&item_id);
+            }
+            widgets::Action::Account(item_id, action) => {
+                self.account.perform(ctx, item_id, action);
+            }
+            widgets::Action::Reply(parent) => {
+                self.drafts.get_mut().entry(parent).or_default();
+            }
+            widgets::Action::PostReply(parent) => {
+                if let Some(draft) = self.drafts.get_mut().get(&parent) {
+                    self.account.reply(ctx, parent, draft);
+                }
+            }
+            widgets::Action::DiscardReply(parent) => {
+                self.drafts.get_mut().remove(&parent);
+                self.drafts_changed.set(true);
+            }
+            widgets::Action::Collapse(item_id, collapsed) => {
+                if collapsed {
+                    self.collapsed.insert(item_id);
+                } else {
+                    self.collapsed.remove(&item_id);
+                }
+            }
+            widgets::Action::LoadReplies(item_id) => {
+                self.expanded.insert(item_id);
+            }
+            widgets::Action::OpenUser(id) => {
+                if !matches!(self.views.last(), Some(View::User { id: user_id, .. }) if *user_id == id)
+                {
+                    self.views.push(View::User { id, page_number: 0 });
+                }
+            }
+        }
+    }
+
+    /// Walks up the parents of comment until it reaches its story and then
+    /// opens the thread scrolled to the comment.
+    fn load_thread_for_comment(&mut self, ctx: &egui::Context) {
+        let (comment_id, ancestor_id) = match self.locate_thread_for_comment {
+            Some(locate) => locate,
+            None => return,
+        };
+
+        let promise =
+            self.item_cache
+                .request(ctx, self.backend.as_ref(), ancestor_id, Priority::Visible);
+
+        match promise.ready() {
+            Some(Ok(ancestor)) if ancestor.r#type == "comment" => {
+                // replies on the way to the comment are loaded however deep it is
+                self.expanded.insert(ancestor_id);
+                self.locate_thread_for_comment = Some((comment_id, ancestor.parent));
+            }
+            Some(Ok(_)) => {
+                self.locate_thread_for_comment = None;
+                self.scroll_to_comment.set(Some(comment_id));
+                self.open_view(View::Comments(ancestor_id));
+            }
+            Some(Err(error)) => {
+                warn!("cannot find thread for comment {}: {}", comment_id, error);
+                self.locate_thread_for_comment = None;
+            }
+            None => {}
+        }
+    }
+
+    fn load_missing_user(&mut self, ctx: &egui::Context) {
+        if let Some(View::User { id, page_number }) = self.views.last() {
+            let promise = self
+                .user_cache
+                .entry(id.clone())
+                .or_insert_with(|| self.backend.user(ctx.clone(), id, Priority::Profile));
+
+            if let Some(Ok(user)) = promise.ready() {
+                for &item_id in page_of(&user.submitted, *page_number, self.page_size) {
+                    self.item_cache
+                        .request(ctx, self.backend.as_ref(), item_id, Priority::Profile);
+                }
+            }
+        }
+    }
+
+    fn render_user_page(
+        &self,
+        user_id: &str,
+        page_number: usize,
+        ui: &mut egui::Ui,
+    ) -> Option<widgets::Action> {
+        let user = match self.user_cache.get(user_id).and_then(|p| p.ready()) {
+            Some(Ok(user)) => user,
+            Some(Err(error)) => {
+                ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                return None;
+            }
+            None => return None,
+        };
+
+        let mut action = None;
+
+        ui.heading(RichText::new(&user.id).strong());
+        ui.horizontal(|ui| {
+            ui.label(format!("{} karma", user.karma));
+            ui.label("•");
+            ui.label(
+                RichText::new(format!("joined {}", human_format::date_time(&user.created))).weak(),
+            );
+        });
+
+        if !user.about.is_empty() {
+            self.render_html_text(&user.about, ui);
+        }
+
+        ui.separator();
+
+        for item_id in page_of(&user.submitted, page_number, self.page_size) {
+            let item = match self.item_cache.get(item_id).and_then(|p| p.ready()) {
+                Some(Ok(item)) if !item.deleted => item,
+                Some(Err(error)) if *error != Error::NullItem => {
+                    if let Some(retry) = widgets::item_error(*item_id, error, ui) {
+                        action = Some(retry);
+                    }
+                    ui.separator();
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let item_action = self.render_listed_item(item, ui);
+
+            if item_action.is_some() {
+                action = item_action;
+            }
+
+            ui.separator();
+        }
+
+        action
+    }
+
+    /// Story or comment in list of items, comments are shown without their
+    /// replies and link to their thread.
+    fn render_listed_item(&self, item: &HnItem, ui: &mut egui::Ui) -> Option<widgets::Action> {
+        if item.r#type != "comment" {
+            return self.render_story(item, ui, false, true);
+        }
+
+        let mut action = widgets::comment(
+            item,
+            ui,
+            self.render_html,
+            self.account.state(item.id),
+            0,
+            |_| None,
+            |_, _| None,
+        )
+        .inner;
+        if ui.link("View in thread").clicked() {
+            action = Some(widgets::Action::OpenComments(item.id));
+        }
+        action
+    }
+
+    /// Refetches loaded item and its loaded descendants, they stay displayed
+    /// until the new ones arrive.
+    fn refresh_item_with_kids(&mut self, ctx: &egui::Context, item_id: HnItemId) {
+        let mut pending = vec![item_id];
+
+        while let Some(id) = pending.pop() {
+            if let Some(item) = self.item_cache.item(&id) {
+                pending.extend(&item.kids);
+                self.item_cache
+                    .refresh(ctx, self.backend.as_ref(), id, Priority::Thread);
+            }
+        }
+    }
+
+    fn refresh(&mut self, ctx: &egui::Context) {
+        if let Some(story_id) = self.displayed_story() {
+            if matches!(self.views.last(), Some(View::Article(_))) {
+                self.articles.remove(&story_id);
+            }
+            self.refresh_item_with_kids(ctx, story_id);
+        } else if let Some(View::User { id, .. }) = self.views.last() {
+            if let Some(Ok(user)) = self.user_cache.remove(id).and_then(|p| p.try_take().ok()) {
+                for &item_id in &user.submitted {
+                    if self.item_cache.item(&item_id).is_some() {
+                        self.item_cache.refresh(
+                            ctx,
+                            self.backend.as_ref(),
+                            item_id,
+                            Priority::Profile,
+                        );
+                    }
+                }
+            }
+        } else if self.page_name == Page::Search {
+            if !self.search_pages.is_empty() {
+                self.search(ctx);
+            }
+        } else {
+            if let RequestStatus::Done(story_ids) = &self.page_status {
+                let displayed: Vec<_> = self.displayed_page_stories(story_ids).copied().collect();
+                for story_id in displayed {
+                    self.item_cache.refresh(
+                        ctx,
+                        self.backend.as_ref(),
+                        story_id,
+                        Priority::Visible,
+                    );
+                }
+            }
+
+            self.page_refresh = None;
+            self.live_refresh = None;
+            self.new_stories = None;
+            if let Some(promise) = self.load_page_stories(ctx, Priority::Visible) {
+                self.page_status = RequestStatus::Loading(promise);
+            }
+        }
+    }
+
+    /// Loads replies of item whose replies are `depth` levels below the story,
+    /// returns whether they are loaded. Replies deeper than `THREAD_DEPTH`,
+    /// past `REPLIES_SHOWN` and below collapsed comments wait until user
+    /// expands them.
+    fn load_comments(&mut self, item: &HnItem, depth: usize, ctx: &egui::Context) -> bool {
+        let Some((depth, shown)) = replies_to_load(
+            depth,
+            self.expanded.contains(&item.id),
+            self.collapsed.contains(&item.id),
+        ) else {
+            return true;
+        };
+
+        let mut loaded = true;
+
+        for (index, &kid) in item.kids.iter().enumerate() {
+            // replies loaded earlier stay loaded
+            if index >= shown && self.item_cache.get(&kid).is_none() {
+                continue;
+            }
+
+            let promise = match self.item_cache.remove(&kid) {
+                Some(promise) => promise,
+                None => self
+                    .item_cache
+                    .fetch(ctx, self.backend.as_ref(), kid, Priority::Thread),
+            };
+
+            if let Some(result) = promise.ready() {
+                match result {
+                    Ok(kid_item) => {
+                        if !self.load_comments(kid_item, depth + 1, ctx) {
+                            loaded = false;
+                        }
+                    }
+                    Err(error) => warn!("cannot load comment: {}", error),
+                }
+            } else {
+                loaded = false;
+            }
+
+            self.item_cache.insert(kid, promise);
+        }
+
+        loaded
+    }
+
+    /// Loads comments of story not seen before from algolia in one request,
+    /// returns whether they can be walked in the api.
+    fn load_whole_thread(&mut self, story: &HnItem, ctx: &egui::Context) -> bool {
+        !self.whole_threads_enabled
+            || self.whole_threads.load(
+                ctx,
+                &self.search,
+                self.backend.as_ref(),
+                &mut self.item_cache,
+                story,
+            )
+    }
+
+    fn load_missing_comments_for_opened_story(&mut self, ctx: &egui::Context) {
+        self.thread_loaded = false;
+
+        if let Some(story_id) = self.displayed_story() {
+            // stories opened from search results might not be loaded yet
+            let promise = match self.item_cache.remove(&story_id) {
+                Some(promise) => promise,
+                None => {
+                    self.item_cache
+                        .fetch(ctx, self.backend.as_ref(), story_id, Priority::Visible)
+                }
+            };
+
+            if let Some(Ok(story)) = promise.ready() {
+                if self.load_whole_thread(story, ctx) {
+                    self.thread_loaded = self.load_comments(story, 0, ctx);
+                }
+
+                for &part in &story.parts {
+                    self.item_cache
+                        .request(ctx, self.backend.as_ref(), part, Priority::Visible);
+                }
+            }
+
+            self.item_cache.insert(story_id, promise);
+        }
+    }
+
+    /// Learns what logged in user did with the opened story and its comments.
+    fn load_missing_account_state(&mut self, ctx: &egui::Context) {
+        if let Some(story_id) = self.displayed_story() {
+            self.account.load_item_page(ctx, story_id);
+        }
+    }
+
+    fn load_live_updates(&mut self, ctx: &egui::Context) {
+        // live stream pushes changes of the displayed stories, polling would
+        // fetch them again
+        let streaming = self
+            .stories_watch
+            .as_ref()
+            .map_or(false, |(_, watch)| watch.is_live());
+        let changes = if self.live_updates_enabled && !streaming {
+            self.live_updates.poll(ctx, self.backend.as_ref())
+        } else {
+            None
+        };
+
+        if let Some(changes) = changes {
+            // only refresh what we have already loaded, the rest is loaded fresh when needed
+            for id in changes.items {
+                if self.get_item(&id).is_some() {
+                    self.item_cache
+                        .refresh(ctx, self.backend.as_ref(), id, Priority::Prefetch);
+                }
+            }
+
+            for id in changes.profiles {
+                if let Some(Ok(_)) = self.user_cache.get(&id).and_then(|p| p.ready()) {
+                    let promise = self.backend.user(ctx.clone(), &id, Priority::Prefetch);
+                    self.user_refreshes.entry(id).or_insert(promise);
+                }
+            }
+
+            // pages of the site are scraped, they are refreshed once they get old
+            // and past front pages never change
+            let page_loaded = matches!(self.page_status, RequestStatus::Done(_));
+            let scraped = matches!(self.feed_source(), Some(FeedSource::Site(_)));
+            if changes.new_items && page_loaded && !scraped && self.live_refresh.is_none() {
+                self.live_refresh = self.load_page_stories(ctx, Priority::Prefetch);
+            }
+        }
+
+        let refreshed_users: Vec<_> = self
+            .user_refreshes
+            .iter()
+            .filter(|(_, promise)| promise.ready().is_some())
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in refreshed_users {
+            if let Some(promise) = self.user_refreshes.remove(&id) {
+                match promise.block_and_take() {
+                    Ok(user) => {
+                        self.user_cache.insert(id, Promise::from_ready(Ok(user)));
+                    }
+                    Err(error) => warn!("cannot refresh user {}: {}", id, error),
+                }
+            }
+        }
+
+        if self
+            .live_refresh
+            .as_ref()
+            .map_or(false, |p| p.ready().is_some())
+        {
+            match self.live_refresh.take().map(|p| p.block_and_take()) {
+                // stories do not move under the reader, changed list waits for them
+                Some(Ok(story_ids)) => match &self.page_status {
+                    RequestStatus::Done(shown) if *shown == story_ids => self.save_page(&story_ids),
+                    RequestStatus::Done(_) => self.new_stories = Some(story_ids),
+                    _ => {}
+                },
+                Some(Err(error)) => warn!("cannot refresh stories: {}", error),
+                None => {}
+            }
+        }
+
+        if let Some(Ok(_)) = self.page_refresh.as_ref().and_then(|p| p.ready()) {
+            if let Some(Ok(story_ids)) = self.page_refresh.take().map(|p| p.block_and_take()) {
+                // keep the page number so the user stays where they were
+                self.save_page(&story_ids);
+                self.page_status = RequestStatus::Done(story_ids);
+            }
+        } else if let Some(Err(error)) = self.page_refresh.as_ref().and_then(|p| p.ready()) {
+            warn!("cannot refresh stories: {}", error);
+            self.page_refresh = None;
+        }
+    }
+
+    fn load_streamed_changes(&mut self, ctx: &egui::Context) {
+        if !self.streaming_enabled {
+            self.stories_watch = None;
+            self.thread_watch = None;
+            return;
+        }
+
+        // subscribe to what is displayed, dropping old watch closes its stream
+        let watched_page = self.stories_watch.as_ref().map(|(page, _)| *page);
+        if watched_page != Some(self.page_name) {
+            self.stories_watch = self
+                .feed_source()
+                .and_then(|source| self.backend.watch_stories(ctx.clone(), &source))
+                .map(|subscription| (self.page_name, Watch::new(subscription)));
+        }
+
+        let displayed_story = self.displayed_story();
+        let watched_story = self.thread_watch.as_ref().map(|(story_id, _)| *story_id);
+        if watched_story != displayed_story {
+            self.thread_watch = displayed_story.and_then(|story_id| {
+                self.backend
+                    .watch_item(ctx.clone(), story_id)
+                    .map(|subscription| (story_id, Watch::new(subscription)))
+            });
+        }
+
+        let streamed_ids = self
+            .stories_watch
+            .as_mut()
+            .and_then(|(_, watch)| watch.poll())
+            .map(sse::list::<HnItemId>);
+        if let Some(story_ids) = streamed_ids.filter(|ids| !ids.is_empty()) {
+            self.save_page(&story_ids);
+            self.page_status = RequestStatus::Done(story_ids);
+        }
+
+        if let Some((_, watch)) = &mut self.thread_watch {
+            if let Some(document) = watch.poll() {
+                match serde_json::from_value::<HnItem>(document.clone()) {
+                    // new kids get loaded by load_comments like any other missing comment
+                    Ok(story) => self.item_cache.update(story),
+                    Err(error) => warn!("invalid item from {}: {}", watch.url(), error),
+                }
+            }
+        }
+    }
+
+    fn search(&mut self, ctx: &egui::Context) {
+        self.search_pages = vec![self.search.search(ctx.clone(), &self.search_query, 0)];
+    }
+
+    fn render_search_page(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut submit = false;
+
+        ui.horizontal(|ui| {
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut self.search_query.text)
+                    .hint_text("Search stories and comments")
+                    .desired_width(ui.available_width() - 80.0),
+            );
+
+            if self.focus_search {
+                response.request_focus();
+                self.focus_search = false;
+            }
+
+            if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                submit = true;
+            }
+
+            if ui.button("Search").clicked() {
+                submit = true;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let query = &mut self.search_query;
+            ui.selectable_value(&mut query.sort, SearchSort::Relevance, "Relevance");
+            ui.selectable_value(&mut query.sort, SearchSort::Date, "Date");
+            ui.separator();
+            ui.selectable_value(&mut query.tags, SearchTags::All, "All");
+            ui.selectable_value(&mut query.tags, SearchTags::Stories, "Stories");
+            ui.selectable_value(&mut query.tags, SearchTags::Comments, "Comments");
+        });
+
+        egui::CollapsingHeader::new("Filters").show(ui, |ui| {
+            let query = &mut self.search_query;
+            egui::Grid::new("search_filters")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Author");
+                    ui.text_edit_singleline(&mut query.author);
+                    ui.end_row();
+
+                    ui.label("Minimum points");
+                    ui.add(egui::DragValue::new(&mut query.min_points));
+                    ui.end_row();
+
+                    ui.label("From");
+                    ui.add(egui::TextEdit::singleline(&mut query.from).hint_text("YYYY-MM-DD"));
+                    ui.end_row();
+
+                    ui.label("To");
+                    ui.add(egui::TextEdit::singleline(&mut query.to).hint_text("YYYY-MM-DD"));
+                    ui.end_row();
+                });
+        });
+
+        ui.separator();
+
+        let mut open_hit = None;
+        let mut retry_page = None;
+
+        for (page, promise) in self.search_pages.iter().enumerate() {
+            match promise.ready() {
+                Some(Ok(results)) => {
+                    if page == 0 {
+                        ui.label(RichText::new(format!("{} results", results.nb_hits)).weak());
+                        ui.separator();
+                    }
+
+                    for hit in &results.hits {
+                        let item = hit.to_item();
+                        let action = if hit.is_comment() {
+                            // the matched comment is shown under the story it was posted in
+                            ui.label(RichText::new(format!("on: {}", item.title)).weak());
+                            self.render_listed_item(&item, ui)
+                        } else {
+                            self.render_story(&item, ui, false, true)
+                        };
+                        if let Some(action) = action {
+                            open_hit = Some((hit.clone(), action));
+                        }
+
+                        ui.separator();
+                    }
+                }
+                Some(Err(error)) => {
+                    ui.vertical_centered(|ui| {
+                        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                        if ui.button("Retry").clicked() {
+                            retry_page = Some(page);
+                        }
+                    });
+                }
+                None => {}
+            }
+        }
+
+        let can_load_more = match self.search_pages.last().and_then(|p| p.ready()) {
+            Some(Ok(results)) => results.page + 1 < results.nb_pages,
+            _ => false,
+        };
+
+        if can_load_more {
+            let load_more = ui
+                .vertical_centered(|ui| ui.button("Load More").clicked())
+                .inner;
+
+            if load_more || ctx.input_mut(|i| i.consume_shortcut(&GO_NEXT)) {
+                let page = self.search_pages.len();
+                self.search_pages
+                    .push(self.search.search(ctx.clone(), &self.search_query, page));
+            }
+        }
 
-    fn load_missing_icons(&mut self, ctx: &egui::Context) {
-        for (_, promise) in &self.item_cache {
-            if let Some(result) = promise.ready() {
-                if let Ok(item) = result {
-                    if let Some(url) = &item.url {
-                        if !self.favicons.contains_key(url) {
-                            self.favicons
-                                .insert(url.clone(), fetch::favicon(ctx.clone(), url.as_str()));
-                        }
-                    }
+        if let Some(page) = retry_page {
+            self.search_pages[page] = self.search.search(ctx.clone(), &self.search_query, page);
+        }
+
+        match open_hit {
+            Some((hit, widgets::Action::OpenComments(_))) => match hit.parent_id {
+                Some(parent) if hit.is_comment() => {
+                    self.locate_thread_for_comment = Some((hit.id(), HnItemId(parent)));
                 }
-            }
+                _ => self.open_view(View::Comments(hit.story_id())),
+            },
+            Some((_, action)) => self.handle_action(ctx, action),
+            None => {}
         }
-    }
 
-    fn get_favicon_or_default(&self, url: &Url) -> &RetainedImage {
-        self.favicons
-            .get(&url)
-            .and_then(|promise| promise.ready())
-            .and_then(|result| result.as_ref().ok())
-            .unwrap_or(&self.default_icon)
+        if submit {
+            self.search(ctx);
+        }
     }
 
-    fn render_story(
-        &self,
-        story: &HnItem,
-        ui: &mut egui::Ui,
-        show_text: bool,
-        can_open_comments: bool,
-    ) -> bool {
-        return widgets::story(
-            story,
-            ui,
-            show_text,
-            can_open_comments,
-            self.render_html,
-            story
-                .url
-                .as_ref()
-                .map(|url| self.get_favicon_or_default(url)),
-        );
+    /// Looks up earlier submissions of the url being submitted once user
+    /// stops editing it.
+    fn load_missing_duplicates(&mut self, ctx: &egui::Context) {
+        if self.views.last() != Some(&View::Submit) {
+            return;
+        }
+        let Some(url) = self.submission.url() else {
+            return;
+        };
+        if ctx.memory(|memory| memory.has_focus(egui::Id::new(SUBMIT_URL_ID))) {
+            return;
+        }
+
+        if self
+            .duplicates
+            .as_ref()
+            .map_or(true, |(checked, _)| *checked != url)
+        {
+            let promise = self.search.submissions(ctx.clone(), &url);
+            self.duplicates = Some((url, promise));
+        }
     }
 
-    fn render_comment(&self, comment_id: HnItemId, ui: &mut egui::Ui) {
-        let promise = match self.item_cache.get(&comment_id) {
-            Some(promise) => promise,
-            None => return,
+    /// Leaves submit view once story is submitted, for the story it was
+    /// counted as upvote for or the list of new stories where it shows up.
+    fn finish_submission(&mut self, ctx: &egui::Context) {
+        let Some(story) = self.account.take_submitted() else {
+            return;
         };
 
-        if let Some(result) = promise.ready() {
-            match result {
-                Ok(comment) => widgets::comment(comment, ui, self.render_html, |child_id, ui| {
-                    self.render_comment(child_id, ui)
-                }),
-                Err(error) => {
-                    ui.label(format!("Error: {}", error));
-                }
-            };
+        self.submission = Default::default();
+        self.duplicates = None;
+        if self.views.last() == Some(&View::Submit) {
+            self.views.pop();
         }
-    }
 
-    fn remove_item_with_kids(&mut self, item_id: HnItemId) {
-        if let Some(promise) = self.item_cache.remove(&item_id) {
-            if let Ok(result) = promise.try_take() {
-                if let Ok(item) = result {
-                    for kid_id in &item.kids {
-                        self.remove_item_with_kids(*kid_id);
+        match story {
+            Some(story_id) => self.open_view(View::Comments(story_id)),
+            None => {
+                let newest = FeedSource::Api("newstories".to_string());
+                let index = self.feeds.iter().position(|feed| feed.source == newest);
+                if let Some(index) = index {
+                    if self.page_name == Page::Feed(index) {
+                        self.refresh(ctx);
                     }
+                    self.page_name = Page::Feed(index);
                 }
             }
         }
     }
 
-    fn refresh(&mut self, ctx: &egui::Context) {
-        if let Some(story_id) = self.display_comments_for_story {
-            self.remove_item_with_kids(story_id);
-        } else {
-            self.item_cache.clear();
-            self.page_status =
-                RequestStatus::Loading(fetch::page_stories(self.page_name, ctx.clone()));
+    /// Privacy mode and domain lists, changes apply right away and are saved.
+    fn render_privacy_window(&mut self, ctx: &egui::Context) {
+        let privacy = self.scheduler.privacy().clone();
+        let mut settings = privacy.settings();
+        let mut changed = false;
+        let mut show_privacy_window = self.show_privacy_window;
+
+        egui::Window::new("Privacy")
+            .open(&mut show_privacy_window)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                changed |= ui
+                    .checkbox(
+                        &mut settings.enabled,
+                        "Privacy mode, block requests to sites other than Hacker News",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(
+                        &mut settings.previews,
+                        "Link previews, load story pages for their description and image",
+                    )
+                    .changed();
+
+                ui.separator();
+                ui.label("Allowed in privacy mode, with subdomains");
+                changed |=
+                    widgets::domain_list("allow", &mut settings.allow, &mut self.allow_input, ui);
+
+                ui.separator();
+                ui.label("Always blocked, with subdomains");
+                changed |=
+                    widgets::domain_list("deny", &mut settings.deny, &mut self.deny_input, ui);
+
+                ui.separator();
+                privacy.with_blocked(|blocked| {
+                    ui.collapsing(format!("Blocked requests ({})", blocked.len()), |ui| {
+                        for request in blocked.iter().rev() {
+                            ui.label(format!(
+                                "{} {}",
+                                human_format::date_time(&request.time),
+                                request.url
+                            ));
+                        }
+                    });
+                });
+            });
+
+        self.show_privacy_window = show_privacy_window;
+
+        if changed {
+            self.show_previews = settings.previews;
+            self.store.save_privacy_settings(&settings);
+            privacy.set_settings(settings);
+            self.favicons.remove_blocked();
         }
     }
 
-    fn load_comments(&mut self, item: &HnItem, ctx: &egui::Context) -> bool {
-        let mut loaded = true;
+    fn render_submit_page(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let mut submit = false;
+        let submission = &mut self.submission;
 
-        for &kid in &item.kids {
-            let promise = match self.item_cache.remove(&kid) {
-                Some(promise) => promise,
-                None => fetch::hn_item(ctx.clone(), kid),
-            };
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut submission.kind, SubmissionKind::Link, "Link");
+            ui.selectable_value(&mut submission.kind, SubmissionKind::Show, "Show HN");
+            ui.selectable_value(&mut submission.kind, SubmissionKind::Ask, "Ask HN");
+        });
 
-            if let Some(result) = promise.ready() {
-                match result {
-                    Ok(kid_item) => {
-                        if !self.load_comments(kid_item, ctx) {
-                            loaded = false;
-                        }
+        egui::Grid::new("submit_form")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Title");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut submission.title)
+                            .desired_width(ui.available_width() - 60.0),
+                    );
+
+                    let length = submission.title().chars().count();
+                    let counter = RichText::new(format!("{}/{}", length, submit::TITLE_LENGTH));
+                    if length > submit::TITLE_LENGTH {
+                        ui.colored_label(ui.visuals().error_fg_color, counter);
+                    } else {
+                        ui.label(counter.weak());
                     }
-                    Err(error) => warn!("cannot load comment: {}", error),
+                });
+                ui.end_row();
+
+                if submission.kind.has_url() {
+                    ui.label("Url");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut submission.url)
+                            .id(egui::Id::new(SUBMIT_URL_ID))
+                            .desired_width(f32::INFINITY),
+                    );
+                    ui.end_row();
                 }
-            } else {
-                loaded = false;
+
+                ui.label("Text");
+                ui.add(
+                    egui::TextEdit::multiline(&mut submission.text)
+                        .hint_text(match submission.kind {
+                            SubmissionKind::Ask => "Question for discussion",
+                            _ => "Optional, shown at the top of the thread",
+                        })
+                        .desired_width(f32::INFINITY),
+                );
+                ui.end_row();
+            });
+
+        let problems = if *submission == Submission::default() {
+            Vec::new() // nothing to complain about before user starts writing
+        } else {
+            submission.problems()
+        };
+        for problem in &problems {
+            ui.colored_label(ui.visuals().warn_fg_color, problem);
+        }
+
+        // user sees earlier submissions of the url before posting it again
+        let url = submission.url();
+        let checked = match (&url, &self.duplicates) {
+            (None, _) => true,
+            (Some(url), Some((checked, promise))) => url == checked && promise.ready().is_some(),
+            (Some(_), None) => false,
+        };
+
+        let submitting = self.account.is_submitting();
+        ui.horizontal(|ui| {
+            let enabled = checked
+                && !submitting
+                && problems.is_empty()
+                && *submission != Submission::default();
+            if ui
+                .add_enabled(enabled, egui::Button::new("Submit"))
+                .clicked()
+            {
+                submit = true;
             }
 
-            self.item_cache.insert(kid, promise);
+            if submitting {
+                ui.spinner();
+            }
+
+            if let Some(error) = self.account.submit_error() {
+                ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+            }
+        });
+
+        if submit {
+            self.account.submit(ctx, &self.submission);
         }
 
-        loaded
-    }
+        let Some(url) = url else {
+            return;
+        };
 
-    fn load_missing_comments_for_opened_story(&mut self, ctx: &egui::Context) {
-        if let Some(story_id) = self.display_comments_for_story {
-            if let Some(promise) = self.item_cache.remove(&story_id) {
-                if let Some(result) = promise.ready() {
-                    if let Ok(story) = result {
-                        self.load_comments(&story, ctx);
+        ui.separator();
+
+        let mut action = None;
+        let mut retry = false;
+
+        match &self.duplicates {
+            Some((checked, promise)) if *checked == url => match promise.ready() {
+                Some(Ok(results)) => {
+                    let hits: Vec<_> = results
+                        .hits
+                        .iter()
+                        .filter(|hit| {
+                            hit.url
+                                .as_deref()
+                                .and_then(|hit_url| Url::parse(hit_url).ok())
+                                .map_or(false, |hit_url| submit::same_url(&hit_url, &url))
+                        })
+                        .collect();
+
+                    if hits.is_empty() {
+                        ui.label(RichText::new("Not submitted before").weak());
+                    } else {
+                        ui.label(format!("Submitted {} times before", hits.len()));
+                        ui.separator();
                     }
-                }
 
-                self.item_cache.insert(story_id, promise);
+                    for hit in hits {
+                        if let Some(story_action) =
+                            self.render_story(&hit.to_item(), ui, false, true)
+                        {
+                            action = Some(story_action);
+                        }
+                        ui.separator();
+                    }
+                }
+                Some(Err(error)) => {
+                    ui.horizontal(|ui| {
+                        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                        retry = ui.button("Retry").clicked();
+                    });
+                }
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Looking for earlier submissions");
+                    });
+                }
+            },
+            _ => {
+                ui.label(
+                    RichText::new("Earlier submissions are looked up once the url is entered")
+                        .weak(),
+                );
             }
         }
+
+        if retry {
+            self.duplicates = None;
+        }
+
+        if let Some(action) = action {
+            self.handle_action(ctx, action);
+        }
     }
 
     fn load_missing_page_stories(&mut self, ctx: &egui::Context) {
         if let RequestStatus::Done(item_ids) = &self.page_status {
             for &id in self.displayed_page_stories(item_ids) {
                 self.item_cache
-                    .entry(id)
-                    .or_insert_with(|| fetch::hn_item(ctx.clone(), id));
+                    .request(ctx, self.backend.as_ref(), id, Priority::Visible);
+            }
+
+            // prefetch the next page so load more is instant
+            let next_page = item_ids
+                .iter()
+                .skip((self.page_number + 1) * self.page_size)
+                .take(self.page_size);
+
+            for &id in next_page {
+                self.item_cache
+                    .request(ctx, self.backend.as_ref(), id, Priority::Prefetch);
+            }
+        }
+    }
+
+    /// Cancels queued requests and forgets them so they are requested again
+    /// when needed.
+    fn cancel_requests(&mut self, priority: Priority) {
+        self.scheduler.cancel(priority);
+
+        self.item_cache.remove_cancelled();
+        self.user_cache
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+        self.user_refreshes
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+        self.articles
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+        self.images
+            .retain(|_, promise| !scheduler::is_cancelled(promise));
+    }
+
+    /// Cancels requests of the view that is no longer displayed.
+    fn cancel_requests_for_left_view(&mut self, old_view: Option<View>) {
+        match old_view {
+            Some(View::Comments(story_id)) if self.displayed_story() != Some(story_id) => {
+                self.cancel_requests(Priority::Thread);
+            }
+            Some(View::Article(story_id))
+                if self.views.last() != Some(&View::Article(story_id)) =>
+            {
+                self.cancel_requests(Priority::Image);
+                if self.displayed_story() != Some(story_id) {
+                    self.cancel_requests(Priority::Thread);
+                }
             }
+            Some(View::User { id, .. }) => {
+                let displayed = matches!(self.views.last(), Some(View::User { id: user_id, .. }) if *user_id == id);
+                if !displayed {
+                    self.cancel_requests(Priority::Profile);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets comments that were expanded or collapsed once no thread is
+    /// open, a thread being located keeps the ancestors it expanded.
+    fn forget_left_threads(&mut self) {
+        let in_thread = self
+            .views
+            .iter()
+            .any(|view| matches!(view, View::Comments(_) | View::Article(_)));
+        if !in_thread && self.locate_thread_for_comment.is_none() {
+            self.expanded.clear();
+            self.collapsed.clear();
         }
     }
 
     fn displayed_page_stories<'a>(
         &self,
-        item_ids: &'a Vec<HnItemId>,
+        item_ids: &'a [HnItemId],
     ) -> impl Iterator<Item = &'a HnItemId> {
         item_ids
             .iter()
@@ -350,17 +1741,14 @@ impl Application {
     }
 
     fn get_item(&self, item_id: &HnItemId) -> Option<&HnItem> {
-        self.item_cache
-            .get(&item_id)
-            .and_then(|promise| promise.ready())
-            .and_then(|result| result.as_ref().ok())
+        self.item_cache.item(item_id)
     }
 }
 
 enum RequestStatus {
     Done(Vec<HnItemId>),
-    Loading(Promise<ehttp::Result<Vec<HnItemId>>>),
-    Error(String),
+    Loading(Promise<error::Result<Vec<HnItemId>>>),
+    Error(Error),
 }
 
 impl Default for RequestStatus {
@@ -378,17 +1766,20 @@ impl eframe::App for Application {
         }
 
         if ctx.input_mut(|i| i.consume_shortcut(&REFRESH_SHORTCUT)) {
-            self.refresh(&ctx);
+            self.refresh(ctx);
         }
 
         if ctx.input_mut(|i| i.consume_shortcut(&GO_BACK)) {
             go_back = true;
         }
 
-        if ctx.input_mut(|i| i.consume_shortcut(&GO_BACK_FROM_COMMENTS)) {
-            if self.display_comments_for_story.is_some() {
-                go_back = true;
-            }
+        // only consume backspace in opened views so it can be typed into search box,
+        // or into any text field that has focus
+        if !self.views.is_empty()
+            && !ctx.wants_keyboard_input()
+            && ctx.input_mut(|i| i.consume_shortcut(&GO_BACK_FROM_COMMENTS))
+        {
+            go_back = true;
         }
 
         self.page_status = match std::mem::take(&mut self.page_status) {
@@ -396,8 +1787,11 @@ impl eframe::App for Application {
             RequestStatus::Loading(mut promise) => {
                 if let Some(result) = promise.ready_mut() {
                     match result {
-                        Ok(resource) => RequestStatus::Done(std::mem::take(resource)),
-                        Err(error) => RequestStatus::Error(std::mem::take(error)),
+                        Ok(resource) => {
+                            self.save_page(resource);
+                            RequestStatus::Done(std::mem::take(resource))
+                        }
+                        Err(error) => self.failed_page(error),
                     }
                 } else {
                     RequestStatus::Loading(promise)
@@ -406,42 +1800,66 @@ impl eframe::App for Application {
             RequestStatus::Error(error) => RequestStatus::Error(error),
         };
 
+        self.check_connection(ctx);
+        self.item_cache.poll(ctx, self.backend.as_ref());
+        self.account.poll();
+        if self.account.username().is_some() {
+            self.login_password.clear();
+        }
+        for parent in self.account.take_posted() {
+            // the reply shows up once parent is refetched with it in its kids
+            self.drafts.get_mut().remove(&parent);
+            self.drafts_changed.set(true);
+            self.item_cache
+                .refresh(ctx, self.backend.as_ref(), parent, Priority::Thread);
+        }
         self.load_missing_page_stories(ctx);
         self.load_missing_icons(ctx);
         self.load_missing_comments_for_opened_story(ctx);
+        self.load_missing_user(ctx);
+        self.load_missing_article(ctx);
+        self.load_missing_duplicates(ctx);
+        self.load_missing_account_state(ctx);
+        self.load_thread_for_comment(ctx);
+        self.load_live_updates(ctx);
+        self.load_streamed_changes(ctx);
 
         let loading = matches!(self.page_status, RequestStatus::Loading(_))
-            || self.item_cache.iter().any(|(_, p)| p.ready().is_none());
+            || self.item_cache.any_loading()
+            || self.search_pages.iter().any(|p| p.ready().is_none())
+            || self.articles.values().any(|p| p.ready().is_none())
+            || self.user_cache.iter().any(|(_, p)| p.ready().is_none());
         let loading_stories = if let RequestStatus::Done(item_ids) = &self.page_status {
-            self.displayed_page_stories(item_ids).any(|id| {
-                self.item_cache
-                    .get(id)
-                    .map_or(false, |p| p.ready().is_none())
-            })
+            self.displayed_page_stories(item_ids)
+                .any(|id| self.item_cache.is_loading(id))
         } else {
             false
         };
 
-        let old_page = self.page_name;
-
-        if ctx.input_mut(|i| i.consume_shortcut(&TAB_TOP)) {
-            self.page_name = Page::Top;
-        }
+        self.sync.poll(
+            ctx,
+            self.backend.as_ref(),
+            !loading && !self.offline,
+            &self.bookmarks,
+        );
 
-        if ctx.input_mut(|i| i.consume_shortcut(&TAB_NEW)) {
-            self.page_name = Page::New;
-        }
+        let old_page = self.page_name;
+        let old_view = self.views.last().cloned();
 
-        if ctx.input_mut(|i| i.consume_shortcut(&TAB_SHOW)) {
-            self.page_name = Page::Show;
-        }
+        self.finish_submission(ctx);
 
-        if ctx.input_mut(|i| i.consume_shortcut(&TAB_ASK)) {
-            self.page_name = Page::Ask;
+        for (index, feed) in self.feeds.iter().enumerate() {
+            if let Some(shortcut) = &feed.shortcut {
+                if ctx.input_mut(|i| i.consume_shortcut(shortcut)) {
+                    self.page_name = Page::Feed(index);
+                }
+            }
         }
 
-        if ctx.input_mut(|i| i.consume_shortcut(&TAB_JOBS)) {
-            self.page_name = Page::Jobs;
+        if ctx.input_mut(|i| i.consume_shortcut(&SEARCH_SHORTCUT)) {
+            self.page_name = Page::Search;
+            self.views.clear();
+            self.focus_search = true;
         }
 
         egui::TopBottomPanel::top("header").show(ctx, |ui| {
@@ -452,11 +1870,35 @@ impl eframe::App for Application {
 
                 ui.add_space(10.0);
 
-                ui.selectable_value(&mut self.page_name, Page::Top, "Top");
-                ui.selectable_value(&mut self.page_name, Page::New, "New");
-                ui.selectable_value(&mut self.page_name, Page::Show, "Show");
-                ui.selectable_value(&mut self.page_name, Page::Ask, "Ask");
-                ui.selectable_value(&mut self.page_name, Page::Jobs, "Jobs");
+                for (index, feed) in self.feeds.iter().enumerate() {
+                    ui.selectable_value(&mut self.page_name, Page::Feed(index), &feed.name);
+                }
+                ui.selectable_value(&mut self.page_name, Page::Bookmarks, "Bookmarks");
+                ui.selectable_value(&mut self.page_name, Page::Search, "Search");
+
+                let front_day = match self.page_name {
+                    Page::Front(day) => Some(day),
+                    _ => None,
+                };
+                let text = match front_day {
+                    Some(day) => RichText::new(day.to_string()).strong(),
+                    None => RichText::new("Past"),
+                };
+                ui.menu_button(text, |ui| {
+                    let picked = widgets::calendar(
+                        ui.make_persistent_id("past_front_pages"),
+                        front_day,
+                        scrape::first_front_page(),
+                        scrape::site_date(OffsetDateTime::now_utc()),
+                        ui,
+                    );
+                    if let Some(day) = picked {
+                        self.page_name = Page::Front(day);
+                        ui.close_menu();
+                    }
+                })
+                .response
+                .on_hover_text("Front page as it was on a past day");
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     let size = ui.available_height() * 0.6;
@@ -470,7 +1912,7 @@ impl eframe::App for Application {
                                 )
                                 .clicked()
                             {
-                                self.refresh(&ctx);
+                                self.refresh(ctx);
                             }
                         }
                         _ => {
@@ -478,11 +1920,10 @@ impl eframe::App for Application {
                         }
                     }
 
-                    let can_go_back =
-                        self.display_comments_for_story.is_some() || self.page_number > 0;
+                    let can_go_back = !self.views.is_empty() || self.page_number > 0;
 
-                    let text = if self.display_comments_for_story.is_some() {
-                        "↩" // "leftwards arrow with hook" - for going back to page from comment section
+                    let text = if !self.views.is_empty() {
+                        "↩" // "leftwards arrow with hook" - for going back to page from comment section or profile
                     } else {
                         "⮨" // "black curved downwards and leftwards arrow" - for going back a page
                     };
@@ -498,10 +1939,54 @@ impl eframe::App for Application {
                             go_back = true;
                         }
                     });
+
+                    if ui.button("Privacy").clicked() {
+                        self.show_privacy_window = !self.show_privacy_window;
+                    }
+
+                    match self.account.username().map(str::to_string) {
+                        Some(username) => {
+                            if ui.button("Submit").clicked() {
+                                self.open_view(View::Submit);
+                            }
+
+                            ui.menu_button(username.clone(), |ui| {
+                                if ui.button("Profile").clicked() {
+                                    self.handle_action(ctx, widgets::Action::OpenUser(username));
+                                    ui.close_menu();
+                                }
+                                if ui.button("Log out").clicked() {
+                                    self.account.logout();
+                                    ui.close_menu();
+                                }
+                            });
+                        }
+                        None => {
+                            if ui.button("Log in").clicked() {
+                                self.show_login_window = true;
+                            }
+                        }
+                    }
                 });
             });
         });
 
+        if self.offline {
+            egui::TopBottomPanel::top("offline").show(ctx, |ui| {
+                let text = match self.displayed_data_fetched() {
+                    Some(fetched) => format!(
+                        "Offline, showing data from {}",
+                        human_format::date_time(&fetched)
+                    ),
+                    None => "Offline".to_string(),
+                };
+
+                ui.vertical_centered(|ui| {
+                    ui.colored_label(ui.visuals().warn_fg_color, text);
+                });
+            });
+        }
+
         egui::TopBottomPanel::bottom("footer")
             .show_separator_line(false)
             .show(ctx, |ui| {
@@ -510,6 +1995,17 @@ impl eframe::App for Application {
                         ui.label("Loading...");
                     }
 
+                    if let Some(error) = self.account.error() {
+                        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                    }
+
+                    if let Some((downloaded, total)) = self.sync.progress() {
+                        ui.label(format!(
+                            "Syncing threads for offline reading: {} of {} items",
+                            downloaded, total
+                        ));
+                    }
+
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
                         ui.hyperlink_to(
                             "\u{e624} Hacker Newsfeed on GitHub",
@@ -521,33 +2017,141 @@ impl eframe::App for Application {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
-                if let Some(story_id) = self.display_comments_for_story {
+                let mut action = None;
+                let mut load_more_submissions = false;
+
+                if let Some(story_id) = self.displayed_story() {
+                    let reading = self.views.last() == Some(&View::Article(story_id));
+
                     if let Some(story) = self.get_item(&story_id) {
-                        self.render_story(story, ui, true, false);
+                        action = self.render_story(story, ui, !reading, false);
 
-                        ui.separator();
+                        if let Some(tab_action) = widgets::story_tabs(story, reading, ui) {
+                            action = Some(tab_action);
+                        }
+
+                        if reading {
+                            ui.separator();
+
+                            if let Some(article_action) = self.render_article(story, ui) {
+                                action = Some(article_action);
+                            }
+                        } else {
+                            if !story.parts.is_empty() {
+                                let options: Vec<_> =
+                                    story.parts.iter().map(|id| self.get_item(id)).collect();
+                                widgets::poll_options(&options, ui, self.render_html);
+                            }
+
+                            // comment box for the story is always open, like on the site
+                            if self.account.username().is_some() {
+                                self.drafts.borrow_mut().entry(story_id).or_default();
+                                if let Some(reply_action) = self.render_reply_box(story_id, ui) {
+                                    action = Some(reply_action);
+                                }
+                            }
+
+                            ui.separator();
+
+                            for comment_id in &story.kids {
+                                if let Some(comment_action) = self.render_comment(*comment_id, ui) {
+                                    action = Some(comment_action);
+                                }
+                            }
+                        }
+                    } else if let Some(Err(error)) =
+                        self.item_cache.get(&story_id).and_then(|p| p.ready())
+                    {
+                        action = widgets::item_error(story_id, error, ui);
+                    }
+                } else if let Some(View::User { id, page_number }) = self.views.last() {
+                    action = self.render_user_page(id, *page_number, ui);
+
+                    let has_more = match self.user_cache.get(id).and_then(|p| p.ready()) {
+                        Some(Ok(user)) => user.submitted.len() > (page_number + 1) * self.page_size,
+                        _ => false,
+                    };
+
+                    if has_more {
+                        ui.vertical_centered(|ui| {
+                            if ui
+                                .add_enabled(!loading, egui::Button::new("Load More"))
+                                .clicked()
+                            {
+                                load_more_submissions = true;
+                            }
+                        });
 
-                        for comment_id in &story.kids {
-                            self.render_comment(*comment_id, ui);
+                        if ctx.input_mut(|i| i.consume_shortcut(&GO_NEXT)) && !loading {
+                            load_more_submissions = true;
                         }
                     }
+                } else if self.views.last() == Some(&View::Submit) {
+                    self.render_submit_page(ctx, ui);
+                } else if self.page_name == Page::Search {
+                    self.render_search_page(ctx, ui);
                 } else {
+                    if let Page::Front(day) = self.page_name {
+                        let switch = widgets::front_page_day(
+                            day,
+                            scrape::first_front_page(),
+                            scrape::site_date(OffsetDateTime::now_utc()),
+                            ui,
+                        );
+                        if let Some(day) = switch {
+                            self.page_name = Page::Front(day);
+                        }
+                        ui.separator();
+                    }
+
+                    if let (Some(fresh), RequestStatus::Done(shown)) =
+                        (&self.new_stories, &self.page_status)
+                    {
+                        let text = match updates::new_stories(shown, fresh) {
+                            0 => "Show updated ranking".to_string(),
+                            1 => "Show 1 new story".to_string(),
+                            count => format!("Show {} new stories", count),
+                        };
+                        if ui.vertical_centered(|ui| ui.button(text).clicked()).inner {
+                            if let Some(story_ids) = self.new_stories.take() {
+                                self.save_page(&story_ids);
+                                self.page_status = RequestStatus::Done(story_ids);
+                            }
+                        }
+                        ui.separator();
+                    }
+
                     let error = match (&self.page_status, loading_stories) {
                         (RequestStatus::Done(story_items), false) => {
                             for story_id in self.displayed_page_stories(story_items) {
-                                if let Some(story) = self.get_item(story_id) {
-                                    if self.render_story(story, ui, false, true) {
-                                        self.display_comments_for_story = Some(story.id);
-                                    }
-
-                                    ui.separator();
+                                let story_action =
+                                    match self.item_cache.get(story_id).and_then(|p| p.ready()) {
+                                        Some(Ok(story)) => {
+                                            let hidden = self
+                                                .account
+                                                .state(story.id)
+                                                .map_or(false, |state| state.hidden);
+                                            if hidden {
+                                                continue;
+                                            }
+                                            // lists scraped from the site can have comments
+                                            self.render_listed_item(story, ui)
+                                        }
+                                        Some(Err(Error::NullItem)) | None => continue,
+                                        Some(Err(error)) => {
+                                            widgets::item_error(*story_id, error, ui)
+                                        }
+                                    };
+
+                                if story_action.is_some() {
+                                    action = story_action;
                                 }
+
+                                ui.separator();
                             }
 
-                            if ctx.input_mut(|i| i.consume_shortcut(&GO_NEXT)) {
-                                if !loading {
-                                    self.page_number += 1;
-                                }
+                            if ctx.input_mut(|i| i.consume_shortcut(&GO_NEXT)) && !loading {
+                                self.page_number += 1;
                             }
 
                             ui.vertical_centered(|ui| {
@@ -574,9 +2178,68 @@ impl eframe::App for Application {
                         });
                     }
                 }
+
+                if load_more_submissions {
+                    if let Some(View::User { page_number, .. }) = self.views.last_mut() {
+                        *page_number += 1;
+                    }
+                }
+
+                if let Some(action) = action {
+                    self.handle_action(ctx, action);
+                }
             });
         });
 
+        let mut show_login_window = self.show_login_window && self.account.username().is_none();
+
+        egui::Window::new("Log in to Hacker News")
+            .open(&mut show_login_window)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut submit = false;
+
+                egui::Grid::new("login_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Username");
+                    ui.text_edit_singleline(&mut self.login_username);
+                    ui.end_row();
+
+                    ui.label("Password");
+                    let response =
+                        ui.add(egui::TextEdit::singleline(&mut self.login_password).password(true));
+                    if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter)) {
+                        submit = true;
+                    }
+                    ui.end_row();
+                });
+
+                let logging_in = self.account.is_logging_in();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!logging_in, egui::Button::new("Log in"))
+                        .clicked()
+                    {
+                        submit = true;
+                    }
+
+                    if logging_in {
+                        ui.spinner();
+                    }
+                });
+
+                if let Some(error) = self.account.login_error() {
+                    ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                }
+
+                if submit && !logging_in {
+                    self.account
+                        .login(ctx, &self.login_username, &self.login_password);
+                }
+            });
+
+        self.show_login_window = show_login_window;
+
         let mut show_debug_window = self.show_debug_window;
 
         egui::Window::new("Debug")
@@ -598,6 +2261,24 @@ impl eframe::App for Application {
                     "Render Html in story text and comments",
                 );
 
+                ui.checkbox(
+                    &mut self.live_updates_enabled,
+                    "Live updates of stories, comments and profiles",
+                );
+
+                ui.checkbox(
+                    &mut self.streaming_enabled,
+                    "Real-time streaming of story list and opened story",
+                );
+
+                ui.checkbox(
+                    &mut self.whole_threads_enabled,
+                    "Load comments of opened story from Algolia in one request",
+                );
+
+                let (queued, running) = self.scheduler.stats();
+                ui.label(format!("Requests: {} queued, {} running", queued, running));
+
                 ui.separator();
 
                 ui.label("Input Html text to render");
@@ -612,33 +2293,135 @@ impl eframe::App for Application {
 
         self.show_debug_window = show_debug_window;
 
+        self.render_privacy_window(ctx);
+
+        if self.drafts_changed.take() {
+            self.store.save_drafts(self.drafts.get_mut());
+        }
+
         if go_back {
-            if self.display_comments_for_story.is_some() {
-                self.display_comments_for_story = None;
-            } else if self.page_number > 0 {
-                self.page_number -= 1;
+            match self.views.last_mut() {
+                Some(View::User { page_number, .. }) if *page_number > 0 => *page_number -= 1,
+                Some(_) => {
+                    self.views.pop();
+                    self.scroll_to_comment.set(None);
+                }
+                None if self.page_number > 0 => self.page_number -= 1,
+                None => {}
             }
         }
 
         if old_page != self.page_name {
-            self.display_comments_for_story = None;
-            self.page_status =
-                RequestStatus::Loading(fetch::page_stories(self.page_name, ctx.clone()));
+            // everything loading for the previous page is no longer needed
+            self.cancel_requests(Priority::Visible);
+            self.cancel_requests(Priority::Profile);
+            self.cancel_requests(Priority::Thread);
+            self.cancel_requests(Priority::Prefetch);
+            self.views.clear();
+            self.page_refresh = None;
+            self.live_refresh = None;
+            self.new_stories = None;
+            self.focus_search = self.page_name == Page::Search;
+            self.load_page(ctx);
             self.page_number = 0;
             ctx.request_repaint();
+        } else {
+            self.cancel_requests_for_left_view(old_view);
         }
+        self.forget_left_threads();
+    }
+}
+
+/// Depth below the story that replies of comment at `depth` are loaded with
+/// and how many of them are loaded, `None` when they wait until user expands
+/// them. Expanded comment loads the levels below it like the story does.
+fn replies_to_load(depth: usize, expanded: bool, collapsed: bool) -> Option<(usize, usize)> {
+    if expanded {
+        return Some((0, usize::MAX));
     }
+    if depth >= THREAD_DEPTH || collapsed {
+        return None;
+    }
+
+    let shown = if depth == 0 {
+        usize::MAX
+    } else {
+        REPLIES_SHOWN
+    };
+    Some((depth, shown))
+}
+
+/// Items on page `page_number` of list split into pages of `page_size`.
+fn page_of<T>(items: &[T], page_number: usize, page_size: usize) -> &[T] {
+    let start = (page_number * page_size).min(items.len());
+    let end = (start + page_size).min(items.len());
+    &items[start..end]
 }
 
 fn main() -> Result<(), eframe::Error> {
     // Log to stdout (if you run with `RUST_LOG=debug`).
     tracing_subscriber::fmt::init();
 
-    let mut native_options = eframe::NativeOptions::default();
-    native_options.initial_window_size = Some(Vec2::new(520., 960.));
+    let native_options = eframe::NativeOptions {
+        initial_window_size: Some(Vec2::new(520., 960.)),
+        ..Default::default()
+    };
     eframe::run_native(
         "Hacker Newsfeed",
         native_options,
-        Box::new(|cc| Box::new(Application::new(cc))),
+        Box::new(|cc| {
+            // browsers also limit connections to single host to six
+            let scheduler =
+                Scheduler::new(6).with_privacy(Privacy::new(PrivacySettings::from_env()));
+
+            Box::new(Application::new(
+                cc,
+                scheduler.clone(),
+                Box::new(Firebase::from_env(scheduler.clone())),
+                Site::from_env(scheduler.clone()),
+                Algolia::from_env(scheduler.clone()),
+                Account::from_env(scheduler),
+                Feeds::from_env(),
+                Store::from_env(),
+                SyncConfig::from_env(),
+            ))
+        }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_user_submissions() {
+        let user: HnUser = serde_json::from_str(
+            r#"{"about":"Hi","created":1173923446,"id":"jl","karma":2937,"submitted":[1,2,3,4,5]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(page_of(&user.submitted, 0, 2), [HnItemId(1), HnItemId(2)]);
+        assert_eq!(page_of(&user.submitted, 2, 2), [HnItemId(5)]);
+        assert!(page_of(&user.submitted, 3, 2).is_empty());
+    }
+
+    #[test]
+    fn limits_replies_until_expanded() {
+        // all comments of the story are loaded, replies up to the limit
+        assert_eq!(replies_to_load(0, false, false), Some((0, usize::MAX)));
+        assert_eq!(replies_to_load(1, false, false), Some((1, REPLIES_SHOWN)));
+        assert_eq!(
+            replies_to_load(THREAD_DEPTH - 1, false, false),
+            Some((THREAD_DEPTH - 1, REPLIES_SHOWN))
+        );
+        assert_eq!(replies_to_load(THREAD_DEPTH, false, false), None);
+
+        // collapsed comment waits, expanding it overrides depth and collapse
+        assert_eq!(replies_to_load(1, false, true), None);
+        assert_eq!(
+            replies_to_load(THREAD_DEPTH + 2, true, false),
+            Some((0, usize::MAX))
+        );
+        assert_eq!(replies_to_load(1, true, true), Some((0, usize::MAX)));
+    }
+}
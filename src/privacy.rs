@@ -0,0 +1,211 @@
+//! Blocking of requests to sites other than Hacker News, they would learn
+//! what stories we read.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use eframe::epaint::ahash::HashSet;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::debug;
+use url::Url;
+
+// how many blocked requests are kept for the debug window
+const BLOCKED_HISTORY: usize = 100;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PrivacySettings {
+    pub enabled: bool,      // block requests to third parties unless they are allowed
+    pub allow: Vec<String>, // domains allowed in privacy mode, with their subdomains
+    pub deny: Vec<String>,  // domains blocked even without privacy mode
+    pub previews: bool,     // load pages of stories for their link previews
+}
+
+impl PrivacySettings {
+    /// Reads `HACKER_NEWSFEED_PRIVACY` set to `1` or `true` and comma separated
+    /// domains in `HACKER_NEWSFEED_ALLOW` and `HACKER_NEWSFEED_DENY`.
+    pub(crate) fn from_env() -> Self {
+        let domains = |name| {
+            std::env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(domain)
+                .collect()
+        };
+
+        Self {
+            enabled: matches!(
+                std::env::var("HACKER_NEWSFEED_PRIVACY").as_deref(),
+                Ok("1" | "true")
+            ),
+            allow: domains("HACKER_NEWSFEED_ALLOW"),
+            deny: domains("HACKER_NEWSFEED_DENY"),
+            previews: false,
+        }
+    }
+}
+
+/// Domain as it is matched against hosts, `None` when there is nothing left.
+pub(crate) fn domain(text: &str) -> Option<String> {
+    let domain = text.trim().trim_start_matches('.').to_ascii_lowercase();
+    (!domain.is_empty()).then_some(domain)
+}
+
+pub(crate) struct BlockedRequest {
+    pub url: String,
+    pub time: OffsetDateTime,
+}
+
+#[derive(Default)]
+struct State {
+    settings: PrivacySettings,
+    first_party: HashSet<String>,
+    blocked: VecDeque<BlockedRequest>,
+}
+
+/// Decides which requests may be sent, shared by everything that sends them.
+/// Hosts of Hacker News apis and feeds are first party and always allowed.
+#[derive(Clone, Default)]
+pub(crate) struct Privacy {
+    state: Arc<Mutex<State>>,
+}
+
+impl Privacy {
+    pub(crate) fn new(settings: PrivacySettings) -> Self {
+        let privacy = Self::default();
+        privacy.state.lock().unwrap().settings = settings;
+
+        for url in [
+            "https://news.ycombinator.com/",
+            "https://hacker-news.firebaseio.com/",
+            "https://hn.algolia.com/",
+        ] {
+            privacy.add_first_party(url);
+        }
+
+        privacy
+    }
+
+    pub(crate) fn add_first_party(&self, url: &str) {
+        if let Some(host) = host(url) {
+            self.state.lock().unwrap().first_party.insert(host);
+        }
+    }
+
+    pub(crate) fn is_first_party(&self, url: &str) -> bool {
+        host(url).map_or(false, |host| {
+            self.state.lock().unwrap().first_party.contains(&host)
+        })
+    }
+
+    pub(crate) fn settings(&self) -> PrivacySettings {
+        self.state.lock().unwrap().settings.clone()
+    }
+
+    pub(crate) fn set_settings(&self, settings: PrivacySettings) {
+        self.state.lock().unwrap().settings = settings;
+    }
+
+    /// Whether request to `url` may be sent, blocked requests are remembered.
+    pub(crate) fn allows(&self, url: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(host) = host(url) else {
+            return true; // invalid urls fail on their own
+        };
+
+        let matches = |domains: &[String]| domains.iter().any(|domain| is_within(&host, domain));
+        let allowed = if state.first_party.contains(&host) {
+            true
+        } else if matches(&state.settings.deny) {
+            false
+        } else {
+            !state.settings.enabled || matches(&state.settings.allow)
+        };
+
+        if !allowed {
+            debug!("Blocked request to {}", url);
+            if state.blocked.len() == BLOCKED_HISTORY {
+                state.blocked.pop_front();
+            }
+            state.blocked.push_back(BlockedRequest {
+                url: url.to_string(),
+                time: OffsetDateTime::now_utc(),
+            });
+        }
+
+        allowed
+    }
+
+    /// Calls `f` with blocked requests, the oldest first.
+    pub(crate) fn with_blocked<R>(&self, f: impl FnOnce(&VecDeque<BlockedRequest>) -> R) -> R {
+        f(&self.state.lock().unwrap().blocked)
+    }
+}
+
+fn host(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_ascii_lowercase())
+}
+
+/// Whether `host` is `domain` or its subdomain.
+fn is_within(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .map_or(false, |prefix| prefix.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_third_parties_in_privacy_mode() {
+        let privacy = Privacy::new(PrivacySettings {
+            enabled: true,
+            allow: vec!["github.com".to_string()],
+            deny: vec!["tracker.example".to_string()],
+            ..Default::default()
+        });
+
+        assert!(privacy.allows("https://hacker-news.firebaseio.com/v0/item/1.json"));
+        assert!(privacy.allows("https://hn.algolia.com/api/v1/search?query=rust"));
+        assert!(privacy.allows("https://github.com/favicon.ico"));
+        assert!(privacy.allows("https://gist.github.com/favicon.ico"));
+        assert!(!privacy.allows("https://notgithub.com/favicon.ico"));
+        assert!(!privacy.allows("https://www.bbc.com/favicon.ico"));
+
+        privacy.add_first_party("http://localhost:8080/ids.json");
+        assert!(privacy.allows("http://localhost:8080/item/1.json"));
+
+        // deny list applies without privacy mode too
+        privacy.set_settings(PrivacySettings {
+            enabled: false,
+            ..privacy.settings()
+        });
+        assert!(privacy.allows("https://www.bbc.com/favicon.ico"));
+        assert!(!privacy.allows("https://cdn.tracker.example/pixel.gif"));
+
+        let blocked: Vec<String> =
+            privacy.with_blocked(|blocked| blocked.iter().map(|b| b.url.clone()).collect());
+        assert_eq!(
+            blocked,
+            [
+                "https://notgithub.com/favicon.ico",
+                "https://www.bbc.com/favicon.ico",
+                "https://cdn.tracker.example/pixel.gif",
+            ]
+        );
+    }
+
+    #[test]
+    fn normalizes_domains() {
+        assert_eq!(domain(" .GitHub.com "), Some("github.com".to_string()));
+        assert_eq!(domain(" . "), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! Reader mode, extracts the main content of article pages so they can be
+//! read in app.
+
+use scraper::{ElementRef, Html, Node, Selector};
+use url::Url;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Block {
+    Heading(usize, String), // level 1 to 6
+    Paragraph(String),
+    Code(String),
+    List { ordered: bool, items: Vec<String> },
+    Quote(String),
+    Image { url: Url, alt: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Article {
+    pub title: String,
+    pub blocks: Vec<Block>,
+}
+
+// elements that never contain the article
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "nav", "aside", "footer", "header", "form", "button", "svg",
+    "iframe", "template", "select",
+];
+
+// elements whose text flows into the surrounding paragraph
+const INLINE: &[&str] = &[
+    "a", "abbr", "b", "bdi", "br", "cite", "code", "data", "del", "dfn", "em", "i", "ins", "kbd",
+    "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u", "var", "wbr",
+];
+
+// hints in class and id of elements that contain the article or something else
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "story", "text",
+];
+const NEGATIVE_HINTS: &[&str] = &[
+    "ad-",
+    "banner",
+    "comment",
+    "footer",
+    "menu",
+    "nav",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+    "subscribe",
+    "widget",
+];
+
+/// Extracts article from html page at `url`, the element with the most
+/// paragraph text is taken as its content (readability-style heuristic).
+pub(crate) fn extract(html: &str, url: &str) -> Option<Article> {
+    let document = Html::parse_document(html);
+    let base_url = Url::parse(url).ok();
+
+    let title_selector = Selector::parse("title, h1").unwrap();
+    let title = document
+        .select(&title_selector)
+        .next()
+        .map(|element| normalize_whitespace(&element.text().collect::<String>()))
+        .unwrap_or_default();
+
+    let candidates = Selector::parse("article, main, section, div, td").unwrap();
+    let content = document
+        .select(&candidates)
+        .filter(|element| !is_skipped(*element))
+        .map(|element| (score(element), element))
+        .filter(|(score, _)| *score > 0)
+        .max_by_key(|(score, _)| *score)
+        .map(|(_, element)| element)?;
+
+    let mut blocks = Vec::new();
+    let mut paragraph = String::new();
+    collect_blocks(content, base_url.as_ref(), &mut blocks, &mut paragraph);
+    flush_paragraph(&mut blocks, &mut paragraph);
+
+    if blocks.is_empty() {
+        return None;
+    }
+
+    Some(Article { title, blocks })
+}
+
+/// Text length of paragraphs directly in element with a bonus for commas,
+/// adjusted by hints in its class and id.
+fn score(element: ElementRef) -> i64 {
+    let paragraphs: i64 = element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|child| matches!(child.value().name(), "p" | "pre" | "blockquote"))
+        .map(|paragraph| {
+            let text: String = paragraph.text().collect();
+            let text = text.trim();
+            if text.chars().count() < 25 {
+                0
+            } else {
+                text.chars().count() as i64 + text.matches(',').count() as i64 * 10
+            }
+        })
+        .sum();
+
+    if paragraphs == 0 {
+        return 0;
+    }
+
+    let hints = format!(
+        "{} {}",
+        element.value().attr("class").unwrap_or_default(),
+        element.value().attr("id").unwrap_or_default()
+    )
+    .to_ascii_lowercase();
+
+    let mut weight = 0;
+    if POSITIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight += 200;
+    }
+    if NEGATIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight -= 500;
+    }
+    if element.value().name() == "article" {
+        weight += 200;
+    }
+
+    paragraphs + weight
+}
+
+fn is_skipped(element: ElementRef) -> bool {
+    std::iter::successors(Some(element), |element| {
+        element.parent().and_then(ElementRef::wrap)
+    })
+    .any(|element| SKIPPED.contains(&element.value().name()))
+}
+
+fn collect_blocks(
+    element: ElementRef,
+    base_url: Option<&Url>,
+    blocks: &mut Vec<Block>,
+    paragraph: &mut String,
+) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => paragraph.push_str(text),
+            Node::Element(_) => {
+                let Some(child) = ElementRef::wrap(child) else {
+                    continue;
+                };
+                let name = child.value().name();
+
+                if SKIPPED.contains(&name) {
+                    continue;
+                }
+
+                if INLINE.contains(&name) {
+                    if name == "br" {
+                        paragraph.push(' ');
+                    } else {
+                        paragraph.extend(child.text());
+                    }
+                    continue;
+                }
+
+                flush_paragraph(blocks, paragraph);
+
+                let text = || normalize_whitespace(&child.text().collect::<String>());
+                match name {
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                        let level = name[1..].parse().unwrap_or(1);
+                        push_text(blocks, text(), |text| Block::Heading(level, text));
+                    }
+                    "pre" => {
+                        let code: String = child.text().collect();
+                        if !code.trim().is_empty() {
+                            blocks.push(Block::Code(code.trim_end().to_string()));
+                        }
+                    }
+                    "ul" | "ol" => {
+                        let items: Vec<String> = child
+                            .children()
+                            .filter_map(ElementRef::wrap)
+                            .filter(|item| item.value().name() == "li")
+                            .map(|item| normalize_whitespace(&item.text().collect::<String>()))
+                            .filter(|item| !item.is_empty())
+                            .collect();
+
+                        if !items.is_empty() {
+                            blocks.push(Block::List {
+                                ordered: name == "ol",
+                                items,
+                            });
+                        }
+                    }
+                    "blockquote" => push_text(blocks, text(), Block::Quote),
+                    "img" => {
+                        let src = child
+                            .value()
+                            .attr("src")
+                            .and_then(|src| Url::options().base_url(base_url).parse(src).ok());
+
+                        if let Some(url) = src {
+                            let alt = child.value().attr("alt").unwrap_or_default().to_string();
+                            blocks.push(Block::Image { url, alt });
+                        }
+                    }
+                    _ => {
+                        collect_blocks(child, base_url, blocks, paragraph);
+                        flush_paragraph(blocks, paragraph);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn push_text(blocks: &mut Vec<Block>, text: String, block: impl FnOnce(String) -> Block) {
+    if !text.is_empty() {
+        blocks.push(block(text));
+    }
+}
+
+fn flush_paragraph(blocks: &mut Vec<Block>, paragraph: &mut String) {
+    let text = normalize_whitespace(paragraph);
+    paragraph.clear();
+    push_text(blocks, text, Block::Paragraph);
+}
+
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOG_POST: &str = r#"
+        <html>
+        <head><title>Frame pointers vs DWARF – my verdict</title></head>
+        <body>
+        <header><nav><a href="/">Home</a> <a href="/about">About</a></nav></header>
+        <div id="sidebar" class="widget-area">
+            <p>Subscribe to get new posts by email, it is free and you can unsubscribe anytime.</p>
+        </div>
+        <article class="post">
+            <h1>Frame pointers vs DWARF</h1>
+            <p>A couple of weeks ago I wrote a series of blog posts about adding frame pointers to <em>Fedora</em>.</p>
+            <h2>Results</h2>
+            <p>The overhead of frame pointers is about 1%, which is small, but not zero, for most workloads.</p>
+            <pre><code>perf record -g ./benchmark
+perf report</code></pre>
+            <ul><li>Faster profiling</li><li>Simpler tools</li></ul>
+            <img src="/images/flamegraph.svg" alt="Flame graph">
+            <blockquote>Measure, don't guess.</blockquote>
+            <script>track();</script>
+        </article>
+        <div class="comments">
+            <p>Great post, thanks for writing it, I learned a lot about unwinding today.</p>
+        </div>
+        </body>
+        </html>"#;
+
+    #[test]
+    fn extracts_main_content() {
+        let article = extract(
+            BLOG_POST,
+            "https://rwmj.wordpress.com/2023/02/14/frame-pointers/",
+        )
+        .unwrap();
+
+        assert_eq!(article.title, "Frame pointers vs DWARF – my verdict");
+        assert_eq!(
+            article.blocks,
+            [
+                Block::Heading(1, "Frame pointers vs DWARF".to_string()),
+                Block::Paragraph(
+                    "A couple of weeks ago I wrote a series of blog posts about adding frame pointers to Fedora."
+                        .to_string()
+                ),
+                Block::Heading(2, "Results".to_string()),
+                Block::Paragraph(
+                    "The overhead of frame pointers is about 1%, which is small, but not zero, for most workloads."
+                        .to_string()
+                ),
+                Block::Code("perf record -g ./benchmark\nperf report".to_string()),
+                Block::List {
+                    ordered: false,
+                    items: vec!["Faster profiling".to_string(), "Simpler tools".to_string()]
+                },
+                Block::Image {
+                    url: "https://rwmj.wordpress.com/images/flamegraph.svg".parse().unwrap(),
+                    alt: "Flame graph".to_string()
+                },
+                Block::Quote("Measure, don't guess.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn joins_loose_text_into_paragraphs() {
+        let html = r#"
+            <div class="content">
+                <p>First paragraph is long enough to be counted as content of the page.</p>
+                Text without paragraph <a href="/x">with link</a> continues<br>on the next line.
+                <div>Nested block</div>
+            </div>"#;
+
+        let article = extract(html, "https://example.com/").unwrap();
+        assert_eq!(
+            article.blocks[1..],
+            [
+                Block::Paragraph(
+                    "Text without paragraph with link continues on the next line.".to_string()
+                ),
+                Block::Paragraph("Nested block".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_without_paragraphs_have_no_article() {
+        let html = "<html><body><div><a href='/'>Home</a></div></body></html>";
+        assert_eq!(extract(html, "https://example.com/"), None);
+    }
+}
@@ -0,0 +1,690 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::Read,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use poll_promise::Promise;
+use url::Url;
+
+use crate::{
+    error::{Error, Result},
+    privacy::Privacy,
+};
+
+/// Order in which queued requests are sent, the first has the highest priority.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) enum Priority {
+    Account,  // login and actions of logged in user
+    Visible,  // stories and search results on screen
+    Profile,  // opened user profile and its submissions
+    Thread,   // comments of the opened story
+    Prefetch, // background refreshes and the next page
+    Image,    // images of article read in app
+    Favicon,
+    Sync, // threads downloaded for offline reading
+}
+
+/// How many requests to first party have to fail in a row before the app
+/// is offline, a single timeout does not make it.
+const OFFLINE_AFTER_FAILURES: usize = 3;
+
+/// How many redirects are followed before the request fails.
+const MAX_REDIRECTS: usize = 5;
+
+/// Whether request of promise was cancelled before it was sent.
+pub(crate) fn is_cancelled<T: Send>(promise: &Promise<Result<T>>) -> bool {
+    matches!(promise.ready(), Some(Err(Error::Cancelled)))
+}
+
+/// Http request sent by the scheduler.
+pub(crate) struct Request {
+    pub method: String,
+    pub url: String,
+    pub body: Vec<u8>,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Request {
+    pub(crate) fn get(url: impl ToString) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            body: Vec::new(),
+            headers: [("Accept".to_string(), "*/*".to_string())].into(),
+        }
+    }
+
+    pub(crate) fn post(url: impl ToString, body: Vec<u8>) -> Self {
+        Self {
+            method: "POST".to_string(),
+            url: url.to_string(),
+            body,
+            headers: [
+                ("Accept".to_string(), "*/*".to_string()),
+                (
+                    "Content-Type".to_string(),
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+            ]
+            .into(),
+        }
+    }
+}
+
+/// Response with status below 400, others fail with `Error::HttpStatus`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Response {
+    pub url: String, // after followed redirects
+    pub status: u16,
+    pub status_text: String,
+    pub bytes: Vec<u8>,
+    pub headers: BTreeMap<String, String>, // names in lower case
+}
+
+impl Response {
+    pub(crate) fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.bytes).ok()
+    }
+
+    pub(crate) fn content_type(&self) -> Option<&str> {
+        self.headers.get("content-type").map(String::as_str)
+    }
+}
+
+type Callback = Box<dyn FnOnce(Result<Response>) + Send>;
+
+struct Job {
+    request: Request,
+    priority: Priority,
+    follow_redirects: bool,
+    on_done: Callback,
+}
+
+#[derive(Default)]
+struct Queue {
+    in_flight: usize,
+    pending: BTreeMap<Priority, VecDeque<Job>>,
+    delayed: BTreeMap<(Instant, u64), Job>, // waiting for their deadline before they are queued
+    next_delayed: u64,
+    timer_running: bool, // thread that queues delayed requests once they are due
+    failures: usize,     // requests to first party that could not reach it in a row
+}
+
+/// Sends http requests with at most `max_in_flight` of them running at
+/// once, the rest waits in queue ordered by priority.
+#[derive(Clone)]
+pub(crate) struct Scheduler {
+    max_in_flight: usize,
+    queue: Arc<Mutex<Queue>>,
+    timer: Arc<Condvar>, // wakes timer thread when delayed requests change
+    agent: ureq::Agent,  // does not follow redirects, they are checked by privacy first
+    privacy: Privacy,
+}
+
+impl Scheduler {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            queue: Default::default(),
+            timer: Default::default(),
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(Duration::from_secs(10))
+                .timeout_read(Duration::from_secs(30))
+                .redirects(0)
+                .build(),
+            privacy: Default::default(),
+        }
+    }
+
+    /// Requests not allowed by `privacy` fail with `Error::Blocked` without
+    /// being sent, all requests are allowed by default.
+    pub(crate) fn with_privacy(mut self, privacy: Privacy) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    pub(crate) fn privacy(&self) -> &Privacy {
+        &self.privacy
+    }
+
+    /// Queues request, `on_done` is called from background thread once the
+    /// request finishes or with `Error::Cancelled` if it is cancelled.
+    /// Blocked requests call `on_done` right away, redirects to blocked urls
+    /// fail with `Error::Blocked`.
+    /// Responses with other than 2xx status are errors.
+    pub(crate) fn fetch(
+        &self,
+        request: Request,
+        priority: Priority,
+        on_done: impl FnOnce(Result<Response>) + Send + 'static,
+    ) {
+        self.queue(request, priority, true, on_done);
+    }
+
+    /// Like `fetch` but the request is queued after `delay`, it can be
+    /// cancelled while it waits.
+    pub(crate) fn fetch_after(
+        &self,
+        delay: Duration,
+        request: Request,
+        priority: Priority,
+        on_done: impl FnOnce(Result<Response>) + Send + 'static,
+    ) {
+        if delay.is_zero() {
+            self.queue(request, priority, true, on_done);
+            return;
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        let id = queue.next_delayed;
+        queue.next_delayed += 1;
+        queue.delayed.insert(
+            (Instant::now() + delay, id),
+            Job {
+                request,
+                priority,
+                follow_redirects: true,
+                on_done: Box::new(on_done),
+            },
+        );
+
+        if queue.timer_running {
+            self.timer.notify_all(); // the new deadline might be the earliest
+        } else {
+            queue.timer_running = true;
+            let scheduler = self.clone();
+            std::thread::Builder::new()
+                .name("fetch timer".to_string())
+                .spawn(move || scheduler.run_timer())
+                .expect("cannot spawn timer thread");
+        }
+    }
+
+    /// Queues delayed requests as they become due, the thread ends once no
+    /// request is waiting.
+    fn run_timer(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            let Some(&(deadline, id)) = queue.delayed.keys().next() else {
+                queue.timer_running = false;
+                return;
+            };
+
+            let now = Instant::now();
+            if deadline > now {
+                queue = self.timer.wait_timeout(queue, deadline - now).unwrap().0;
+                continue;
+            }
+
+            let job = queue.delayed.remove(&(deadline, id)).unwrap();
+            drop(queue);
+            self.queue(job.request, job.priority, job.follow_redirects, job.on_done);
+            queue = self.queue.lock().unwrap();
+        }
+    }
+
+    /// Like `fetch` but redirect responses are passed to `on_done` instead of
+    /// being followed, e.g. to read cookies they set.
+    pub(crate) fn fetch_without_redirects(
+        &self,
+        request: Request,
+        priority: Priority,
+        on_done: impl FnOnce(Result<Response>) + Send + 'static,
+    ) {
+        self.queue(request, priority, false, on_done);
+    }
+
+    fn queue(
+        &self,
+        request: Request,
+        priority: Priority,
+        follow_redirects: bool,
+        on_done: impl FnOnce(Result<Response>) + Send + 'static,
+    ) {
+        if !self.privacy.allows(&request.url) {
+            on_done(Err(Error::Blocked));
+            return;
+        }
+
+        self.queue
+            .lock()
+            .unwrap()
+            .pending
+            .entry(priority)
+            .or_default()
+            .push_back(Job {
+                request,
+                priority,
+                follow_redirects,
+                on_done: Box::new(on_done),
+            });
+
+        self.start_pending();
+    }
+
+    /// Drops queued and delayed requests with given priority, requests that
+    /// were already sent finish normally.
+    pub(crate) fn cancel(&self, priority: Priority) {
+        let mut jobs: Vec<Job> = Vec::new();
+        {
+            let mut queue = self.queue.lock().unwrap();
+            jobs.extend(queue.pending.remove(&priority).into_iter().flatten());
+
+            let delayed: Vec<(Instant, u64)> = queue
+                .delayed
+                .iter()
+                .filter(|(_, job)| job.priority == priority)
+                .map(|(key, _)| *key)
+                .collect();
+            for key in delayed {
+                jobs.extend(queue.delayed.remove(&key));
+            }
+            self.timer.notify_all(); // ends the timer when nothing waits
+        }
+
+        for job in jobs {
+            (job.on_done)(Err(Error::Cancelled));
+        }
+    }
+
+    /// Number of (queued, running) requests.
+    pub(crate) fn stats(&self) -> (usize, usize) {
+        let queue = self.queue.lock().unwrap();
+        let queued = queue.pending.values().map(VecDeque::len).sum::<usize>() + queue.delayed.len();
+        (queued, queue.in_flight)
+    }
+
+    /// Whether the last requests to first party could not reach it, other
+    /// sites might be down while we are online.
+    pub(crate) fn is_offline(&self) -> bool {
+        self.queue.lock().unwrap().failures >= OFFLINE_AFTER_FAILURES
+    }
+
+    fn start_pending(&self) {
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap();
+                if queue.in_flight >= self.max_in_flight {
+                    return;
+                }
+
+                match queue.pending.values_mut().find_map(VecDeque::pop_front) {
+                    Some(job) => {
+                        queue.in_flight += 1;
+                        job
+                    }
+                    None => return,
+                }
+            };
+
+            let scheduler = self.clone();
+            std::thread::Builder::new()
+                .name(format!("fetch {}", job.request.url))
+                .spawn(move || {
+                    let first_party = scheduler.privacy.is_first_party(&job.request.url);
+                    let result = scheduler.send(job.request, job.follow_redirects);
+                    if first_party {
+                        scheduler.set_offline(&result);
+                    }
+                    (job.on_done)(result);
+                    scheduler.finished();
+                })
+                .expect("cannot spawn fetch thread");
+        }
+    }
+
+    /// Sends request and follows its redirects while privacy allows them.
+    fn send(&self, mut request: Request, follow_redirects: bool) -> Result<Response> {
+        let mut redirects = 0;
+        loop {
+            let response = send(&self.agent, &request)?;
+            let location = match response.headers.get("location") {
+                Some(location) if follow_redirects && (300..400).contains(&response.status) => {
+                    location
+                }
+                _ => return Ok(response),
+            };
+
+            if redirects == MAX_REDIRECTS {
+                return Err(Error::HttpStatus {
+                    status: response.status,
+                    status_text: response.status_text,
+                });
+            }
+            redirects += 1;
+
+            let from =
+                Url::parse(&request.url).map_err(|error| Error::Decode(error.to_string()))?;
+            let to = from
+                .join(location)
+                .map_err(|error| Error::Decode(format!("Invalid redirect: {}", error)))?;
+            if !self.privacy.allows(to.as_str()) {
+                return Err(Error::Blocked);
+            }
+
+            // session of one site is not sent to another
+            if from.host_str() != to.host_str() {
+                request.headers.retain(|name, _| {
+                    !name.eq_ignore_ascii_case("cookie")
+                        && !name.eq_ignore_ascii_case("authorization")
+                });
+            }
+            // only these keep method and body of the original request
+            if !matches!(response.status, 307 | 308) {
+                request.method = "GET".to_string();
+                request.body.clear();
+            }
+            request.url = to.to_string();
+        }
+    }
+
+    fn set_offline(&self, result: &Result<Response>) {
+        let unreachable = matches!(result, Err(Error::Network(_) | Error::Timeout));
+        let mut queue = self.queue.lock().unwrap();
+        if unreachable {
+            queue.failures += 1;
+        } else {
+            queue.failures = 0;
+        }
+    }
+
+    fn finished(&self) {
+        self.queue.lock().unwrap().in_flight -= 1;
+        self.start_pending();
+    }
+}
+
+fn send(agent: &ureq::Agent, request: &Request) -> Result<Response> {
+    let mut builder = agent.request(&request.method, &request.url);
+    for (name, value) in &request.headers {
+        builder = builder.set(name, value);
+    }
+
+    let response = if request.body.is_empty() {
+        builder.call()
+    } else {
+        builder.send_bytes(&request.body)
+    };
+
+    let response = match response {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => {
+            // the body of error responses is not what the caller expects, do not pass it on
+            return Err(Error::HttpStatus {
+                status,
+                status_text: response.status_text().to_string(),
+            });
+        }
+        Err(ureq::Error::Transport(error)) => return Err(Error::from_transport(error)),
+    };
+
+    let url = response.get_url().to_string();
+    let status = response.status();
+    let status_text = response.status_text().to_string();
+    let headers = response
+        .headers_names()
+        .iter()
+        .filter_map(|name| {
+            Some((
+                name.to_ascii_lowercase(),
+                response.header(name)?.to_string(),
+            ))
+        })
+        .collect();
+
+    let mut bytes = Vec::new();
+    response.into_reader().read_to_end(&mut bytes)?;
+
+    Ok(Response {
+        url,
+        status,
+        status_text,
+        bytes,
+        headers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        privacy::PrivacySettings,
+        test_server::{Response, TestServer},
+    };
+
+    fn fetch(
+        scheduler: &Scheduler,
+        server: &TestServer,
+        path: &str,
+        priority: Priority,
+    ) -> Receiver<Result<String>> {
+        let (sender, receiver) = mpsc::channel();
+        scheduler.fetch(Request::get(server.url(path)), priority, move |result| {
+            let _ = sender.send(result.map(|response| response.url));
+        });
+        receiver
+    }
+
+    #[test]
+    fn sends_queued_requests_by_priority() {
+        let (release, blocked) = mpsc::sync_channel::<()>(0);
+        let blocked = Mutex::new(blocked);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let server = {
+            let order = order.clone();
+            TestServer::start(move |request| {
+                if request.path == "/blocker" {
+                    blocked.lock().unwrap().recv().unwrap();
+                } else {
+                    order.lock().unwrap().push(request.path.clone());
+                }
+                Response::json("null")
+            })
+        };
+
+        let scheduler = Scheduler::new(1);
+        let blocker = fetch(&scheduler, &server, "/blocker", Priority::Visible);
+        let favicon = fetch(&scheduler, &server, "/favicon", Priority::Favicon);
+        let comment = fetch(&scheduler, &server, "/comment", Priority::Thread);
+        let story = fetch(&scheduler, &server, "/story", Priority::Visible);
+        assert_eq!(scheduler.stats(), (3, 1));
+
+        release.send(()).unwrap();
+        for receiver in [blocker, favicon, comment, story] {
+            receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), ["/story", "/comment", "/favicon"]);
+    }
+
+    #[test]
+    fn reports_status_without_body() {
+        let server = TestServer::start(|_| Response::new(500, "text/html", "<h1>Oops</h1>"));
+        let scheduler = Scheduler::new(1);
+
+        let result = fetch(&scheduler, &server, "/item/1.json", Priority::Visible).recv();
+        assert_eq!(
+            result.unwrap(),
+            Err(Error::HttpStatus {
+                status: 500,
+                status_text: "Test".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn checks_privacy_of_redirects() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/story" => Response::new(302, "text/html", "").with_header("Location", "/article"),
+            "/tracked" => Response::new(302, "text/html", "")
+                .with_header("Location", "http://tracker.example/pixel"),
+            _ => Response::json("null"),
+        });
+        let scheduler = Scheduler::new(1).with_privacy(Privacy::new(PrivacySettings {
+            deny: vec!["tracker.example".to_string()],
+            ..Default::default()
+        }));
+        let timeout = Duration::from_secs(5);
+
+        let story = fetch(&scheduler, &server, "/story", Priority::Visible);
+        assert_eq!(
+            story.recv_timeout(timeout).unwrap(),
+            Ok(server.url("/article"))
+        );
+
+        let tracked = fetch(&scheduler, &server, "/tracked", Priority::Visible);
+        assert_eq!(tracked.recv_timeout(timeout).unwrap(), Err(Error::Blocked));
+        scheduler.privacy().with_blocked(|blocked| {
+            assert_eq!(blocked.back().unwrap().url, "http://tracker.example/pixel");
+        });
+    }
+
+    #[test]
+    fn cancels_queued_requests() {
+        let (release, blocked) = mpsc::sync_channel::<()>(0);
+        let blocked = Mutex::new(blocked);
+        let server = TestServer::start(move |request| {
+            if request.path == "/blocker" {
+                blocked.lock().unwrap().recv().unwrap();
+            }
+            Response::json("null")
+        });
+
+        let scheduler = Scheduler::new(1);
+        let blocker = fetch(&scheduler, &server, "/blocker", Priority::Thread);
+        let comment = fetch(&scheduler, &server, "/comment", Priority::Thread);
+        let story = fetch(&scheduler, &server, "/story", Priority::Visible);
+
+        scheduler.cancel(Priority::Thread);
+        assert_eq!(comment.recv().unwrap(), Err(Error::Cancelled));
+
+        // running request is not affected
+        release.send(()).unwrap();
+        let timeout = Duration::from_secs(5);
+        assert!(blocker.recv_timeout(timeout).unwrap().is_ok());
+        assert!(story.recv_timeout(timeout).unwrap().is_ok());
+    }
+
+    #[test]
+    fn cancels_delayed_requests() {
+        let server = TestServer::start(|_| Response::json("null"));
+        let scheduler = Scheduler::new(1);
+
+        let (sender, receiver) = mpsc::channel();
+        scheduler.fetch_after(
+            Duration::from_secs(60),
+            Request::get(server.url("/retry")),
+            Priority::Thread,
+            move |result| {
+                let _ = sender.send(result.map(|_| ()));
+            },
+        );
+        assert_eq!(scheduler.stats(), (1, 0));
+
+        scheduler.cancel(Priority::Thread);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Err(Error::Cancelled)
+        );
+        assert_eq!(scheduler.stats(), (0, 0));
+
+        // timer thread does not keep waiting for the cancelled request
+        for _ in 0..200 {
+            if !scheduler.queue.lock().unwrap().timer_running {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!scheduler.queue.lock().unwrap().timer_running);
+    }
+
+    #[test]
+    fn sends_delayed_requests_when_due() {
+        let server = TestServer::start(|_| Response::json("null"));
+        let scheduler = Scheduler::new(1);
+
+        let (sender, receiver) = mpsc::channel();
+        for (delay, path) in [(300, "/later"), (50, "/sooner")] {
+            let sender = sender.clone();
+            scheduler.fetch_after(
+                Duration::from_millis(delay),
+                Request::get(server.url(path)),
+                Priority::Thread,
+                move |result| {
+                    let _ = sender.send(result.map(|response| response.url));
+                },
+            );
+        }
+        assert_eq!(scheduler.stats(), (2, 0));
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            Ok(server.url("/sooner"))
+        );
+        assert_eq!(
+            receiver.recv_timeout(timeout).unwrap(),
+            Ok(server.url("/later"))
+        );
+    }
+
+    #[test]
+    fn goes_offline_when_server_is_unreachable() {
+        let server = TestServer::start(|_| Response::json("null"));
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let privacy = Privacy::default();
+        privacy.add_first_party(&server.url("/"));
+        let scheduler = Scheduler::new(1).with_privacy(privacy);
+        let timeout = Duration::from_secs(5);
+
+        let unreachable = |host| {
+            let (sender, receiver) = mpsc::channel();
+            scheduler.fetch(
+                Request::get(format!("http://{}:{}/", host, closed_port)),
+                Priority::Visible,
+                move |result| {
+                    let _ = sender.send(result.map(|_| ()));
+                },
+            );
+            receiver.recv_timeout(timeout).unwrap()
+        };
+
+        assert!(!scheduler.is_offline());
+
+        // other sites might be down while we are online
+        for _ in 0..OFFLINE_AFTER_FAILURES {
+            assert!(matches!(unreachable("localhost"), Err(Error::Network(_))));
+        }
+        assert!(!scheduler.is_offline());
+
+        // a single failure is not enough, a success in between starts over
+        assert!(matches!(unreachable("127.0.0.1"), Err(Error::Network(_))));
+        assert!(!scheduler.is_offline());
+        let story = fetch(&scheduler, &server, "/story", Priority::Prefetch);
+        assert!(story.recv_timeout(timeout).unwrap().is_ok());
+
+        for _ in 0..OFFLINE_AFTER_FAILURES {
+            assert!(matches!(unreachable("127.0.0.1"), Err(Error::Network(_))));
+        }
+        assert!(scheduler.is_offline());
+
+        let story = fetch(&scheduler, &server, "/story", Priority::Prefetch);
+        assert!(story.recv_timeout(timeout).unwrap().is_ok());
+        assert!(!scheduler.is_offline());
+    }
+}
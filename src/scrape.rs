@@ -0,0 +1,368 @@
+//! Lists of the site that have no counterpart in the api, e.g. past front
+//! pages, best comments, stories from a site and threads of a user. Their
+//! pages are scraped into items ranked the way the site ranks them.
+
+use eframe::egui;
+use scraper::{Html, Selector};
+use time::{Date, Month, OffsetDateTime, UtcOffset};
+use tracing::warn;
+use url::Url;
+
+use crate::{
+    error::{self, Error},
+    feeds::FeedSource,
+    scheduler::{Priority, Request, Scheduler},
+    HnItemId,
+};
+
+/// How many pages of list are loaded, following its "More" links.
+const PAGES: usize = 3;
+
+/// The official site, logged in account uses it too.
+const SITE_URL: &str = "https://news.ycombinator.com/";
+
+/// Site url from `HACKER_NEWSFEED_SITE_URL` environment variable if set and
+/// valid, otherwise the official site.
+pub(crate) fn site_url_from_env() -> Url {
+    if let Ok(site_url) = std::env::var("HACKER_NEWSFEED_SITE_URL") {
+        match Url::parse(&site_url) {
+            Ok(site_url) => return site_url,
+            Err(error) => warn!("Invalid site url {}: {}", site_url, error),
+        }
+    }
+
+    Url::parse(SITE_URL).unwrap()
+}
+
+/// `url` ending with slash so paths of the site are joined to it.
+pub(crate) fn with_trailing_slash(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url
+}
+
+/// The first day the site has front page for.
+pub(crate) fn first_front_page() -> Date {
+    Date::from_calendar_date(2007, Month::February, 19).unwrap()
+}
+
+/// Day of the site at `time`, its days end in US Pacific time. Standard time
+/// is used all year so summer days are over an hour later than they could.
+pub(crate) fn site_date(time: OffsetDateTime) -> Date {
+    let offset = UtcOffset::from_hms(-8, 0, 0).unwrap();
+    time.to_offset(offset).date()
+}
+
+/// Stories that made it to the front page on the day, in the order of their
+/// best rank.
+pub(crate) fn front_page(day: Date) -> FeedSource {
+    FeedSource::Site(format!("front?day={}", day))
+}
+
+/// Item listed on page of the site.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RankedItem {
+    pub id: HnItemId,
+    pub rank: usize, // shown next to stories, position on the page for comments
+}
+
+/// Items of one page in the order of their rank and the link to the next one.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ScrapedPage {
+    pub items: Vec<RankedItem>,
+    pub more: Option<String>,
+}
+
+/// Pages of Hacker News site, read without logging in.
+pub(crate) struct Site {
+    base_url: Url,
+    scheduler: Scheduler,
+}
+
+impl Site {
+    pub(crate) fn new(base_url: Url, scheduler: Scheduler) -> Self {
+        let base_url = with_trailing_slash(base_url);
+        scheduler.privacy().add_first_party(base_url.as_str());
+
+        Self {
+            base_url,
+            scheduler,
+        }
+    }
+
+    /// Uses site url from `HACKER_NEWSFEED_SITE_URL` environment variable if
+    /// set and valid, otherwise the official site.
+    pub(crate) fn from_env(scheduler: Scheduler) -> Self {
+        Self::new(site_url_from_env(), scheduler)
+    }
+
+    /// Loads items listed at `path` of the site like `front?day=2023-02-14`
+    /// from its first `PAGES` pages, `on_done` is called from background
+    /// thread with them in the order of their rank.
+    pub(crate) fn items(
+        &self,
+        ctx: egui::Context,
+        path: &str,
+        priority: Priority,
+        on_done: impl FnOnce(error::Result<Vec<RankedItem>>) + Send + 'static,
+    ) {
+        match self.base_url.join(path) {
+            Ok(url) => Pages {
+                ctx,
+                scheduler: self.scheduler.clone(),
+                priority,
+                url: url.to_string(),
+                items: Vec::new(),
+                remaining: PAGES,
+            }
+            .send(Box::new(on_done)),
+            Err(error) => on_done(Err(Error::InvalidRequest(error.to_string()))),
+        }
+    }
+}
+
+type Callback = Box<dyn FnOnce(error::Result<Vec<RankedItem>>) + Send>;
+
+/// List whose pages are requested one after another.
+struct Pages {
+    ctx: egui::Context,
+    scheduler: Scheduler,
+    priority: Priority,
+    url: String,
+    items: Vec<RankedItem>,
+    remaining: usize,
+}
+
+impl Pages {
+    fn send(mut self, on_done: Callback) {
+        let scheduler = self.scheduler.clone();
+        let request = Request::get(&self.url);
+
+        scheduler.fetch(request, self.priority, move |response| {
+            let page = response.and_then(|response| {
+                let html = response
+                    .text()
+                    .ok_or_else(|| Error::Decode("Page is not text".to_string()))?;
+                Ok(parse_page(&response.url, html))
+            });
+
+            match page {
+                Ok(page) => {
+                    // items move to the next page while the list is loaded,
+                    // they keep the rank they were seen with first
+                    for item in page.items {
+                        if !self.items.iter().any(|known| known.id == item.id) {
+                            self.items.push(item);
+                        }
+                    }
+                    self.remaining -= 1;
+
+                    match page.more {
+                        Some(more) if self.remaining > 0 => {
+                            self.url = more;
+                            self.send(on_done);
+                        }
+                        _ => {
+                            self.ctx.request_repaint(); // wake up UI thread
+                            on_done(Ok(self.items));
+                        }
+                    }
+                }
+                Err(error) => {
+                    self.ctx.request_repaint(); // wake up UI thread
+                    on_done(Err(error));
+                }
+            }
+        });
+    }
+}
+
+/// Reads items listed on page of the site, threads of user list their
+/// comments with replies to them and only the comments are kept.
+pub(crate) fn parse_page(page_url: &str, html: &str) -> ScrapedPage {
+    let html = Html::parse_document(html);
+    let rows = Selector::parse("tr.athing[id]").unwrap();
+    let rank = Selector::parse("span.rank").unwrap();
+    let indent = Selector::parse("td.ind[indent]").unwrap();
+    let more = Selector::parse("a.morelink[href]").unwrap();
+
+    let mut items = Vec::new();
+
+    for row in html.select(&rows) {
+        let Some(id) = row.value().attr("id").and_then(|id| id.parse().ok()) else {
+            continue;
+        };
+
+        let reply = row
+            .select(&indent)
+            .next()
+            .and_then(|ind| ind.value().attr("indent"))
+            .map_or(false, |indent| indent != "0");
+        if reply {
+            continue;
+        }
+
+        let rank = row
+            .select(&rank)
+            .next()
+            .and_then(|rank| {
+                rank.text()
+                    .collect::<String>()
+                    .trim_end_matches('.')
+                    .parse()
+                    .ok()
+            })
+            .unwrap_or(items.len() + 1);
+
+        items.push(RankedItem {
+            id: HnItemId(id),
+            rank,
+        });
+    }
+
+    items.sort_by_key(|item| item.rank);
+
+    let more = html
+        .select(&more)
+        .next()
+        .and_then(|more| more.value().attr("href"))
+        .and_then(|href| Url::parse(page_url).ok()?.join(href).ok())
+        .map(String::from);
+
+    ScrapedPage { items, more }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    const FRONT_PAGE: &str = r#"<html><body><table id="hnmain"><tr><td><table border="0" class="itemlist">
+        <tr class="athing" id="34791256"><td align="right" valign="top" class="title"><span class="rank">1.</span></td><td valign="top" class="votelinks"><center><a id="up_34791256" href="vote?id=34791256&amp;how=up&amp;goto=front%3Fday%3D2023-02-15"><div class="votearrow" title="upvote"></div></a></center></td><td class="title"><span class="titleline"><a href="https://example.com/">Example story</a><span class="sitebit comhead"> (<a href="from?site=example.com"><span class="sitestr">example.com</span></a>)</span></span></td></tr>
+        <tr><td colspan="2"></td><td class="subtext"><span class="score" id="score_34791256">512 points</span> by <a href="user?id=pg" class="hnuser">pg</a> | <a href="item?id=34791256">203&nbsp;comments</a></td></tr>
+        <tr class="spacer" style="height:5px"></tr>
+        <tr class="athing" id="34790000"><td align="right" valign="top" class="title"><span class="rank">2.</span></td><td class="title"><span class="titleline"><a href="item?id=34790000">Ask HN: Something?</a></span></td></tr>
+        <tr><td colspan="2"></td><td class="subtext"><span class="score" id="score_34790000">90 points</span></td></tr>
+        <tr class="morespace" style="height:10px"></tr>
+        <tr><td colspan="2"></td><td class="title"><a href="front?day=2023-02-15&amp;p=2" class="morelink" rel="next">More</a></td></tr>
+        </table></td></tr></table></body></html>"#;
+
+    // the last story of the first page moved to the second one
+    const SECOND_FRONT_PAGE: &str = r#"<table><tr class="athing" id="34790000"><td><span class="rank">31.</span></td></tr>
+        <tr class="athing" id="34780000"><td><span class="rank">32.</span></td></tr></table>"#;
+
+    fn ranked(id: usize, rank: usize) -> RankedItem {
+        RankedItem {
+            id: HnItemId(id),
+            rank,
+        }
+    }
+
+    const THREADS_PAGE: &str = r#"<html><body><table class="comment-tree">
+        <tr class="athing comtr" id="34800001"><td><table border="0"><tr><td class="ind" indent="0"><img src="s.gif" height="1" width="0"></td><td class="default"><span class="comhead"><a href="user?id=pg" class="hnuser">pg</a></span><div class="comment"><span class="commtext c00">First comment</span></div></td></tr></table></td></tr>
+        <tr class="athing comtr" id="34800002"><td><table border="0"><tr><td class="ind" indent="1"><img src="s.gif" height="1" width="40"></td><td class="default"><span class="commtext c00">Reply to it</span></td></tr></table></td></tr>
+        <tr class="athing comtr" id="34800003"><td><table border="0"><tr><td class="ind" indent="0"><img src="s.gif" height="1" width="0"></td><td class="default"><span class="commtext c00">Second comment</span></td></tr></table></td></tr>
+        </table><a href="threads?id=pg&amp;next=34800003" class="morelink" rel="next">More</a></body></html>"#;
+
+    const BEST_COMMENTS_PAGE: &str = r#"<html><body><table border="0">
+        <tr class="athing" id="34700001"><td><table border="0"><tr><td class="ind"></td><td class="default"><span class="commtext c00">Best</span></td></tr></table></td></tr>
+        <tr class="athing" id="34700002"><td><table border="0"><tr><td class="ind"></td><td class="default"><span class="commtext c00">Second best</span></td></tr></table></td></tr>
+        </table></body></html>"#;
+
+    #[test]
+    fn parses_ranked_pages() {
+        let front = parse_page(
+            "https://news.ycombinator.com/front?day=2023-02-15",
+            FRONT_PAGE,
+        );
+        assert_eq!(
+            front,
+            ScrapedPage {
+                items: vec![ranked(34791256, 1), ranked(34790000, 2)],
+                more: Some("https://news.ycombinator.com/front?day=2023-02-15&p=2".to_string()),
+            }
+        );
+
+        // ranks go on from the previous page
+        let second = parse_page(
+            "https://news.ycombinator.com/front?day=2023-02-15&p=2",
+            SECOND_FRONT_PAGE,
+        );
+        assert_eq!(second.items, [ranked(34790000, 31), ranked(34780000, 32)]);
+
+        // comments are ranked by their position
+        let threads = parse_page("https://news.ycombinator.com/threads?id=pg", THREADS_PAGE);
+        assert_eq!(threads.items, [ranked(34800001, 1), ranked(34800003, 2)]);
+        assert_eq!(
+            threads.more.as_deref(),
+            Some("https://news.ycombinator.com/threads?id=pg&next=34800003")
+        );
+
+        let best = parse_page(
+            "https://news.ycombinator.com/bestcomments",
+            BEST_COMMENTS_PAGE,
+        );
+        assert_eq!(best.items.len(), 2);
+        assert_eq!(best.more, None);
+
+        // items are ordered by their rank, not by their place in the page
+        let reordered = parse_page(
+            "https://news.ycombinator.com/front",
+            r#"<table><tr class="athing" id="2"><td><span class="rank">2.</span></td></tr>
+            <tr class="athing" id="1"><td><span class="rank">1.</span></td></tr></table>"#,
+        );
+        assert_eq!(reordered.items, [ranked(1, 1), ranked(2, 2)]);
+    }
+
+    #[test]
+    fn days_end_in_pacific_time() {
+        let day = Date::from_calendar_date(2023, Month::February, 16).unwrap();
+        let at = |hour| day.with_hms(hour, 0, 0).unwrap().assume_utc();
+
+        assert_eq!(site_date(at(7)), day.previous_day().unwrap());
+        assert_eq!(site_date(at(8)), day);
+    }
+
+    #[test]
+    fn formats_front_page_day() {
+        let day = Date::from_calendar_date(2023, Month::February, 5).unwrap();
+        assert_eq!(
+            front_page(day),
+            FeedSource::Site("front?day=2023-02-05".to_string())
+        );
+    }
+
+    #[test]
+    fn follows_more_links() {
+        let server = TestServer::start(|request| match request.query.as_str() {
+            "day=2023-02-15" => Response::html(FRONT_PAGE),
+            "day=2023-02-15&p=2" => Response::html(SECOND_FRONT_PAGE),
+            _ => Response::not_found(),
+        });
+
+        let site = Site::new(server.url("/").parse().unwrap(), Scheduler::new(1));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        site.items(
+            egui::Context::default(),
+            "front?day=2023-02-15",
+            Priority::Visible,
+            move |items| {
+                let _ = sender.send(items);
+            },
+        );
+
+        // the moved story keeps the rank it was seen with first
+        assert_eq!(
+            receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .unwrap()
+                .unwrap(),
+            [
+                ranked(34791256, 1),
+                ranked(34790000, 2),
+                ranked(34780000, 32)
+            ]
+        );
+    }
+}
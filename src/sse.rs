@@ -0,0 +1,647 @@
+//! Client for Firebase streaming api, see
+//! https://firebase.google.com/docs/reference/rest/database#section-streaming
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use eframe::egui;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::backoff::Backoff;
+
+/// Change of json document at `path`, path `/` is the whole document.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Event {
+    Put { path: String, data: Value },   // replace value at path
+    Patch { path: String, data: Value }, // replace children of value at path
+}
+
+#[derive(Deserialize)]
+struct EventData {
+    path: String,
+    data: Value,
+}
+
+impl Event {
+    fn parse(event: &str, data: &str) -> Option<Self> {
+        let parse_data = || match serde_json::from_str::<EventData>(data) {
+            Ok(data) => Some(data),
+            Err(error) => {
+                warn!("Invalid {} event data {}: {}", event, data, error);
+                None
+            }
+        };
+
+        match event {
+            "put" => parse_data().map(|data| Event::Put {
+                path: data.path,
+                data: data.data,
+            }),
+            "patch" => parse_data().map(|data| Event::Patch {
+                path: data.path,
+                data: data.data,
+            }),
+            // keep-alive, cancel and auth_revoked carry no data changes
+            _ => None,
+        }
+    }
+
+    /// Applies the change to `document`.
+    pub(crate) fn apply(&self, document: &mut Value) {
+        match self {
+            Event::Put { path, data } => set(document, path_segments(path), data.clone()),
+            Event::Patch { path, data } => {
+                if let Value::Object(children) = data {
+                    for (key, value) in children {
+                        let segments = path_segments(path).chain(std::iter::once(key.as_str()));
+                        set(document, segments, value.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn path_segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Sets `value` at path in `document`, creating objects along the way,
+/// `null` removes the value like firebase does.
+fn set<'a>(document: &mut Value, mut segments: impl Iterator<Item = &'a str>, value: Value) {
+    let segment = match segments.next() {
+        Some(segment) => segment,
+        None => {
+            *document = value;
+            return;
+        }
+    };
+
+    // firebase stores arrays as objects with numeric keys
+    if let (Value::Array(array), Ok(index)) = (&mut *document, segment.parse::<usize>()) {
+        if index >= array.len() {
+            array.resize(index + 1, Value::Null);
+        }
+        set(&mut array[index], segments, value);
+        return;
+    }
+
+    if !document.is_object() {
+        *document = Value::Object(Default::default());
+    }
+
+    if let Value::Object(object) = document {
+        let child = object.entry(segment).or_insert(Value::Null);
+        set(child, segments, value);
+        if child.is_null() {
+            object.remove(segment);
+        }
+    }
+}
+
+#[derive(Default)]
+struct ConnectionState {
+    socket: Option<TcpStream>,
+    live: bool, // connected and receiving events
+    closed: bool,
+}
+
+/// Socket of the stream, shut down when the subscription is dropped so the
+/// read blocked on it returns right away. Waiting for reconnect ends too.
+#[derive(Clone, Default)]
+struct Connection(Arc<(Mutex<ConnectionState>, Condvar)>);
+
+impl Connection {
+    fn set_socket(&self, socket: TcpStream) {
+        let mut state = self.0 .0.lock().unwrap();
+        if state.closed {
+            let _ = socket.shutdown(Shutdown::Both);
+        } else {
+            state.socket = Some(socket);
+        }
+    }
+
+    fn set_live(&self, live: bool) {
+        self.0 .0.lock().unwrap().live = live;
+    }
+
+    fn close(&self) {
+        let mut state = self.0 .0.lock().unwrap();
+        state.closed = true;
+        state.live = false;
+        if let Some(socket) = state.socket.take() {
+            let _ = socket.shutdown(Shutdown::Both);
+        }
+        self.0 .1.notify_all();
+    }
+
+    fn is_live(&self) -> bool {
+        self.0 .0.lock().unwrap().live
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0 .0.lock().unwrap().closed
+    }
+
+    /// Waits for `delay` unless the connection is closed first, returns
+    /// whether it is closed.
+    fn wait(&self, delay: Duration) -> bool {
+        let (state, closed) = &*self.0;
+        let state = closed
+            .wait_timeout_while(state.lock().unwrap(), delay, |state| !state.closed)
+            .unwrap()
+            .0;
+        state.closed
+    }
+}
+
+/// Tls connector that keeps socket of the connection it secures, ureq does
+/// not give access to it otherwise.
+struct ClosableTls {
+    config: Arc<rustls::ClientConfig>,
+    connection: Connection,
+}
+
+impl ClosableTls {
+    fn new(connection: Connection) -> Self {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|anchor| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        Self {
+            config: Arc::new(config),
+            connection,
+        }
+    }
+}
+
+impl ureq::TlsConnector for ClosableTls {
+    fn connect(
+        &self,
+        dns_name: &str,
+        io: Box<dyn ureq::ReadWrite>,
+    ) -> Result<Box<dyn ureq::ReadWrite>, ureq::Error> {
+        if let Some(socket) = io.socket().and_then(|socket| socket.try_clone().ok()) {
+            self.connection.set_socket(socket);
+        }
+
+        self.config.connect(dns_name, io)
+    }
+}
+
+/// Streams events from `url` on background thread, reconnects with
+/// exponential backoff when connection fails or closes. The stream stops
+/// when the subscription is dropped.
+pub(crate) struct Subscription {
+    url: String,
+    receiver: Receiver<Event>,
+    connection: Connection,
+}
+
+impl Subscription {
+    pub(crate) fn new(ctx: egui::Context, url: String, backoff: Backoff) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let connection = Connection::default();
+
+        {
+            let url = url.clone();
+            let connection = connection.clone();
+            std::thread::Builder::new()
+                .name(format!("sse {}", url))
+                .spawn(move || stream(ctx, &url, sender, &connection, backoff))
+                .expect("cannot spawn sse thread");
+        }
+
+        Self {
+            url,
+            receiver,
+            connection,
+        }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Events received since the last call.
+    pub(crate) fn events(&self) -> Vec<Event> {
+        self.receiver.try_iter().collect()
+    }
+
+    /// Whether the stream is connected, it reconnects in the background
+    /// when it is not.
+    pub(crate) fn is_live(&self) -> bool {
+        self.connection.is_live()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.connection.close();
+    }
+}
+
+/// Json document kept up to date by events from subscription.
+pub(crate) struct Watch {
+    subscription: Subscription,
+    document: Value,
+}
+
+impl Watch {
+    pub(crate) fn new(subscription: Subscription) -> Self {
+        Self {
+            subscription,
+            document: Value::Null,
+        }
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        self.subscription.url()
+    }
+
+    pub(crate) fn is_live(&self) -> bool {
+        self.subscription.is_live()
+    }
+
+    /// Applies received events and returns the document if it changed.
+    pub(crate) fn poll(&mut self) -> Option<&Value> {
+        let events = self.subscription.events();
+        for event in &events {
+            event.apply(&mut self.document);
+        }
+
+        if events.is_empty() {
+            None
+        } else {
+            Some(&self.document)
+        }
+    }
+}
+
+/// Firebase stores arrays as objects with numeric keys, this reads both
+/// representations and skips removed entries.
+pub(crate) fn list<T: DeserializeOwned>(document: &Value) -> Vec<T> {
+    let values: Vec<&Value> = match document {
+        Value::Array(array) => array.iter().collect(),
+        Value::Object(object) => {
+            let mut entries: Vec<(usize, &Value)> = object
+                .iter()
+                .filter_map(|(key, value)| Some((key.parse().ok()?, value)))
+                .collect();
+            entries.sort_by_key(|(index, _)| *index);
+            entries.into_iter().map(|(_, value)| value).collect()
+        }
+        _ => Vec::new(),
+    };
+
+    values
+        .into_iter()
+        .filter(|value| !value.is_null())
+        .filter_map(|value| T::deserialize(value).ok())
+        .collect()
+}
+
+/// Firebase sends keep-alive every 30 seconds, no data for longer means the
+/// connection is dead.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn stream(
+    ctx: egui::Context,
+    url: &str,
+    sender: Sender<Event>,
+    connection: &Connection,
+    mut backoff: Backoff,
+) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_read(READ_TIMEOUT)
+        .tls_connector(Arc::new(ClosableTls::new(connection.clone())))
+        .build();
+
+    while !connection.is_closed() {
+        let body = if url.starts_with("http:") {
+            connect_plain(url, connection).map_err(|error| error.to_string())
+        } else {
+            agent
+                .get(url)
+                .set("Accept", "text/event-stream")
+                .call()
+                .map(|response| {
+                    Box::new(BufReader::new(response.into_reader())) as Box<dyn BufRead + Send>
+                })
+                .map_err(|error| error.to_string())
+        };
+
+        match body {
+            Ok(body) => {
+                debug!("Connected to event stream {}", url);
+                connection.set_live(true);
+                let result = read_events(body, &ctx, &sender, connection, &mut backoff);
+                connection.set_live(false);
+                if result.is_err() {
+                    return; // subscription was dropped
+                }
+            }
+            Err(error) => warn!("Cannot connect to event stream {}: {}", url, error),
+        }
+
+        let delay = backoff.next_delay();
+        debug!("Reconnecting to event stream {} in {:?}", url, delay);
+        if connection.wait(delay) {
+            return;
+        }
+    }
+}
+
+/// Sends request for stream over plain http, ureq does not give access to
+/// sockets of plain connections so they could not be closed. Redirects are
+/// not followed, servers of the api are reached over https anyway.
+fn connect_plain(url: &str, connection: &Connection) -> io::Result<Box<dyn BufRead + Send>> {
+    let invalid = |error: String| io::Error::new(io::ErrorKind::InvalidInput, error);
+    let url = Url::parse(url).map_err(|error| invalid(error.to_string()))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| invalid("Url has no host".to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let mut socket = TcpStream::connect((host, port))?;
+    socket.set_read_timeout(Some(READ_TIMEOUT))?;
+    connection.set_socket(socket.try_clone()?);
+
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    // http 1.0 responses are not chunked, the body is read until the server closes it
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}:{}\r\nAccept: text/event-stream\r\n\r\n",
+        path, host, port
+    );
+    socket.write_all(request.as_bytes())?;
+
+    let mut reader = BufReader::new(socket);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.split_whitespace().nth(1) != Some("200") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected response {}", line.trim_end()),
+        ));
+    }
+
+    // headers end with empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    Ok(Box::new(reader))
+}
+
+/// Reads events until the stream ends, fails with error when the receiving
+/// side is gone.
+fn read_events(
+    reader: impl BufRead,
+    ctx: &egui::Context,
+    sender: &Sender<Event>,
+    connection: &Connection,
+    backoff: &mut Backoff,
+) -> Result<(), mpsc::SendError<Event>> {
+    let mut event = String::new();
+    let mut data = String::new();
+
+    for line in reader.lines() {
+        if connection.is_closed() {
+            return Ok(());
+        }
+
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                warn!("Event stream interrupted: {}", error);
+                return Ok(());
+            }
+        };
+
+        if line.is_empty() {
+            // empty line dispatches the event
+            if let Some(event) = Event::parse(&event, &data) {
+                sender.send(event)?;
+                ctx.request_repaint(); // wake up UI thread
+            }
+
+            // we got complete event, the connection works
+            backoff.reset();
+            event.clear();
+            data.clear();
+        } else if let Some(value) = line.strip_prefix("event:") {
+            event = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            if !data.is_empty() {
+                data.push('\n');
+            }
+            data.push_str(value.trim_start());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        net::TcpListener,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    fn apply(document: &mut Value, event: &str, data: &str) {
+        Event::parse(event, data).unwrap().apply(document);
+    }
+
+    #[test]
+    fn applies_put_and_patch_to_list() {
+        let mut document = Value::Null;
+        apply(&mut document, "put", r#"{"path":"/","data":[1,2,3]}"#);
+        assert_eq!(document, json!([1, 2, 3]));
+
+        apply(&mut document, "put", r#"{"path":"/1","data":5}"#);
+        assert_eq!(document, json!([1, 5, 3]));
+
+        apply(
+            &mut document,
+            "patch",
+            r#"{"path":"/","data":{"0":7,"3":9}}"#,
+        );
+        assert_eq!(document, json!([7, 5, 3, 9]));
+    }
+
+    #[test]
+    fn applies_put_and_patch_to_item() {
+        let mut document = json!({"id": 1, "score": 10, "kids": [2]});
+        apply(
+            &mut document,
+            "patch",
+            r#"{"path":"/","data":{"score":11,"descendants":2}}"#,
+        );
+        apply(&mut document, "put", r#"{"path":"/kids/1","data":3}"#);
+        apply(&mut document, "put", r#"{"path":"/id","data":null}"#);
+        assert_eq!(
+            document,
+            json!({"score": 11, "descendants": 2, "kids": [2, 3]})
+        );
+    }
+
+    #[test]
+    fn reads_list_from_object() {
+        let document = json!({"1": 20, "0": 10, "2": null, "10": 30});
+        assert_eq!(list::<usize>(&document), vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn ignores_keep_alive() {
+        assert_eq!(Event::parse("keep-alive", "null"), None);
+    }
+
+    #[test]
+    fn streams_events_and_reconnects() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let connections = connections.clone();
+            TestServer::start(move |request| {
+                assert_eq!(request.header("accept"), Some("text/event-stream"));
+
+                // every connection sends events and closes to force reconnect
+                let body = match connections.fetch_add(1, Ordering::SeqCst) {
+                    0 => "event: put\ndata: {\"path\":\"/\",\"data\":[1,2]}\n\nevent: keep-alive\ndata: null\n\n",
+                    _ => "event: patch\ndata: {\"path\":\"/\",\ndata: \"data\":{\"2\":3}}\n\n",
+                };
+                Response::new(200, "text/event-stream", body)
+            })
+        };
+
+        let subscription = Subscription::new(
+            egui::Context::default(),
+            server.url("/v0/topstories.json"),
+            Backoff::new(Duration::from_millis(10), Duration::from_millis(10)),
+        );
+
+        let mut events = Vec::new();
+        for _ in 0..200 {
+            events.extend(subscription.events());
+            if events.len() >= 2 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut document = Value::Null;
+        for event in &events[..2] {
+            event.apply(&mut document);
+        }
+        assert_eq!(document, json!([1, 2, 3]));
+        assert!(connections.load(Ordering::SeqCst) >= 2);
+    }
+
+    #[test]
+    fn closing_connection_ends_blocked_read() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut socket = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let _server_side = listener.accept().unwrap();
+
+        let connection = Connection::default();
+        connection.set_socket(socket.try_clone().unwrap());
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            // the server never writes, only the shutdown ends the read
+            let _ = sender.send(socket.read(&mut [0; 16]).ok());
+        });
+
+        connection.close();
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Some(0)
+        );
+        assert!(connection.is_closed());
+    }
+
+    #[test]
+    fn dropping_subscription_closes_plain_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let subscription = Subscription::new(
+            egui::Context::default(),
+            format!(
+                "http://{}/v0/topstories.json",
+                listener.local_addr().unwrap()
+            ),
+            Backoff::new(Duration::from_secs(60), Duration::from_secs(60)),
+        );
+
+        // the server answers and then keeps quiet, like firebase between events
+        let (mut server_side, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        let mut reader = BufReader::new(server_side.try_clone().unwrap());
+        while !request.ends_with("\r\n\r\n") {
+            reader.read_line(&mut request).unwrap();
+        }
+        assert!(request.starts_with("GET /v0/topstories.json"));
+        server_side
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n")
+            .unwrap();
+        for _ in 0..200 {
+            if subscription.is_live() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(subscription.is_live());
+
+        server_side
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        drop(subscription);
+        assert_eq!(server_side.read(&mut [0; 16]).unwrap(), 0);
+    }
+
+    #[test]
+    fn closing_connection_ends_reconnect_wait() {
+        let connection = Connection::default();
+        let (sender, receiver) = mpsc::channel();
+        {
+            let connection = connection.clone();
+            std::thread::spawn(move || {
+                let _ = sender.send(connection.wait(Duration::from_secs(60)));
+            });
+        }
+
+        connection.close();
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+    }
+}
@@ -0,0 +1,463 @@
+//! Items and feeds saved on disk so they are available right after start.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+};
+
+use eframe::epaint::ahash::HashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tracing::{debug, warn};
+
+use crate::{
+    favicons::FaviconEntry, feeds::FeedSource, fetch::LinkPreview, privacy::PrivacySettings,
+    sync::SyncJob, HnItem, HnItemId,
+};
+
+/// Directory for data of the app, `HACKER_NEWSFEED_DATA_DIR` environment
+/// variable if set, otherwise the platform data directory.
+pub(crate) fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("HACKER_NEWSFEED_DATA_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(target_os = "windows") {
+        PathBuf::from(std::env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+    } else {
+        match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+        }
+    };
+
+    Some(base.join("hacker-newsfeed"))
+}
+
+/// Value with the time it was fetched from the api.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Stored<T> {
+    #[serde(with = "time::serde::timestamp")]
+    pub fetched: OffsetDateTime,
+    pub value: T,
+}
+
+impl<T> Stored<T> {
+    pub(crate) fn age(&self) -> Duration {
+        OffsetDateTime::now_utc() - self.fetched
+    }
+}
+
+/// How long stored feed is displayed without asking the api.
+pub(crate) const FEED_TTL: Duration = Duration::minutes(1);
+
+/// How long stored item is used without asking the api. Items can be edited
+/// for two hours and commented on for two weeks, after that they barely change.
+pub(crate) fn item_ttl(item: &HnItem) -> Duration {
+    let age = OffsetDateTime::now_utc() - item.time;
+
+    if age < Duration::hours(2) {
+        Duration::minutes(1)
+    } else if age < Duration::days(1) {
+        Duration::minutes(10)
+    } else if age < Duration::weeks(2) {
+        Duration::hours(1)
+    } else {
+        Duration::weeks(1)
+    }
+}
+
+enum Write {
+    File(PathBuf, Vec<u8>),
+    Flush(Sender<()>),
+}
+
+/// Json files in data directory, one per item and feed. Files are written on
+/// background thread, store without directory keeps nothing.
+#[derive(Clone)]
+pub(crate) struct Store {
+    dir: Option<PathBuf>,
+    writer: Option<Sender<Write>>,
+}
+
+impl Store {
+    pub(crate) fn new(dir: Option<PathBuf>) -> Self {
+        let writer = dir.as_ref().map(|_| {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("store writer".to_string())
+                .spawn(move || {
+                    for write in receiver {
+                        match write {
+                            Write::File(path, bytes) => {
+                                if let Err(error) = write_file(&path, &bytes) {
+                                    warn!("Cannot write {}: {}", path.display(), error);
+                                }
+                            }
+                            Write::Flush(done) => {
+                                let _ = done.send(());
+                            }
+                        }
+                    }
+                })
+                .expect("cannot spawn store writer thread");
+            sender
+        });
+
+        Self { dir, writer }
+    }
+
+    pub(crate) fn from_env() -> Self {
+        Self::new(data_dir().map(|dir| dir.join("store")))
+    }
+
+    pub(crate) fn item(&self, id: HnItemId) -> Option<Stored<HnItem>> {
+        self.read(&format!("items/{}.json", id))
+    }
+
+    /// Whether item is stored, without reading it.
+    pub(crate) fn has_item(&self, id: HnItemId) -> bool {
+        self.dir
+            .as_ref()
+            .map_or(false, |dir| dir.join(format!("items/{}.json", id)).exists())
+    }
+
+    pub(crate) fn save_item(&self, item: &HnItem) {
+        self.write(&format!("items/{}.json", item.id), item);
+    }
+
+    /// Saves item that did not come from the api as fetched long ago, it is
+    /// refetched the first time it is read like any stale item.
+    pub(crate) fn save_unvalidated_item(&self, item: &HnItem) {
+        self.write_fetched(
+            &format!("items/{}.json", item.id),
+            item,
+            OffsetDateTime::UNIX_EPOCH,
+        );
+    }
+
+    pub(crate) fn feed(&self, source: &FeedSource) -> Option<Stored<Vec<HnItemId>>> {
+        self.read(&feed_path(source))
+    }
+
+    pub(crate) fn save_feed(&self, source: &FeedSource, ids: &[HnItemId]) {
+        self.write(&feed_path(source), ids);
+    }
+
+    /// Bookmarked stories, the newest first.
+    pub(crate) fn bookmarks(&self) -> Vec<HnItemId> {
+        self.read("bookmarks.json")
+            .map(|stored| stored.value)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save_bookmarks(&self, ids: &[HnItemId]) {
+        self.write("bookmarks.json", ids);
+    }
+
+    /// Replies being written by parent story or comment, they survive crash
+    /// and leaving the thread.
+    pub(crate) fn drafts(&self) -> HashMap<HnItemId, String> {
+        self.read("drafts.json")
+            .map(|stored| stored.value)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn save_drafts(&self, drafts: &HashMap<HnItemId, String>) {
+        let drafts: HashMap<_, _> = drafts
+            .iter()
+            .filter(|(_, draft)| !draft.trim().is_empty())
+            .collect();
+        self.write("drafts.json", &drafts);
+    }
+
+    /// Privacy settings changed in the app, they replace the ones from
+    /// environment.
+    pub(crate) fn privacy_settings(&self) -> Option<PrivacySettings> {
+        self.read("privacy.json").map(|stored| stored.value)
+    }
+
+    pub(crate) fn save_privacy_settings(&self, settings: &PrivacySettings) {
+        self.write("privacy.json", settings);
+    }
+
+    /// Sync job, its time is when it was saved.
+    pub(crate) fn sync_job(&self) -> Option<Stored<SyncJob>> {
+        self.read("sync.json")
+    }
+
+    pub(crate) fn save_sync_job(&self, job: &SyncJob) {
+        self.write("sync.json", job);
+    }
+
+    /// Favicon of site with given origin, its time is when it was validated.
+    pub(crate) fn favicon(&self, origin: &str) -> Option<Stored<FaviconEntry>> {
+        self.read(&favicon_path(origin, "json"))
+    }
+
+    pub(crate) fn favicon_image(&self, origin: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.dir.as_ref()?.join(favicon_path(origin, "img"))).ok()
+    }
+
+    /// Saves favicon entry, the image is kept when it is `None`.
+    pub(crate) fn save_favicon(&self, origin: &str, entry: &FaviconEntry, image: Option<&[u8]>) {
+        if let Some(image) = image {
+            self.write_bytes(&favicon_path(origin, "img"), image.to_vec());
+        }
+        self.write(&favicon_path(origin, "json"), entry);
+    }
+
+    /// Link preview of story page, loaded together with favicon of its site.
+    pub(crate) fn preview(&self, id: HnItemId) -> Option<Stored<LinkPreview>> {
+        self.read(&format!("previews/{}.json", id))
+    }
+
+    pub(crate) fn save_preview(&self, id: HnItemId, preview: &LinkPreview) {
+        self.write(&format!("previews/{}.json", id), preview);
+    }
+
+    /// Waits until all saved values are written.
+    pub(crate) fn flush(&self) {
+        if let Some(writer) = &self.writer {
+            let (done, wait) = mpsc::channel();
+            if writer.send(Write::Flush(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+    }
+
+    fn read<T: DeserializeOwned>(&self, path: &str) -> Option<Stored<T>> {
+        let path = self.dir.as_ref()?.join(path);
+        let bytes = std::fs::read(&path).ok()?;
+
+        match serde_json::from_slice(&bytes) {
+            Ok(stored) => Some(stored),
+            Err(error) => {
+                debug!("Ignoring invalid {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+
+    fn write<T: Serialize + ?Sized>(&self, path: &str, value: &T) {
+        self.write_fetched(path, value, OffsetDateTime::now_utc());
+    }
+
+    fn write_fetched<T: Serialize + ?Sized>(&self, path: &str, value: &T, fetched: OffsetDateTime) {
+        if self.writer.is_none() {
+            return;
+        }
+
+        let stored = Stored { fetched, value };
+
+        match serde_json::to_vec(&stored) {
+            Ok(bytes) => self.write_bytes(path, bytes),
+            Err(error) => warn!("Cannot serialize {}: {}", path, error),
+        }
+    }
+
+    fn write_bytes(&self, path: &str, bytes: Vec<u8>) {
+        if let (Some(dir), Some(writer)) = (&self.dir, &self.writer) {
+            let _ = writer.send(Write::File(dir.join(path), bytes));
+        }
+    }
+}
+
+fn feed_path(source: &FeedSource) -> String {
+    let name = match source {
+        FeedSource::Api(name) => format!("api_{}", name),
+        FeedSource::Url(url) => format!("url_{}", url),
+        FeedSource::Site(path) => format!("site_{}", path),
+    };
+
+    format!("feeds/{}.json", file_name(&name))
+}
+
+fn favicon_path(origin: &str, extension: &str) -> String {
+    format!("favicons/{}.{}", file_name(origin), extension)
+}
+
+/// Longest file name without extension, file systems allow 255 bytes.
+const MAX_FILE_NAME: usize = 200;
+
+/// Percent-encodes characters that are not safe in file names so different
+/// names never share a file. Long names are cut and end with hash of the
+/// whole name after `~`, which encoded names do not contain.
+pub(crate) fn file_name(name: &str) -> String {
+    let mut file_name = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            file_name.push(byte as char);
+        } else {
+            file_name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    if file_name.len() > MAX_FILE_NAME {
+        let hash = format!("~{:016x}", fnv1a(name.as_bytes()));
+        file_name.truncate(MAX_FILE_NAME - hash.len());
+        file_name.push_str(&hash);
+    }
+    file_name
+}
+
+/// Hash that stays the same across releases, unlike the one of std.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Writes whole file or nothing, readers never see partially written file.
+fn write_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, bytes)?;
+    std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hacker-newsfeed-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_items_and_feeds() {
+        let store = Store::new(Some(temp_dir("store")));
+        let item = HnItem {
+            id: HnItemId(8863),
+            title: "My YC app: Dropbox - Throw away your USB drive".to_string(),
+            url: Some(
+                "http://www.getdropbox.com/u/2/screencast.html"
+                    .parse()
+                    .unwrap(),
+            ),
+            kids: vec![HnItemId(9224)],
+            ..Default::default()
+        };
+        let top = FeedSource::Api("topstories".to_string());
+        let custom = FeedSource::Url("http://localhost:8080/ids.json?page=1".parse().unwrap());
+
+        assert!(store.item(item.id).is_none());
+
+        store.save_item(&item);
+        store.save_feed(&top, &[HnItemId(1), HnItemId(2)]);
+        store.save_feed(&custom, &[HnItemId(3)]);
+        store.flush();
+
+        let stored = store.item(item.id).unwrap();
+        assert!(stored.age() < Duration::minutes(1));
+        assert_eq!(stored.value.title, item.title);
+        assert_eq!(stored.value.url, item.url);
+        assert_eq!(stored.value.kids, item.kids);
+        assert_eq!(
+            stored.value.time.unix_timestamp(),
+            item.time.unix_timestamp()
+        );
+
+        assert_eq!(
+            store.feed(&top).unwrap().value,
+            vec![HnItemId(1), HnItemId(2)]
+        );
+        assert_eq!(store.feed(&custom).unwrap().value, vec![HnItemId(3)]);
+    }
+
+    #[test]
+    fn keeps_similar_feeds_apart() {
+        let store = Store::new(Some(temp_dir("similar-feeds")));
+        let dotted = FeedSource::Url("http://a.b/c".parse().unwrap());
+        let underscored = FeedSource::Url("http://a_b/c".parse().unwrap());
+
+        store.save_feed(&dotted, &[HnItemId(1)]);
+        store.save_feed(&underscored, &[HnItemId(2)]);
+        store.flush();
+
+        assert_eq!(store.feed(&dotted).unwrap().value, vec![HnItemId(1)]);
+        assert_eq!(store.feed(&underscored).unwrap().value, vec![HnItemId(2)]);
+        assert_eq!(file_name("url_http://a.b/c"), "url_http%3A%2F%2Fa%2Eb%2Fc");
+    }
+
+    #[test]
+    fn hashes_long_feed_names() {
+        let store = Store::new(Some(temp_dir("long-feeds")));
+        let long = |end| {
+            FeedSource::Url(
+                format!("http://a.b/{}{}", "c/".repeat(200), end)
+                    .parse()
+                    .unwrap(),
+            )
+        };
+
+        store.save_feed(&long("d"), &[HnItemId(1)]);
+        store.save_feed(&long("e"), &[HnItemId(2)]);
+        store.flush();
+
+        assert_eq!(store.feed(&long("d")).unwrap().value, vec![HnItemId(1)]);
+        assert_eq!(store.feed(&long("e")).unwrap().value, vec![HnItemId(2)]);
+        assert_eq!(
+            feed_path(&long("d")).len(),
+            "feeds/".len() + 200 + ".json".len()
+        );
+    }
+
+    #[test]
+    fn saves_drafts_with_text() {
+        let store = Store::new(Some(temp_dir("drafts")));
+        let mut drafts = HashMap::default();
+        drafts.insert(HnItemId(1), "First *draft*".to_string());
+        drafts.insert(HnItemId(2), " \n".to_string());
+
+        store.save_drafts(&drafts);
+        store.flush();
+
+        drafts.remove(&HnItemId(2));
+        assert_eq!(store.drafts(), drafts);
+    }
+
+    #[test]
+    fn saves_privacy_settings() {
+        let store = Store::new(Some(temp_dir("privacy")));
+        assert_eq!(store.privacy_settings(), None);
+
+        let settings = PrivacySettings {
+            enabled: true,
+            allow: vec!["github.com".to_string()],
+            deny: vec!["tracker.example".to_string()],
+            previews: true,
+        };
+        store.save_privacy_settings(&settings);
+        store.flush();
+
+        assert_eq!(store.privacy_settings(), Some(settings));
+    }
+
+    #[test]
+    fn store_without_directory_keeps_nothing() {
+        let store = Store::new(None);
+        store.save_item(&HnItem::default());
+        store.flush();
+        assert!(store.item(HnItemId::default()).is_none());
+    }
+
+    #[test]
+    fn old_items_live_longer() {
+        let item = |age| HnItem {
+            time: OffsetDateTime::now_utc() - age,
+            ..Default::default()
+        };
+
+        assert!(item_ttl(&item(Duration::minutes(5))) < item_ttl(&item(Duration::days(3))));
+        assert_eq!(item_ttl(&item(Duration::days(365))), Duration::weeks(1));
+    }
+}
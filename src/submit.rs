@@ -0,0 +1,165 @@
+//! Stories submitted from the app, checked against the rules of the site
+//! before they are posted so the user does not lose them to a rejected form.
+
+use url::Url;
+
+/// Longest title the site accepts, in characters.
+pub(crate) const TITLE_LENGTH: usize = 80;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum SubmissionKind {
+    #[default]
+    Link,
+    Show, // something user made that others can try, with optional url
+    Ask,  // question for discussion, without url
+}
+
+impl SubmissionKind {
+    /// Title prefix the site lists the story under.
+    fn prefix(self) -> &'static str {
+        match self {
+            SubmissionKind::Link => "",
+            SubmissionKind::Show => "Show HN: ",
+            SubmissionKind::Ask => "Ask HN: ",
+        }
+    }
+
+    pub(crate) fn has_url(self) -> bool {
+        self != SubmissionKind::Ask
+    }
+}
+
+/// Story being written in the submit view.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Submission {
+    pub kind: SubmissionKind,
+    pub title: String, // without the prefix of its kind
+    pub url: String,
+    pub text: String,
+}
+
+impl Submission {
+    /// Title as it is posted, with the prefix of its kind unless user typed
+    /// it already.
+    pub(crate) fn title(&self) -> String {
+        let title = self.title.trim();
+        let prefix = self.kind.prefix();
+
+        if title
+            .get(..prefix.len())
+            .map_or(false, |start| start.eq_ignore_ascii_case(prefix))
+        {
+            title.to_string()
+        } else {
+            format!("{}{}", prefix, title)
+        }
+    }
+
+    /// Url of the story, `None` when it has none or it is not a valid web
+    /// url.
+    pub(crate) fn url(&self) -> Option<Url> {
+        if !self.kind.has_url() {
+            return None;
+        }
+
+        Url::parse(self.url.trim())
+            .ok()
+            .filter(|url| matches!(url.scheme(), "http" | "https") && url.has_host())
+    }
+
+    /// Why the site would reject the story, empty when it can be posted.
+    pub(crate) fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let length = self.title().chars().count();
+        if self.title.trim().is_empty() {
+            problems.push("Title is required".to_string());
+        } else if length > TITLE_LENGTH {
+            problems.push(format!(
+                "Title is {} characters long, at most {} are allowed",
+                length, TITLE_LENGTH
+            ));
+        }
+
+        let url = self.url.trim();
+        if self.kind.has_url() && !url.is_empty() && self.url().is_none() {
+            problems.push("Url must start with http:// or https://".to_string());
+        }
+        if self.kind == SubmissionKind::Link && url.is_empty() {
+            problems.push("Url is required, questions are submitted as Ask HN".to_string());
+        }
+
+        problems
+    }
+}
+
+/// Whether two urls point to the same page, they may differ in scheme,
+/// `www.` subdomain, trailing slash and fragment.
+pub(crate) fn same_url(a: &Url, b: &Url) -> bool {
+    let key = |url: &Url| {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let host = host
+            .strip_prefix("www.")
+            .map(str::to_string)
+            .unwrap_or(host);
+        let path = url.path().trim_end_matches('/').to_string();
+        (host, path, url.query().map(str::to_string))
+    };
+
+    key(a) == key(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_title_and_url() {
+        let mut submission = Submission {
+            kind: SubmissionKind::Show,
+            title: "My weekend project".to_string(),
+            url: "example.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(submission.title(), "Show HN: My weekend project");
+        assert_eq!(
+            submission.problems(),
+            ["Url must start with http:// or https://"]
+        );
+
+        // ask posts have no url
+        submission.kind = SubmissionKind::Ask;
+        submission.title = "ask hn: Why is the sky blue?".to_string();
+        assert_eq!(submission.title(), "ask hn: Why is the sky blue?");
+        assert!(submission.problems().is_empty());
+
+        submission.kind = SubmissionKind::Link;
+        submission.title = "x".repeat(TITLE_LENGTH);
+        submission.url = "https://example.com/".to_string();
+        assert!(submission.problems().is_empty());
+
+        submission.kind = SubmissionKind::Show;
+        assert_eq!(
+            submission.problems(),
+            ["Title is 89 characters long, at most 80 are allowed"]
+        );
+    }
+
+    #[test]
+    fn matches_same_page() {
+        let url = |url: &str| Url::parse(url).unwrap();
+
+        assert!(same_url(
+            &url("https://www.example.com/post/"),
+            &url("http://example.com/post#comments")
+        ));
+        assert!(!same_url(
+            &url("https://example.com/post"),
+            &url("https://example.com/post?page=2")
+        ));
+        assert!(!same_url(
+            &url("https://example.com/post"),
+            &url("https://blog.example.com/post")
+        ));
+    }
+}
@@ -0,0 +1,367 @@
+//! Downloads whole comment trees of the front page and bookmarked stories
+//! into the store while the app is idle so they can be read offline.
+
+use std::time::{Duration, Instant};
+
+use eframe::{egui, epaint::ahash::HashMap};
+use poll_promise::Promise;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{
+    backend::Backend,
+    error,
+    feeds::FeedSource,
+    scheduler::Priority,
+    store::{self, Store},
+    HnItem, HnItemId,
+};
+
+/// How often new sync starts after the previous one finished.
+const SYNC_INTERVAL: time::Duration = time::Duration::hours(1);
+
+// stories of the front page
+const FRONT_PAGE_SIZE: usize = 30;
+
+// requests running at once, the rest of the app should not wait for sync
+const MAX_IN_FLIGHT: usize = 2;
+
+// how many downloaded items between saves of the job
+const SAVE_EVERY: usize = 25;
+
+// how long to wait after network error before continuing
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+pub(crate) struct SyncConfig {
+    pub depth: usize,  // levels of comments below the story, 0 syncs only stories
+    pub budget: usize, // items downloaded by one sync at most, 0 disables sync
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            depth: 32,
+            budget: 5000,
+        }
+    }
+}
+
+impl SyncConfig {
+    /// Reads `HACKER_NEWSFEED_SYNC_DEPTH` and `HACKER_NEWSFEED_SYNC_BUDGET`,
+    /// unset or invalid values keep their default.
+    pub(crate) fn from_env() -> Self {
+        let mut config = Self::default();
+
+        let var = |name| match std::env::var(name).map(|value| value.parse()) {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(error)) => {
+                warn!("Invalid {}: {}", name, error);
+                None
+            }
+            Err(_) => None,
+        };
+
+        if let Some(depth) = var("HACKER_NEWSFEED_SYNC_DEPTH") {
+            config.depth = depth;
+        }
+
+        if let Some(budget) = var("HACKER_NEWSFEED_SYNC_BUDGET") {
+            config.budget = budget;
+        }
+
+        config
+    }
+}
+
+/// Progress of sync, saved in the store so it continues after restart.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct SyncJob {
+    pending: Vec<(HnItemId, usize)>, // items to download with their depth, the last goes first
+    downloaded: usize,
+}
+
+impl SyncJob {
+    fn new(roots: &[HnItemId]) -> Self {
+        Self {
+            pending: roots.iter().rev().map(|&id| (id, 0)).collect(),
+            downloaded: 0,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+pub(crate) struct ThreadSync {
+    store: Store,
+    config: SyncConfig,
+    job: SyncJob,
+    finished_at: Option<time::OffsetDateTime>,
+    in_flight: HashMap<HnItemId, (usize, Promise<error::Result<HnItem>>)>,
+    unsaved: usize, // downloaded items since the job was saved
+    paused_until: Option<Instant>,
+}
+
+impl ThreadSync {
+    /// Continues the job of the last run if it did not finish.
+    pub(crate) fn new(store: Store, config: SyncConfig) -> Self {
+        let (job, finished_at) = match store.sync_job() {
+            Some(stored) if stored.value.is_finished() => (stored.value, Some(stored.fetched)),
+            Some(stored) => (stored.value, None),
+            None => (SyncJob::default(), None),
+        };
+
+        Self {
+            store,
+            config,
+            job,
+            finished_at,
+            in_flight: Default::default(),
+            unsaved: 0,
+            paused_until: None,
+        }
+    }
+
+    /// (downloaded, known total) items of running sync.
+    pub(crate) fn progress(&self) -> Option<(usize, usize)> {
+        if self.job.is_finished() && self.in_flight.is_empty() {
+            return None;
+        }
+
+        let total = self.job.downloaded + self.in_flight.len() + self.job.pending.len();
+        Some((self.job.downloaded, total.min(self.config.budget)))
+    }
+
+    /// Collects downloaded items and when `idle` requests more of them,
+    /// starts new sync of the front page and `bookmarks` when it is due.
+    pub(crate) fn poll(
+        &mut self,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+        idle: bool,
+        bookmarks: &[HnItemId],
+    ) {
+        self.collect_downloaded();
+
+        if !idle || self.config.budget == 0 {
+            return;
+        }
+
+        if let Some(paused_until) = self.paused_until {
+            if Instant::now() < paused_until {
+                ctx.request_repaint_after(paused_until - Instant::now());
+                return;
+            }
+            self.paused_until = None;
+        }
+
+        if self.job.is_finished() && self.in_flight.is_empty() {
+            let due = self.finished_at.map_or(true, |finished_at| {
+                time::OffsetDateTime::now_utc() - finished_at >= SYNC_INTERVAL
+            });
+            if !due {
+                return;
+            }
+
+            let front_page = self.front_page();
+            let bookmarks = bookmarks.iter().filter(|id| !front_page.contains(id));
+            let roots: Vec<_> = front_page.iter().chain(bookmarks).copied().collect();
+            self.job = SyncJob::new(&roots);
+            self.finished_at = None;
+        }
+
+        while self.in_flight.len() < MAX_IN_FLIGHT
+            && self.job.downloaded + self.in_flight.len() < self.config.budget
+        {
+            let Some((id, depth)) = self.job.pending.pop() else {
+                break;
+            };
+
+            if self.in_flight.contains_key(&id) {
+                continue;
+            }
+
+            // fresh items are not downloaded again, only their kids are
+            match self.store.item(id) {
+                Some(stored) if stored.age() < store::item_ttl(&stored.value) => {
+                    self.push_kids(&stored.value, depth);
+                }
+                _ => {
+                    let promise = backend.item(ctx.clone(), id, Priority::Sync);
+                    self.in_flight.insert(id, (depth, promise));
+                }
+            }
+        }
+
+        if self.job.downloaded >= self.config.budget {
+            self.job.pending.clear();
+        }
+
+        if self.job.is_finished() && self.in_flight.is_empty() && self.finished_at.is_none() {
+            self.finished_at = Some(time::OffsetDateTime::now_utc());
+            self.save();
+        }
+    }
+
+    fn collect_downloaded(&mut self) {
+        let downloaded: Vec<_> = self
+            .in_flight
+            .iter()
+            .filter(|(_, (_, promise))| promise.ready().is_some())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in downloaded {
+            let Some((depth, promise)) = self.in_flight.remove(&id) else {
+                continue;
+            };
+
+            match promise.block_and_take() {
+                Ok(item) => {
+                    self.store.save_item(&item);
+                    self.push_kids(&item, depth);
+                    self.job.downloaded += 1;
+                    self.unsaved += 1;
+                }
+                Err(error) if error.is_transient() => {
+                    // try again later, the network might be down
+                    self.job.pending.push((id, depth));
+                    self.paused_until = Some(Instant::now() + RETRY_AFTER);
+                }
+                Err(error) => warn!("cannot sync item {}: {}", id, error),
+            }
+        }
+
+        if self.unsaved >= SAVE_EVERY {
+            self.save();
+        }
+    }
+
+    fn push_kids(&mut self, item: &HnItem, depth: usize) {
+        if depth < self.config.depth {
+            // the first kid is popped first so threads sync from the top
+            self.job
+                .pending
+                .extend(item.kids.iter().rev().map(|&kid| (kid, depth + 1)));
+        }
+    }
+
+    fn front_page(&self) -> Vec<HnItemId> {
+        let mut story_ids = self
+            .store
+            .feed(&FeedSource::Api("topstories".to_string()))
+            .map(|stored| stored.value)
+            .unwrap_or_default();
+        story_ids.truncate(FRONT_PAGE_SIZE);
+        story_ids
+    }
+
+    fn save(&mut self) {
+        // items being downloaded are downloaded again after restart
+        let mut job = SyncJob {
+            pending: self.job.pending.clone(),
+            downloaded: self.job.downloaded,
+        };
+        job.pending
+            .extend(self.in_flight.iter().map(|(id, (depth, _))| (*id, *depth)));
+
+        self.store.save_sync_job(&job);
+        self.unsaved = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backend::Firebase,
+        scheduler::Scheduler,
+        store::temp_dir,
+        test_server::{Response, TestServer},
+    };
+
+    fn thread_server() -> TestServer {
+        // 1 ─┬─ 2 ── 4
+        //    └─ 3
+        // 5
+        TestServer::start(|request| match request.path.as_str() {
+            "/v0/item/1.json" => Response::json(r#"{"id":1,"kids":[2,3]}"#),
+            "/v0/item/2.json" => Response::json(r#"{"id":2,"kids":[4]}"#),
+            "/v0/item/3.json" => Response::json(r#"{"id":3}"#),
+            "/v0/item/4.json" => Response::json(r#"{"id":4}"#),
+            "/v0/item/5.json" => Response::json(r#"{"id":5}"#),
+            _ => Response::not_found(),
+        })
+    }
+
+    fn run(
+        sync: &mut ThreadSync,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+        bookmarks: &[HnItemId],
+    ) {
+        sync.poll(ctx, backend, true, bookmarks);
+        while sync.progress().is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            sync.poll(ctx, backend, true, bookmarks);
+        }
+    }
+
+    #[test]
+    fn syncs_front_page_and_bookmarks_to_depth() {
+        let server = thread_server();
+        let ctx = egui::Context::default();
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(2));
+        let store = Store::new(Some(temp_dir("sync-depth")));
+        store.save_feed(&FeedSource::Api("topstories".to_string()), &[HnItemId(1)]);
+        store.flush();
+
+        let config = SyncConfig {
+            depth: 1,
+            budget: 100,
+        };
+        let mut sync = ThreadSync::new(store.clone(), config);
+        run(&mut sync, &ctx, &backend, &[HnItemId(5)]);
+        store.flush();
+
+        for id in [1, 2, 3, 5] {
+            assert!(store.item(HnItemId(id)).is_some(), "item {} is synced", id);
+        }
+        assert!(store.item(HnItemId(4)).is_none());
+
+        // finished sync is not repeated until it is due
+        sync.poll(&ctx, &backend, true, &[HnItemId(5)]);
+        assert_eq!(sync.progress(), None);
+    }
+
+    #[test]
+    fn resumes_after_restart_within_budget() {
+        let server = thread_server();
+        let ctx = egui::Context::default();
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(2));
+        let store = Store::new(Some(temp_dir("sync-resume")));
+
+        // job of previous run that got to the first comment
+        store.save_sync_job(&SyncJob {
+            pending: vec![(HnItemId(3), 1), (HnItemId(4), 2)],
+            downloaded: 2,
+        });
+        store.flush();
+
+        let config = SyncConfig {
+            depth: 32,
+            budget: 3,
+        };
+        let mut sync = ThreadSync::new(store.clone(), config);
+        assert_eq!(sync.progress(), Some((2, 3)));
+
+        run(&mut sync, &ctx, &backend, &[]);
+        store.flush();
+
+        // the last pending item is popped first
+        assert!(store.item(HnItemId(4)).is_some());
+        assert!(store.item(HnItemId(3)).is_none());
+        assert!(store.sync_job().unwrap().value.is_finished());
+    }
+}
@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use poll_promise::Promise;
+use tracing::warn;
+
+use crate::{backend::Backend, error, HnItemId, HnUpdates};
+
+/// Changes reported by the api since the last poll.
+#[derive(Default)]
+pub(crate) struct Changes {
+    pub items: Vec<HnItemId>,
+    pub profiles: Vec<String>,
+    pub new_items: bool, // max item id increased, story lists might have changed
+}
+
+/// How many stories of `fetched` list are not in the `shown` one.
+pub(crate) fn new_stories(shown: &[HnItemId], fetched: &[HnItemId]) -> usize {
+    fetched.iter().filter(|id| !shown.contains(id)).count()
+}
+
+/// Periodically polls `updates.json` and `maxitem.json` in the background.
+pub(crate) struct LiveUpdates {
+    interval: Duration,
+    last_poll: Option<Instant>,
+    updates: Option<Promise<error::Result<HnUpdates>>>,
+    max_item: Option<Promise<error::Result<HnItemId>>>,
+    last_max_item: Option<HnItemId>,
+}
+
+impl LiveUpdates {
+    pub(crate) fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_poll: None,
+            updates: None,
+            max_item: None,
+            last_max_item: None,
+        }
+    }
+
+    /// Starts new poll when interval elapses and returns changes once both
+    /// requests finish.
+    pub(crate) fn poll(&mut self, ctx: &egui::Context, backend: &dyn Backend) -> Option<Changes> {
+        if self.updates.is_none() && self.max_item.is_none() {
+            let now = Instant::now();
+            let due = self.last_poll.map_or(true, |last_poll| {
+                now.duration_since(last_poll) >= self.interval
+            });
+
+            if due {
+                self.last_poll = Some(now);
+                self.updates = Some(backend.updates(ctx.clone()));
+                self.max_item = Some(backend.max_item(ctx.clone()));
+            } else {
+                // wake up for the next poll even if user does not interact with the app
+                ctx.request_repaint_after(self.interval - now.duration_since(self.last_poll?));
+                return None;
+            }
+        }
+
+        let updates_ready = self.updates.as_ref().map_or(true, |p| p.ready().is_some());
+        let max_item_ready = self.max_item.as_ref().map_or(true, |p| p.ready().is_some());
+        if !updates_ready || !max_item_ready {
+            return None;
+        }
+
+        let mut changes = Changes::default();
+
+        match self.updates.take().map(|p| p.block_and_take()) {
+            Some(Ok(updates)) => {
+                changes.items = updates.items;
+                changes.profiles = updates.profiles;
+            }
+            Some(Err(error)) => warn!("cannot load updates: {}", error),
+            None => {}
+        }
+
+        match self.max_item.take().map(|p| p.block_and_take()) {
+            Some(Ok(max_item)) => {
+                // first poll only establishes the baseline
+                changes.new_items = self
+                    .last_max_item
+                    .map_or(false, |last_max_item| max_item.0 > last_max_item.0);
+                self.last_max_item = Some(max_item);
+            }
+            Some(Err(error)) => warn!("cannot load max item: {}", error),
+            None => {}
+        }
+
+        Some(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::{
+        backend::Firebase,
+        scheduler::Scheduler,
+        test_server::{Response, TestServer},
+    };
+
+    fn wait_for_changes(
+        updates: &mut LiveUpdates,
+        ctx: &egui::Context,
+        backend: &dyn Backend,
+    ) -> Changes {
+        for _ in 0..500 {
+            if let Some(changes) = updates.poll(ctx, backend) {
+                return changes;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("no changes polled");
+    }
+
+    #[test]
+    fn reports_changed_items_and_new_ones() {
+        let max_item = Arc::new(AtomicUsize::new(100));
+        let server = {
+            let max_item = max_item.clone();
+            TestServer::start(move |request| match request.path.as_str() {
+                "/v0/updates.json" => Response::json(r#"{"items":[42],"profiles":["pg"]}"#),
+                "/v0/maxitem.json" => Response::json(max_item.load(Ordering::SeqCst).to_string()),
+                _ => Response::not_found(),
+            })
+        };
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(2));
+        let ctx = egui::Context::default();
+        let mut updates = LiveUpdates::new(Duration::ZERO);
+
+        // the first poll only learns the max item
+        let changes = wait_for_changes(&mut updates, &ctx, &backend);
+        assert_eq!(changes.items, [HnItemId(42)]);
+        assert_eq!(changes.profiles, ["pg"]);
+        assert!(!changes.new_items);
+
+        let changes = wait_for_changes(&mut updates, &ctx, &backend);
+        assert!(!changes.new_items);
+
+        max_item.store(105, Ordering::SeqCst);
+        let changes = wait_for_changes(&mut updates, &ctx, &backend);
+        assert!(changes.new_items);
+    }
+
+    #[test]
+    fn waits_for_interval_between_polls() {
+        let server = TestServer::start(|_| Response::json("1"));
+        let backend = Firebase::new(server.url("/v0").parse().unwrap(), Scheduler::new(2));
+        let ctx = egui::Context::default();
+        let mut updates = LiveUpdates::new(Duration::from_secs(60));
+
+        wait_for_changes(&mut updates, &ctx, &backend);
+        assert!(updates.poll(&ctx, &backend).is_none());
+        assert!(updates.updates.is_none() && updates.max_item.is_none());
+    }
+
+    #[test]
+    fn counts_stories_not_shown_yet() {
+        let shown = [HnItemId(1), HnItemId(2), HnItemId(3)];
+        assert_eq!(
+            new_stories(&shown, &[HnItemId(3), HnItemId(1), HnItemId(2)]),
+            0
+        );
+        assert_eq!(
+            new_stories(&shown, &[HnItemId(4), HnItemId(1), HnItemId(5)]),
+            2
+        );
+    }
+}
@@ -1,10 +1,24 @@
 use eframe::{
-    egui::{self, CollapsingHeader, RichText, TextFormat},
-    epaint::{text::LayoutJob, FontId, Vec2},
+    egui::{
+        self,
+        collapsing_header::{self, CollapsingState},
+        Key, RichText,
+    },
+    epaint::Vec2,
 };
 use egui_extras::RetainedImage;
+use time::{Date, Weekday};
+use url::Url;
 
-use crate::{comment_parser, human_format, HnItem, HnItemId};
+use crate::{
+    account::{ItemAction, ItemState},
+    comment_parser,
+    error::Error,
+    fetch::LinkPreview,
+    human_format, privacy,
+    reader::{Article, Block},
+    HnItem, HnItemId,
+};
 
 fn rich_text_with_style(text: impl Into<String>, style: &comment_parser::TextStyle) -> RichText {
     let mut rich_text = RichText::new(text);
@@ -36,7 +50,7 @@ pub(crate) fn html_text(text: &str, ui: &mut egui::Ui) {
                 comment_parser::Item::NewLine => {
                     ui.label("\n");
                 }
-                comment_parser::Item::Link(mut url, mut text) => {
+                comment_parser::Item::Link(url, text) => {
                     let url = url.to_string();
                     let text = text.to_string();
                     ui.hyperlink_to(rich_text_with_style(text, &style), url);
@@ -46,35 +60,116 @@ pub(crate) fn html_text(text: &str, ui: &mut egui::Ui) {
     });
 }
 
+/// What user wants to do after interacting with story or comment.
+pub(crate) enum Action {
+    OpenComments(HnItemId),
+    OpenArticle(HnItemId),
+    OpenUser(String),
+    Retry(HnItemId), // load story or comment that failed again
+    ToggleBookmark(HnItemId),
+    Account(HnItemId, ItemAction), // vote, favorite or hide as logged in user
+    Reply(HnItemId),               // open reply box under story or comment
+    PostReply(HnItemId),
+    DiscardReply(HnItemId),
+    Collapse(HnItemId, bool), // true when user closed comment, false when opened again
+    LoadReplies(HnItemId),    // replies below the loaded levels of thread
+}
+
+fn user_link(ui: &mut egui::Ui, user: &str) -> bool {
+    ui.scope(|ui| {
+        ui.visuals_mut().hyperlink_color = ui.visuals().strong_text_color();
+        ui.link(RichText::new(user).strong())
+            .on_hover_text("Show profile")
+            .clicked()
+    })
+    .inner
+}
+
+/// Size in pixels of favicon displayed next to story url, favicons are
+/// chosen to fit it.
+pub(crate) fn favicon_pixels(ctx: &egui::Context) -> u32 {
+    (ctx.style().spacing.interact_size.y * ctx.pixels_per_point()).round() as u32
+}
+
+// longest description shown in link preview
+const PREVIEW_LENGTH: usize = 200;
+
+/// What is known about the site of story with url.
+pub(crate) struct Site<'a> {
+    pub favicon: &'a RetainedImage,
+    pub preview: Option<&'a LinkPreview>,
+    pub thumbnail: Option<&'a RetainedImage>,
+}
+
+/// Link to upvote or unvote item, the action is returned when it is clicked.
+fn vote_link(ui: &mut egui::Ui, state: ItemState) -> Option<ItemAction> {
+    let (text, action) = if state.upvoted {
+        ("unvote", ItemAction::Unvote)
+    } else {
+        ("▲ upvote", ItemAction::Upvote)
+    };
+
+    ui.link(text).clicked().then_some(action)
+}
+
+fn favorite_link(ui: &mut egui::Ui, state: ItemState) -> Option<ItemAction> {
+    let (text, action) = if state.favorited {
+        ("unfavorite", ItemAction::Unfavorite)
+    } else {
+        ("favorite", ItemAction::Favorite)
+    };
+
+    ui.link(text)
+        .on_hover_text("Favorites are listed on your profile on Hacker News")
+        .clicked()
+        .then_some(action)
+}
+
+/// Story with title, site and its stats, `account` is what logged in user
+/// did with the story, `None` when they are logged out.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn story(
     story: &HnItem,
     ui: &mut egui::Ui,
     show_text: bool,
     can_open_comments: bool,
     render_html: bool,
-    favicon: Option<&RetainedImage>,
-) -> bool {
+    bookmarked: bool,
+    site: Option<Site>,
+    account: Option<ItemState>,
+) -> Option<Action> {
     enum Intent {
         OpenComments,
         OpenLink,
+        ReadArticle,
+        OpenPoll,
+        ShowUser,
+        ToggleBookmark,
+        Account(ItemAction),
     }
 
+    let is_poll_option = story.r#type == "pollopt";
     let comment_link_enabled = story.descendants > 0 && can_open_comments;
     let link_enabled = story.url.is_some() || comment_link_enabled;
     let mut intent = None;
 
-    if let Some(url) = &story.url {
+    if let (Some(url), Some(site)) = (&story.url, &site) {
         ui.horizontal(|ui| {
-            if let Some(icon) = favicon {
-                let height = ui.available_height();
-                icon.show_size(ui, Vec2::new(height, height));
-            }
+            let height = ui.spacing().interact_size.y;
+            site.favicon.show_size(ui, Vec2::new(height, height));
 
             ui.label(RichText::new(human_format::url(url)).monospace());
         });
     }
 
-    let title_text = RichText::new(&story.title).heading().strong();
+    // poll options have no title, only text
+    let title = if is_poll_option {
+        comment_parser::Parser::new(&story.text).to_string()
+    } else {
+        story.title.clone()
+    };
+
+    let title_text = RichText::new(title).heading().strong();
     if link_enabled {
         ui.scope(|ui| {
             ui.visuals_mut().hyperlink_color = ui.visuals().widgets.active.fg_stroke.color;
@@ -86,13 +181,21 @@ pub(crate) fn story(
         ui.label(title_text);
     };
 
+    if let Some(site) = &site {
+        if let Some(preview) = site.preview {
+            link_preview(&story.title, preview, site.thumbnail, ui);
+        }
+    }
+
     ui.horizontal(|ui| {
-        ui.label(RichText::new(&story.by).strong());
+        if user_link(ui, &story.by) {
+            intent = Some(Intent::ShowUser);
+        }
         ui.label("•");
         ui.label(RichText::new(human_format::date_time(&story.time)).weak());
     });
 
-    if show_text && story.text.len() > 0 {
+    if show_text && !story.text.is_empty() && !is_poll_option {
         if render_html {
             html_text(&story.text, ui);
         } else {
@@ -106,6 +209,21 @@ pub(crate) fn story(
             ui.label("•");
         }
 
+        if let Some(state) = account {
+            if let Some(action) = vote_link(ui, state) {
+                intent = Some(Intent::Account(action));
+            }
+            ui.label("•");
+        }
+
+        if is_poll_option {
+            if ui.link("View poll").clicked() {
+                intent = Some(Intent::OpenPoll);
+            }
+
+            return;
+        }
+
         ui.add_enabled_ui(comment_link_enabled, |ui| {
             if ui
                 .link(human_format::comment_count(story.descendants))
@@ -114,68 +232,629 @@ pub(crate) fn story(
                 intent = Some(Intent::OpenComments);
             }
         });
+
+        ui.label("•");
+
+        if story.url.is_some() {
+            if ui
+                .link("Read")
+                .on_hover_text("Read the article in app")
+                .clicked()
+            {
+                intent = Some(Intent::ReadArticle);
+            }
+            ui.label("•");
+        }
+
+        let text = if bookmarked { "Unbookmark" } else { "Bookmark" };
+        if ui
+            .link(text)
+            .on_hover_text("Bookmarked stories are synced for offline reading")
+            .clicked()
+        {
+            intent = Some(Intent::ToggleBookmark);
+        }
+
+        if let Some(state) = account {
+            ui.label("•");
+            if let Some(action) = favorite_link(ui, state) {
+                intent = Some(Intent::Account(action));
+            }
+
+            ui.label("•");
+            let (text, action) = if state.hidden {
+                ("unhide", ItemAction::Unhide)
+            } else {
+                ("hide", ItemAction::Hide)
+            };
+            if ui
+                .link(text)
+                .on_hover_text("Hidden stories are left out of story lists")
+                .clicked()
+            {
+                intent = Some(Intent::Account(action));
+            }
+        }
     });
 
-    // If there is url set and the intent is to open the link then open the url
-    // otherwise if whatever intent is set meaning we are able to interact, then
-    // open comments, this is so stories without url open comment section when
-    // they click the title
+    // If there is url set and the intent is to open the link then open it in
+    // browser otherwise if whatever intent is set meaning we are able to
+    // interact, then open comments, this is so stories without url open
+    // comment section when they click the title
     match (&story.url, intent) {
         (Some(url), Some(Intent::OpenLink)) => {
             ui.output_mut(|o| o.open_url(url));
-            false
+            None
         }
-        (_, Some(_)) => true,
-        _ => false,
+        (_, Some(Intent::ShowUser)) => Some(Action::OpenUser(story.by.clone())),
+        (_, Some(Intent::OpenPoll)) => Some(Action::OpenComments(story.poll)),
+        (_, Some(Intent::ToggleBookmark)) => Some(Action::ToggleBookmark(story.id)),
+        (_, Some(Intent::Account(action))) => Some(Action::Account(story.id, action)),
+        (_, Some(Intent::ReadArticle)) => Some(Action::OpenArticle(story.id)),
+        (_, Some(_)) => Some(Action::OpenComments(story.id)),
+        _ => None,
     }
 }
 
-pub(crate) fn comment<F>(comment: &HnItem, ui: &mut egui::Ui, render_html: bool, draw_child: F)
+/// Line with description of the page under story title, the page title is
+/// shown only when it says more than the story title.
+fn link_preview(
+    title: &str,
+    preview: &LinkPreview,
+    thumbnail: Option<&RetainedImage>,
+    ui: &mut egui::Ui,
+) {
+    let mut parts = Vec::new();
+    if let Some(published) = preview.published {
+        parts.push(published.to_string());
+    }
+    if let Some(page_title) = &preview.title {
+        if !page_title.eq_ignore_ascii_case(title) {
+            parts.push(page_title.clone());
+        }
+    }
+    if let Some(description) = &preview.description {
+        if description.chars().count() > PREVIEW_LENGTH {
+            let shortened: String = description.chars().take(PREVIEW_LENGTH).collect();
+            parts.push(format!("{}…", shortened.trim_end()));
+        } else {
+            parts.push(description.clone());
+        }
+    }
+
+    if parts.is_empty() && thumbnail.is_none() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        if let Some(thumbnail) = thumbnail {
+            let height = ui.spacing().interact_size.y * 3.0;
+            thumbnail.show_max_size(ui, Vec2::new(height * 2.0, height));
+        }
+
+        ui.add(egui::Label::new(RichText::new(parts.join(" • ")).weak()).wrap(true));
+    });
+}
+
+/// Switches opened story with url between its article and comments.
+pub(crate) fn story_tabs(story: &HnItem, reading: bool, ui: &mut egui::Ui) -> Option<Action> {
+    let url = story.url.as_ref()?;
+
+    ui.horizontal(|ui| {
+        let mut action = None;
+
+        if ui.selectable_label(reading, "Article").clicked() && !reading {
+            action = Some(Action::OpenArticle(story.id));
+        }
+
+        let comments = human_format::comment_count(story.descendants);
+        if ui.selectable_label(!reading, comments).clicked() && reading {
+            action = Some(Action::OpenComments(story.id));
+        }
+
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.hyperlink_to("Open in browser", url);
+        });
+
+        action
+    })
+    .inner
+}
+
+/// Article read in app, images that are not loaded show their alt text.
+pub(crate) fn article<'a>(
+    article: &Article,
+    ui: &mut egui::Ui,
+    image: impl Fn(&Url) -> Option<&'a RetainedImage>,
+) {
+    // the page title usually repeats the first heading
+    let repeats_title =
+        matches!(article.blocks.first(), Some(Block::Heading(_, text)) if *text == article.title);
+    if !article.title.is_empty() && !repeats_title {
+        ui.label(RichText::new(&article.title).heading().strong());
+    }
+
+    for block in &article.blocks {
+        match block {
+            Block::Heading(level, text) => {
+                let text = RichText::new(text).strong();
+                ui.add_space(ui.spacing().item_spacing.y);
+                ui.label(if *level <= 2 { text.heading() } else { text });
+            }
+            Block::Paragraph(text) => {
+                ui.label(text);
+            }
+            Block::Code(code) => {
+                egui::Frame::none()
+                    .fill(ui.visuals().code_bg_color)
+                    .inner_margin(egui::style::Margin::same(4.0))
+                    .show(ui, |ui| {
+                        egui::ScrollArea::horizontal()
+                            .id_source(code)
+                            .show(ui, |ui| {
+                                ui.add(
+                                    egui::Label::new(RichText::new(code).monospace()).wrap(false),
+                                );
+                            });
+                    });
+            }
+            Block::List { ordered, items } => {
+                for (index, item) in items.iter().enumerate() {
+                    let bullet = if *ordered {
+                        format!("{}.", index + 1)
+                    } else {
+                        "•".to_string()
+                    };
+
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(bullet);
+                        ui.label(item);
+                    });
+                }
+            }
+            Block::Quote(text) => {
+                egui::Frame::none()
+                    .outer_margin(egui::style::Margin {
+                        left: 20f32,
+                        ..Default::default()
+                    })
+                    .show(ui, |ui| {
+                        ui.label(RichText::new(text).italics());
+                    });
+            }
+            Block::Image { url, alt } => match image(url) {
+                Some(image) => {
+                    image
+                        .show_max_size(ui, Vec2::new(ui.available_width(), f32::INFINITY))
+                        .on_hover_text(alt);
+                }
+                None if !alt.is_empty() => {
+                    ui.label(RichText::new(format!("[{}]", alt)).weak());
+                }
+                None => {}
+            },
+        }
+    }
+}
+
+/// Placeholder for story or comment that failed to load.
+pub(crate) fn item_error(item_id: HnItemId, error: &Error, ui: &mut egui::Ui) -> Option<Action> {
+    ui.horizontal_wrapped(|ui| {
+        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+        ui.link("Retry")
+            .on_hover_text(format!("Load item {} again", item_id))
+            .clicked()
+            .then_some(Action::Retry(item_id))
+    })
+    .inner
+}
+
+/// Options of poll in the order they are listed in poll `parts`, options that
+/// are not loaded yet are `None`.
+pub(crate) fn poll_options(options: &[Option<&HnItem>], ui: &mut egui::Ui, render_html: bool) {
+    for (option, share) in options.iter().zip(vote_shares(options)) {
+        let Some(option) = option else {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(RichText::new("Loading option").weak());
+            });
+            continue;
+        };
+
+        if render_html {
+            html_text(&option.text, ui);
+        } else {
+            ui.label(&option.text);
+        }
+
+        ui.add(
+            egui::ProgressBar::new(share)
+                .text(human_format::points(option.score).unwrap_or_else(|| "0 points".to_string())),
+        );
+    }
+}
+
+/// Share of votes of each poll option among the loaded ones.
+fn vote_shares(options: &[Option<&HnItem>]) -> Vec<f32> {
+    let total_score: usize = options.iter().flatten().map(|option| option.score).sum();
+
+    options
+        .iter()
+        .map(|option| match option {
+            Some(option) if total_score > 0 => option.score as f32 / total_score as f32,
+            _ => 0.0,
+        })
+        .collect()
+}
+
+/// Comment with its replies drawn by `draw_child`, `account` is what logged
+/// in user did with the comment, `None` when they are logged out. Reply box
+/// being written is drawn by `draw_reply` above the replies. Link to load
+/// `more_replies` is shown below them.
+pub(crate) fn comment<F, R>(
+    comment: &HnItem,
+    ui: &mut egui::Ui,
+    render_html: bool,
+    account: Option<ItemState>,
+    more_replies: usize,
+    draw_reply: R,
+    draw_child: F,
+) -> egui::InnerResponse<Option<Action>>
 where
-    F: Fn(HnItemId, &mut egui::Ui),
+    F: Fn(HnItemId, &mut egui::Ui) -> Option<Action>,
+    R: FnOnce(&mut egui::Ui) -> Option<Action>,
 {
-    let mut text_layout = LayoutJob::default();
-    if comment.by.len() > 0 {
-        text_layout.append(
-            &comment.by,
-            0.0,
-            TextFormat::simple(FontId::proportional(16.0), ui.visuals().strong_text_color()),
-        );
-        text_layout.append(
-            "  •  ",
-            0.0,
-            TextFormat::simple(FontId::proportional(16.0), ui.visuals().weak_text_color()),
-        );
+    let mut action = None;
+
+    let id = ui.make_persistent_id(comment.id);
+    let mut state = CollapsingState::load_with_default_open(ui.ctx(), id, true);
+    let open = state.is_open();
+
+    let header_response = ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 0.0; // the toggle button uses the full indent width
+        state.show_toggle_button(ui, collapsing_header::paint_default_icon);
+        ui.spacing_mut().item_spacing.x = 5.0;
+
+        if !comment.by.is_empty() {
+            if user_link(ui, &comment.by) {
+                action = Some(Action::OpenUser(comment.by.clone()));
+            }
+            ui.label(RichText::new("•").weak());
+        }
+
+        // clicking the rest of the header toggles the comment like a collapsing header would
+        let date_time = RichText::new(human_format::date_time(&comment.time)).weak();
+        if ui
+            .add(egui::Label::new(date_time).sense(egui::Sense::click()))
+            .clicked()
+        {
+            state.toggle(ui);
+        }
+
+        if let Some(item_state) = account.filter(|_| !comment.deleted) {
+            ui.label(RichText::new("•").weak());
+            if let Some(item_action) = vote_link(ui, item_state) {
+                action = Some(Action::Account(comment.id, item_action));
+            }
+            ui.label(RichText::new("•").weak());
+            if let Some(item_action) = favorite_link(ui, item_state) {
+                action = Some(Action::Account(comment.id, item_action));
+            }
+            ui.label(RichText::new("•").weak());
+            if ui.link("reply").clicked() {
+                action = Some(Action::Reply(comment.id));
+            }
+        }
+    });
+
+    if state.is_open() != open {
+        action = Some(Action::Collapse(comment.id, open));
     }
-    text_layout.append(
-        &human_format::date_time(&comment.time),
-        0.0,
-        TextFormat::simple(FontId::proportional(16.0), ui.visuals().weak_text_color()),
-    );
 
-    CollapsingHeader::new(text_layout)
-        .id_source(comment.id)
-        .default_open(true)
-        .show(ui, |ui| {
-            if comment.deleted {
-                ui.label("[deleted]");
-            } else {
-                if render_html {
-                    html_text(&comment.text, ui);
-                } else {
-                    ui.label(&comment.text);
+    state.show_body_indented(&header_response.response, ui, |ui| {
+        if comment.deleted {
+            ui.label("[deleted]");
+        } else if render_html {
+            html_text(&comment.text, ui);
+        } else {
+            ui.label(&comment.text);
+        }
+
+        if let Some(reply_action) = draw_reply(ui) {
+            action = Some(reply_action);
+        }
+
+        egui::Frame::none()
+            .outer_margin(egui::style::Margin {
+                left: 20f32,
+                ..Default::default()
+            })
+            .show(ui, |ui| {
+                for child in &comment.kids {
+                    if let Some(child_action) = draw_child(*child, ui) {
+                        action = Some(child_action);
+                    }
                 }
+
+                let more = match more_replies {
+                    0 => None,
+                    1 => Some("1 more reply".to_string()),
+                    count => Some(format!("{} more replies", count)),
+                };
+                if let Some(more) = more {
+                    if ui.link(more).clicked() {
+                        action = Some(Action::LoadReplies(comment.id));
+                    }
+                }
+            });
+    });
+
+    egui::InnerResponse::new(action, header_response.response)
+}
+
+/// Box for writing reply to story or comment with preview of how the site
+/// formats it, `error` is why the last attempt to post it failed. The
+/// response is of the text field so edits of the draft can be saved.
+pub(crate) fn reply_box(
+    parent: HnItemId,
+    draft: &mut String,
+    posting: bool,
+    error: Option<&Error>,
+    ui: &mut egui::Ui,
+) -> egui::InnerResponse<Option<Action>> {
+    let response = ui.add(
+        egui::TextEdit::multiline(draft)
+            .id(ui.make_persistent_id(("reply", parent)))
+            .hint_text("Blank line starts paragraph, *text* is italic, text indented by two spaces is code")
+            .desired_rows(4)
+            .desired_width(f32::INFINITY),
+    );
+
+    if !draft.trim().is_empty() {
+        ui.label(RichText::new("Preview").weak());
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            html_text(&comment_parser::format(draft), ui);
+        });
+    }
+
+    let action = ui
+        .horizontal(|ui| {
+            let mut action = None;
+
+            if ui
+                .add_enabled(
+                    !posting && !draft.trim().is_empty(),
+                    egui::Button::new("Post"),
+                )
+                .clicked()
+            {
+                action = Some(Action::PostReply(parent));
+            }
+
+            if posting {
+                ui.spinner();
+            } else if ui.link("Discard").clicked() {
+                action = Some(Action::DiscardReply(parent));
+            }
+
+            if let Some(error) = error {
+                ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+            }
+
+            action
+        })
+        .inner;
+
+    egui::InnerResponse::new(action, response)
+}
+
+/// First day of month `months` after the month of date, negative go back.
+fn add_months(date: Date, months: i32) -> Date {
+    let index = date.year() * 12 + date.month() as i32 - 1 + months;
+    let month = time::Month::try_from((index.rem_euclid(12) + 1) as u8).unwrap();
+    Date::from_calendar_date(index.div_euclid(12), month, 1).unwrap_or(date)
+}
+
+/// Month calendar for picking day between `first` and `last`, the month it
+/// shows is remembered by `id`. Returns the picked day.
+pub(crate) fn calendar(
+    id: egui::Id,
+    selected: Option<Date>,
+    first: Date,
+    last: Date,
+    ui: &mut egui::Ui,
+) -> Option<Date> {
+    let mut month = ui
+        .data_mut(|data| data.get_temp::<Date>(id))
+        .unwrap_or_else(|| add_months(selected.unwrap_or(last), 0));
+    let mut picked = None;
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(month > first, egui::Button::new("◀"))
+            .on_hover_text("Previous month")
+            .clicked()
+        {
+            month = add_months(month, -1);
+        }
+
+        ui.label(RichText::new(format!("{} {}", month.month(), month.year())).strong());
+
+        if ui
+            .add_enabled(add_months(month, 1) <= last, egui::Button::new("▶"))
+            .on_hover_text("Next month")
+            .clicked()
+        {
+            month = add_months(month, 1);
+        }
+    });
+
+    egui::Grid::new(id.with("days")).show(ui, |ui| {
+        for weekday in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+            ui.label(RichText::new(weekday).weak());
+        }
+        ui.end_row();
+
+        for _ in 0..month.weekday().number_days_from_monday() {
+            ui.label("");
+        }
+
+        let mut day = month;
+        loop {
+            let label = egui::SelectableLabel::new(selected == Some(day), day.day().to_string());
+            if ui.add_enabled(first <= day && day <= last, label).clicked() {
+                picked = Some(day);
             }
 
-            egui::Frame::none()
-                .outer_margin(egui::style::Margin {
-                    left: 20f32,
-                    ..Default::default()
-                })
-                .show(ui, |ui| {
-                    for child in &comment.kids {
-                        draw_child(*child, ui);
+            if day.weekday() == Weekday::Sunday {
+                ui.end_row();
+            }
+
+            match day.next_day() {
+                Some(next) if next.month() == month.month() => day = next,
+                _ => break,
+            }
+        }
+    });
+
+    ui.data_mut(|data| data.insert_temp(id, month));
+    picked
+}
+
+/// Heading of front page of past day with links to the days around it,
+/// returns the day to switch to.
+pub(crate) fn front_page_day(
+    day: Date,
+    first: Date,
+    last: Date,
+    ui: &mut egui::Ui,
+) -> Option<Date> {
+    let previous = day.previous_day().filter(|previous| *previous >= first);
+    let next = day.next_day().filter(|next| *next <= last);
+    let mut switch = None;
+
+    ui.horizontal(|ui| {
+        if ui
+            .add_enabled(previous.is_some(), egui::Button::new("◀"))
+            .on_hover_text("Previous day")
+            .clicked()
+        {
+            switch = previous;
+        }
+
+        ui.label(RichText::new(format!("Front page of {}", human_format::date(day))).strong());
+
+        if ui
+            .add_enabled(next.is_some(), egui::Button::new("▶"))
+            .on_hover_text("Next day")
+            .clicked()
+        {
+            switch = next;
+        }
+    });
+
+    switch
+}
+
+/// Editable list of domains, `input` holds the domain being added. Returns
+/// whether the list changed.
+pub(crate) fn domain_list(
+    id: &str,
+    domains: &mut Vec<String>,
+    input: &mut String,
+    ui: &mut egui::Ui,
+) -> bool {
+    let mut changed = false;
+
+    ui.push_id(id, |ui| {
+        domains.retain(|domain| {
+            ui.horizontal(|ui| {
+                let removed = ui.small_button("✖").on_hover_text("Remove").clicked();
+                ui.label(domain);
+                changed |= removed;
+                !removed
+            })
+            .inner
+        });
+
+        ui.horizontal(|ui| {
+            let response = ui.add(egui::TextEdit::singleline(input).hint_text("example.com"));
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            if ui.button("Add").clicked() || submitted {
+                if let Some(domain) = privacy::domain(input) {
+                    if !domains.contains(&domain) {
+                        domains.push(domain);
+                        changed = true;
                     }
-                });
+                }
+                input.clear();
+            }
+        });
+    });
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Month;
+
+    use super::*;
+
+    #[test]
+    fn adds_months_across_years() {
+        let date = |year, month, day| Date::from_calendar_date(year, month, day).unwrap();
+        let may = date(2023, Month::May, 17);
+
+        assert_eq!(add_months(may, 0), date(2023, Month::May, 1));
+        assert_eq!(add_months(may, 8), date(2024, Month::January, 1));
+        assert_eq!(add_months(may, 31), date(2025, Month::December, 1));
+        assert_eq!(add_months(may, -4), date(2023, Month::January, 1));
+        assert_eq!(add_months(may, -5), date(2022, Month::December, 1));
+        assert_eq!(add_months(may, -29), date(2020, Month::December, 1));
+        assert_eq!(
+            add_months(date(2024, Month::March, 31), -1),
+            date(2024, Month::February, 1)
+        );
+    }
+
+    #[test]
+    fn calendar_shows_month_of_selected_day() {
+        let date = |year, month, day| Date::from_calendar_date(year, month, day).unwrap();
+        let id = egui::Id::new("calendar");
+        let ctx = egui::Context::default();
+
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                let picked = calendar(
+                    id,
+                    Some(date(2022, Month::December, 31)),
+                    date(2007, Month::February, 19),
+                    date(2023, Month::February, 16),
+                    ui,
+                );
+                assert_eq!(picked, None);
+            });
         });
+
+        let month = ctx.data_mut(|data| data.get_temp::<Date>(id));
+        assert_eq!(month, Some(date(2022, Month::December, 1)));
+    }
+
+    #[test]
+    fn splits_votes_among_loaded_options() {
+        let option = |score| HnItem {
+            r#type: "pollopt".to_string(),
+            score,
+            ..Default::default()
+        };
+        let (yes, no) = (option(30), option(10));
+
+        assert_eq!(
+            vote_shares(&[Some(&yes), None, Some(&no)]),
+            [0.75, 0.0, 0.25]
+        );
+        assert_eq!(vote_shares(&[Some(&option(0)), None]), [0.0, 0.0]);
+    }
 }